@@ -0,0 +1,264 @@
+use failure::Fail;
+use std::path::PathBuf;
+use std::{fmt, mem};
+
+// Where an error was found, with the line of source it is on. The line and column start at 1, and
+// are 0 for errors about a whole file, such as a file that cannot be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssembleError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub source_line: String,
+    pub help: Option<String>,
+    // The macro calls and `rept` blocks that the line was expanded from, innermost first.
+    pub expansions: Vec<Expansion>,
+}
+
+// Where a line that has an error was expanded from. The error itself is at the line in the body of
+// the macro or block, after the parameters have been replaced.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub source_line: String,
+}
+
+impl AssembleError {
+    pub(crate) fn with_help(mut self, help: Option<String>) -> AssembleError {
+        self.help = help;
+        self
+    }
+}
+
+// The source line is shown below the location, with a caret under the column. Tabs are kept in
+// front of the caret, so that it lines up however wide the terminal shows them.
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path.display(), self.message)?;
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path.display(),
+                self.line,
+                self.column,
+                self.message
+            )?;
+            snippet(f, &self.source_line, self.column)?;
+        }
+
+        for expansion in &self.expansions {
+            write!(
+                f,
+                "\nnote: in the expansion of {} at {}:{}:{}",
+                expansion.name,
+                expansion.path.display(),
+                expansion.line,
+                expansion.column
+            )?;
+            snippet(f, &expansion.source_line, expansion.column)?;
+        }
+        if let Some(ref help) = self.help {
+            write!(f, "\nhelp: {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+fn snippet(f: &mut fmt::Formatter, source_line: &str, column: usize) -> fmt::Result {
+    let indent: String = source_line
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    write!(f, "\n{}\n{}^", source_line, indent)
+}
+
+impl Fail for AssembleError {}
+
+// Every error found in a run of the assembler, in the order they were found.
+#[derive(Debug, PartialEq)]
+pub struct AssembleErrors(pub Vec<AssembleError>);
+
+impl fmt::Display for AssembleErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, error) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, "\n\n")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for AssembleErrors {}
+
+// An error on a line that has not been located in its file yet.
+#[derive(Debug, PartialEq)]
+pub(crate) struct LineError {
+    pub(crate) column: usize,
+    pub(crate) message: String,
+}
+
+impl LineError {
+    pub(crate) fn new<S: Into<String>>(column: usize, message: S) -> LineError {
+        LineError {
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+// Suggests up to three of the candidates that are closest to a misspelled name, as a help
+// message. Returns None if none of them are close.
+pub(crate) fn suggest<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut closest: Vec<_> = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    closest.sort();
+    closest.dedup();
+    if let Some(&(distance, _)) = closest.first() {
+        closest.retain(|&(other, _)| other == distance);
+    }
+
+    let names: Vec<_> = closest
+        .iter()
+        .take(3)
+        .map(|&(_, candidate)| format!("`{}`", candidate))
+        .collect();
+    match names.split_last() {
+        None => None,
+        Some((last, [])) => Some(format!("did you mean {}?", last)),
+        Some((last, rest)) => Some(format!("did you mean {} or {}?", rest.join(", "), last)),
+    }
+}
+
+// The number of insertions, deletions, substitutions and swaps of adjacent characters that turn
+// one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Each row holds the distances from a prefix of `a` to every prefix of `b`.
+    let mut previous: Vec<usize> = Vec::new();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let before = mem::replace(&mut previous, row.clone());
+        row[0] = i;
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let mut distance = substitution.min(previous[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(before[j - 2] + 1);
+            }
+            row[j] = distance;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(column: usize, help: Option<&str>) -> AssembleError {
+        AssembleError {
+            path: PathBuf::from("main.s"),
+            line: 3,
+            column,
+            message: "unknown instruction `ad`".to_string(),
+            source_line: "\tad r1, r2".to_string(),
+            help: help.map(|help| help.to_string()),
+            expansions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn display_snippet() {
+        assert_eq!(
+            error(2, Some("did you mean `add`?")).to_string(),
+            "main.s:3:2: unknown instruction `ad`\n\tad r1, r2\n\t^\nhelp: did you mean `add`?"
+        );
+
+        let errors = AssembleErrors(vec![error(2, None), error(5, None)]);
+        assert_eq!(
+            errors.to_string(),
+            "main.s:3:2: unknown instruction `ad`\n\tad r1, r2\n\t^\n\n\
+             main.s:3:5: unknown instruction `ad`\n\tad r1, r2\n\t   ^"
+        );
+    }
+
+    #[test]
+    fn display_file_error() {
+        let error = AssembleError {
+            line: 0,
+            column: 0,
+            ..error(0, None)
+        };
+        assert_eq!(error.to_string(), "main.s: unknown instruction `ad`");
+    }
+
+    #[test]
+    fn display_expansions() {
+        let expansion = |path: &str, line, source_line: &str| Expansion {
+            name: "macro `draw`".to_string(),
+            path: PathBuf::from(path),
+            line,
+            column: 5,
+            source_line: source_line.to_string(),
+        };
+        let error = AssembleError {
+            expansions: vec![
+                expansion("sprites.s", 8, "    draw r2"),
+                expansion("main.s", 20, "    draw r1"),
+            ],
+            ..error(2, None)
+        };
+        assert_eq!(
+            error.to_string(),
+            "main.s:3:2: unknown instruction `ad`\n\tad r1, r2\n\t^\n\
+             note: in the expansion of macro `draw` at sprites.s:8:5\n    draw r2\n    ^\n\
+             note: in the expansion of macro `draw` at main.s:20:5\n    draw r1\n    ^"
+        );
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("add", "add"), 0);
+        assert_eq!(edit_distance("ad", "add"), 1);
+        assert_eq!(edit_distance("mvo", "mov"), 1);
+        assert_eq!(edit_distance("pushal", "pushall"), 1);
+        assert_eq!(edit_distance("", "ret"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggestions() {
+        let candidates = ["add", "and", "addi", "sub", "mov", "mul"];
+        assert_eq!(
+            suggest("ad", candidates.iter().cloned()),
+            Some("did you mean `add` or `and`?".to_string())
+        );
+        assert_eq!(
+            suggest("mvo", candidates.iter().cloned()),
+            Some("did you mean `mov`?".to_string())
+        );
+        assert_eq!(
+            suggest("adi", candidates.iter().cloned()),
+            Some("did you mean `add` or `addi`?".to_string())
+        );
+        assert_eq!(suggest("xyz", candidates.iter().cloned()), None);
+    }
+}
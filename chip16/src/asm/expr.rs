@@ -0,0 +1,288 @@
+use super::diagnostic::LineError;
+use super::lexer::{Lexer, Token};
+
+// Expressions are parsed and evaluated recursively, so they are nested no deeper than this. Each
+// parenthesis, unary operator and binary operator is a level.
+const MAX_EXPRESSION_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum UnaryOperator {
+    Plus,
+    Negate,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BinaryOperator {
+    Or,
+    Xor,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOperator {
+    fn from_token(token: &Token) -> Option<BinaryOperator> {
+        match *token {
+            Token::Pipe => Some(BinaryOperator::Or),
+            Token::Caret => Some(BinaryOperator::Xor),
+            Token::Ampersand => Some(BinaryOperator::And),
+            Token::Equal => Some(BinaryOperator::Equal),
+            Token::NotEqual => Some(BinaryOperator::NotEqual),
+            Token::Less => Some(BinaryOperator::Less),
+            Token::LessEqual => Some(BinaryOperator::LessEqual),
+            Token::Greater => Some(BinaryOperator::Greater),
+            Token::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+            Token::ShiftLeft => Some(BinaryOperator::ShiftLeft),
+            Token::ShiftRight => Some(BinaryOperator::ShiftRight),
+            Token::Plus => Some(BinaryOperator::Add),
+            Token::Minus => Some(BinaryOperator::Subtract),
+            Token::Star => Some(BinaryOperator::Multiply),
+            Token::Slash => Some(BinaryOperator::Divide),
+            Token::Percent => Some(BinaryOperator::Remainder),
+            _ => None,
+        }
+    }
+
+    // The same precedence as in C, where a higher precedence binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 0,
+            BinaryOperator::Xor => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 3,
+            BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual => 4,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 5,
+            BinaryOperator::Add | BinaryOperator::Subtract => 6,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 7,
+        }
+    }
+
+    // Returns None if the right operand is out of range for the operator. Comparisons are 1 when
+    // they hold and 0 when they do not.
+    pub(crate) fn apply(self, left: i64, right: i64) -> Option<i64> {
+        match self {
+            BinaryOperator::Or => Some(left | right),
+            BinaryOperator::Xor => Some(left ^ right),
+            BinaryOperator::And => Some(left & right),
+            BinaryOperator::Equal => Some((left == right) as i64),
+            BinaryOperator::NotEqual => Some((left != right) as i64),
+            BinaryOperator::Less => Some((left < right) as i64),
+            BinaryOperator::LessEqual => Some((left <= right) as i64),
+            BinaryOperator::Greater => Some((left > right) as i64),
+            BinaryOperator::GreaterEqual => Some((left >= right) as i64),
+            BinaryOperator::ShiftLeft if (0..64).contains(&right) => Some(left << right),
+            BinaryOperator::ShiftRight if (0..64).contains(&right) => Some(left >> right),
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => None,
+            BinaryOperator::Add => Some(left.wrapping_add(right)),
+            BinaryOperator::Subtract => Some(left.wrapping_sub(right)),
+            BinaryOperator::Multiply => Some(left.wrapping_mul(right)),
+            BinaryOperator::Divide => left.checked_div(right),
+            BinaryOperator::Remainder => left.checked_rem(right),
+        }
+    }
+}
+
+// A numeric expression. Symbols and operators keep their column, so errors found while
+// evaluating can point at them.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expr {
+    Number(i64),
+    Symbol {
+        name: String,
+        column: usize,
+    },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        column: usize,
+    },
+}
+
+pub(crate) fn parse(lexer: &mut Lexer) -> Result<Expr, LineError> {
+    parse_binary(lexer, 0, 0)
+}
+
+fn parse_binary(lexer: &mut Lexer, min_precedence: u8, depth: usize) -> Result<Expr, LineError> {
+    let mut left = parse_unary(lexer, depth)?;
+    // Operators of the same precedence nest to the left, so each one makes the tree deeper.
+    let mut depth = depth;
+    loop {
+        let operator = match lexer.peek()?.as_ref().and_then(BinaryOperator::from_token) {
+            Some(operator) if operator.precedence() >= min_precedence => operator,
+            _ => return Ok(left),
+        };
+        let column = lexer.column();
+        lexer.next()?;
+
+        depth += 1;
+        let right = parse_binary(lexer, operator.precedence() + 1, depth)?;
+        left = Expr::Binary {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+            column,
+        };
+    }
+}
+
+fn parse_unary(lexer: &mut Lexer, depth: usize) -> Result<Expr, LineError> {
+    let column = lexer.column();
+    if depth > MAX_EXPRESSION_DEPTH {
+        return Err(LineError::new(
+            column,
+            "the expression is nested too deeply",
+        ));
+    }
+    let operator = match lexer.next()? {
+        Some(Token::Plus) => UnaryOperator::Plus,
+        Some(Token::Minus) => UnaryOperator::Negate,
+        Some(Token::Tilde) => UnaryOperator::Not,
+        Some(Token::Number(value)) => return Ok(Expr::Number(value)),
+        Some(Token::Identifier(name)) => return Ok(Expr::Symbol { name, column }),
+        Some(Token::LeftParen) => {
+            let expr = parse_binary(lexer, 0, depth + 1)?;
+            let column = lexer.column();
+            return match lexer.next()? {
+                Some(Token::RightParen) => Ok(expr),
+                _ => Err(LineError::new(column, "expected `)`")),
+            };
+        }
+        _ => return Err(LineError::new(column, "expected an expression")),
+    };
+
+    let operand = parse_unary(lexer, depth + 1)?;
+    Ok(Expr::Unary {
+        operator,
+        operand: Box::new(operand),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expr: &Expr) -> i64 {
+        match *expr {
+            Expr::Number(value) => value,
+            Expr::Symbol { .. } => 100,
+            Expr::Unary {
+                operator,
+                ref operand,
+            } => match operator {
+                UnaryOperator::Plus => evaluate(operand),
+                UnaryOperator::Negate => -evaluate(operand),
+                UnaryOperator::Not => !evaluate(operand),
+            },
+            Expr::Binary {
+                operator,
+                ref left,
+                ref right,
+                ..
+            } => operator.apply(evaluate(left), evaluate(right)).unwrap(),
+        }
+    }
+
+    fn value(source: &str) -> i64 {
+        let mut lexer = Lexer::new(source);
+        let expr = parse(&mut lexer).unwrap();
+        assert!(lexer.at_end());
+        evaluate(&expr)
+    }
+
+    #[test]
+    fn parse_precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7);
+        assert_eq!(value("(1 + 2) * 3"), 9);
+        assert_eq!(value("10 - 4 - 3"), 3);
+        assert_eq!(value("1 << 4 + 1"), 32);
+        assert_eq!(value("0xF0 | 0x0F & 0x3C"), 0xFC);
+        assert_eq!(value("6 ^ 3 & 1"), 7);
+        assert_eq!(value("-2 * -3"), 6);
+        assert_eq!(value("~0 & 0xFF"), 0xFF);
+        assert_eq!(value("17 % 5 / 2"), 1);
+        assert_eq!(value("label + 2"), 102);
+        assert_eq!(value("1 + 1 == 2"), 1);
+        assert_eq!(value("1 << 2 > 3 == 1 <= 0"), 0);
+        assert_eq!(value("2 != 3 & 4 >= 4"), 1);
+    }
+
+    #[test]
+    fn parse_symbol_columns() {
+        let expr = parse(&mut Lexer::new("1 + label")).unwrap();
+        match expr {
+            Expr::Binary { right, column, .. } => {
+                assert_eq!(column, 3);
+                assert_eq!(
+                    *right,
+                    Expr::Symbol {
+                        name: "label".to_string(),
+                        column: 5
+                    }
+                );
+            }
+            _ => panic!("expected a binary expression"),
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |source| parse(&mut Lexer::new(source)).unwrap_err();
+        assert_eq!(error("(1 + 2").message, "expected `)`");
+        assert_eq!(error("(1 + 2").column, 7);
+        assert_eq!(error("1 +").message, "expected an expression");
+        assert_eq!(error("\"text\"").column, 1);
+    }
+
+    #[test]
+    fn limit_nesting() {
+        let nested = |open: &str, close: &str, count| {
+            format!("{}1{}", open.repeat(count), close.repeat(count))
+        };
+        assert_eq!(value(&nested("(", ")", MAX_EXPRESSION_DEPTH)), 1);
+        assert_eq!(value(&nested("-", "", MAX_EXPRESSION_DEPTH)), 1);
+        assert_eq!(value(&nested("", " + 1", MAX_EXPRESSION_DEPTH)), 257);
+
+        let error = |source: String| parse(&mut Lexer::new(&source)).unwrap_err();
+        for &count in &[MAX_EXPRESSION_DEPTH + 1, 100_000] {
+            let parens = error(nested("(", ")", count));
+            assert_eq!(parens.message, "the expression is nested too deeply");
+            assert_eq!(parens.column, MAX_EXPRESSION_DEPTH + 2);
+            assert_eq!(
+                error(nested("-", "", count)).message,
+                "the expression is nested too deeply"
+            );
+            assert_eq!(
+                error(nested("", "+1", count)).message,
+                "the expression is nested too deeply"
+            );
+        }
+    }
+
+    #[test]
+    fn apply_checks_operands() {
+        assert_eq!(BinaryOperator::Divide.apply(1, 0), None);
+        assert_eq!(BinaryOperator::Remainder.apply(1, 0), None);
+        assert_eq!(BinaryOperator::ShiftLeft.apply(1, 64), None);
+        assert_eq!(BinaryOperator::ShiftRight.apply(1, -1), None);
+    }
+}
@@ -0,0 +1,493 @@
+use super::diagnostic::LineError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token {
+    Identifier(String),
+    Number(i64),
+    String(Vec<u8>),
+    Comma,
+    Colon,
+    LeftParen,
+    RightParen,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+// Splits a single line of source into tokens. Everything after a `;` is a comment. Columns start
+// at 1 and count characters, so they can be shown under the line in an error.
+#[derive(Clone)]
+pub(crate) struct Lexer<'a> {
+    line: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub(crate) fn new(line: &'a str) -> Lexer<'a> {
+        Lexer { line, position: 0 }
+    }
+
+    // The column of the next token, or of the end of the line.
+    pub(crate) fn column(&self) -> usize {
+        let mut lexer = self.clone();
+        lexer.skip_whitespace();
+        lexer.column_at(lexer.position)
+    }
+
+    pub(crate) fn at_end(&self) -> bool {
+        let mut lexer = self.clone();
+        lexer.skip_whitespace();
+        lexer.rest().is_empty() || lexer.rest().starts_with(';')
+    }
+
+    pub(crate) fn peek(&self) -> Result<Option<Token>, LineError> {
+        self.clone().next()
+    }
+
+    pub(crate) fn next(&mut self) -> Result<Option<Token>, LineError> {
+        self.skip_whitespace();
+        let column = self.column_at(self.position);
+        let c = match self.rest().chars().next() {
+            None | Some(';') => return Ok(None),
+            Some(c) => c,
+        };
+
+        let token = match c {
+            ',' => self.symbol(Token::Comma, 1),
+            ':' => self.symbol(Token::Colon, 1),
+            '(' => self.symbol(Token::LeftParen, 1),
+            ')' => self.symbol(Token::RightParen, 1),
+            '+' => self.symbol(Token::Plus, 1),
+            '-' => self.symbol(Token::Minus, 1),
+            '*' => self.symbol(Token::Star, 1),
+            '/' => self.symbol(Token::Slash, 1),
+            '%' => self.symbol(Token::Percent, 1),
+            '&' => self.symbol(Token::Ampersand, 1),
+            '|' => self.symbol(Token::Pipe, 1),
+            '^' => self.symbol(Token::Caret, 1),
+            '~' => self.symbol(Token::Tilde, 1),
+            '<' if self.rest().starts_with("<<") => self.symbol(Token::ShiftLeft, 2),
+            '<' if self.rest().starts_with("<=") => self.symbol(Token::LessEqual, 2),
+            '<' => self.symbol(Token::Less, 1),
+            '>' if self.rest().starts_with(">>") => self.symbol(Token::ShiftRight, 2),
+            '>' if self.rest().starts_with(">=") => self.symbol(Token::GreaterEqual, 2),
+            '>' => self.symbol(Token::Greater, 1),
+            '=' if self.rest().starts_with("==") => self.symbol(Token::Equal, 2),
+            '!' if self.rest().starts_with("!=") => self.symbol(Token::NotEqual, 2),
+            '"' => Token::String(self.string(column)?),
+            '\'' => Token::Number(self.character(column)?),
+            '#' | '$' => {
+                self.position += 1;
+                Token::Number(self.digits(16, column)?)
+            }
+            '0'..='9' => Token::Number(self.number(column)?),
+            c if is_identifier_start(c) => Token::Identifier(self.identifier()),
+            c => {
+                return Err(LineError::new(
+                    column,
+                    format!("unexpected character `{}`", c),
+                ))
+            }
+        };
+        Ok(Some(token))
+    }
+
+    // Reads a file name, which is either quoted or runs until whitespace, a comma or a comment.
+    pub(crate) fn path(&mut self) -> Result<Option<String>, LineError> {
+        self.skip_whitespace();
+        let column = self.column_at(self.position);
+        if self.rest().starts_with('"') {
+            let bytes = self.string(column)?;
+            return String::from_utf8(bytes)
+                .map(Some)
+                .map_err(|_| LineError::new(column, "the file name is not valid UTF-8"));
+        }
+
+        let length = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .unwrap_or_else(|| self.rest().len());
+        let path = &self.rest()[..length];
+        self.position += length;
+        Ok(if path.is_empty() {
+            None
+        } else {
+            Some(path.to_string())
+        })
+    }
+
+    // Splits the rest of the line into the text of each argument of a macro, at the commas that
+    // are not inside parentheses, strings or characters.
+    pub(crate) fn arguments(&mut self) -> Result<Vec<(String, usize)>, LineError> {
+        let mut arguments = Vec::new();
+        if self.at_end() {
+            return Ok(arguments);
+        }
+        loop {
+            self.skip_whitespace();
+            let start = self.position;
+            let column = self.column_at(start);
+            let mut depth = 0usize;
+            while let Some(c) = self.rest().chars().next() {
+                match c {
+                    ';' => break,
+                    ',' if depth == 0 => break,
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    '"' => {
+                        self.string(self.column_at(self.position))?;
+                        continue;
+                    }
+                    '\'' => {
+                        self.character(self.column_at(self.position))?;
+                        continue;
+                    }
+                    _ => {}
+                }
+                self.position += c.len_utf8();
+            }
+
+            let argument = self.line[start..self.position].trim_end();
+            if argument.is_empty() {
+                return Err(LineError::new(column, "expected an argument"));
+            }
+            arguments.push((argument.to_string(), column));
+            if !self.rest().starts_with(',') {
+                return Ok(arguments);
+            }
+            self.position += 1;
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.line[self.position..]
+    }
+
+    fn column_at(&self, position: usize) -> usize {
+        self.line[..position].chars().count() + 1
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn symbol(&mut self, token: Token, length: usize) -> Token {
+        self.position += length;
+        token
+    }
+
+    fn identifier(&mut self) -> String {
+        let length = self
+            .rest()
+            .find(|c| !is_identifier_continue(c))
+            .unwrap_or_else(|| self.rest().len());
+        let identifier = self.rest()[..length].to_string();
+        self.position += length;
+        identifier
+    }
+
+    // Decimal, or hexadecimal and binary with a `0x` or `0b` prefix.
+    fn number(&mut self, column: usize) -> Result<i64, LineError> {
+        let rest = self.rest();
+        if rest.starts_with("0x") || rest.starts_with("0X") {
+            self.position += 2;
+            self.digits(16, column)
+        } else if rest.starts_with("0b") || rest.starts_with("0B") {
+            self.position += 2;
+            self.digits(2, column)
+        } else {
+            self.digits(10, column)
+        }
+    }
+
+    fn digits(&mut self, radix: u32, column: usize) -> Result<i64, LineError> {
+        let length = self
+            .rest()
+            .find(|c: char| !is_identifier_continue(c))
+            .unwrap_or_else(|| self.rest().len());
+        let digits = &self.rest()[..length];
+        self.position += length;
+
+        i64::from_str_radix(digits, radix)
+            .ok()
+            .filter(|&value| value <= 0xFFFF_FFFF)
+            .ok_or_else(|| LineError::new(column, "invalid number"))
+    }
+
+    fn string(&mut self, column: usize) -> Result<Vec<u8>, LineError> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.escaped_char(column)? {
+                (b'"', false) => return Ok(bytes),
+                (byte, _) => bytes.push(byte),
+            }
+        }
+    }
+
+    fn character(&mut self, column: usize) -> Result<i64, LineError> {
+        self.position += 1;
+        let (byte, _) = self.escaped_char(column)?;
+        if !self.rest().starts_with('\'') {
+            return Err(LineError::new(column, "unterminated character"));
+        }
+        self.position += 1;
+        Ok(byte as i64)
+    }
+
+    // Returns the next byte of a string or character, and whether it was escaped.
+    fn escaped_char(&mut self, column: usize) -> Result<(u8, bool), LineError> {
+        let mut chars = self.rest().chars();
+        let c = match chars.next() {
+            Some(c) => c,
+            None => return Err(LineError::new(column, "unterminated string")),
+        };
+        if !c.is_ascii() {
+            return Err(LineError::new(
+                self.column_at(self.position),
+                "only ASCII characters are allowed",
+            ));
+        }
+        if c != '\\' {
+            self.position += 1;
+            return Ok((c as u8, false));
+        }
+
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('0') => b'\0',
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('\'') => b'\'',
+            _ => {
+                return Err(LineError::new(
+                    self.column_at(self.position),
+                    "unknown escape sequence",
+                ))
+            }
+        };
+        self.position += 2;
+        Ok((byte, true))
+    }
+}
+
+// Replaces the identifiers that `replacement` returns a replacement for, except in strings,
+// characters and comments.
+pub(crate) fn substitute<F>(line: &str, replacement: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(line.len());
+    let mut lexer = Lexer::new(line);
+    while let Some(c) = lexer.rest().chars().next() {
+        let start = lexer.position;
+        match c {
+            ';' => {
+                result.push_str(lexer.rest());
+                break;
+            }
+            '"' | '\'' => {
+                let valid = if c == '"' {
+                    lexer.string(0).is_ok()
+                } else {
+                    lexer.character(0).is_ok()
+                };
+                // The rest of the line is kept as it is, for the parser to report.
+                if !valid {
+                    result.push_str(&line[start..]);
+                    break;
+                }
+            }
+            c if is_identifier_start(c) => {
+                let identifier = lexer.identifier();
+                if let Some(replacement) = replacement(&identifier) {
+                    result.push_str(&replacement);
+                    continue;
+                }
+            }
+            // Numbers are skipped whole, so that the digits of `0xab` or `#ab` are not names.
+            '0'..='9' | '#' | '$' => {
+                lexer.position += 1;
+                lexer.identifier();
+            }
+            c => lexer.position += c.len_utf8(),
+        }
+        result.push_str(&line[start..lexer.position]);
+    }
+    result
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(line);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next().unwrap() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    #[test]
+    fn tokenize_instruction() {
+        assert_eq!(
+            tokens("loop: addi r1, -0x10 ; comment"),
+            vec![
+                Token::Identifier("loop".to_string()),
+                Token::Colon,
+                Token::Identifier("addi".to_string()),
+                Token::Identifier("r1".to_string()),
+                Token::Comma,
+                Token::Minus,
+                Token::Number(0x10),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_numbers() {
+        assert_eq!(
+            tokens("10 0x1F #FF $ab 0b101 'A' '\\n'"),
+            vec![
+                Token::Number(10),
+                Token::Number(0x1F),
+                Token::Number(0xFF),
+                Token::Number(0xAB),
+                Token::Number(5),
+                Token::Number(65),
+                Token::Number(10),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_operators() {
+        assert_eq!(
+            tokens("(a<<2)>>b|c&~d^e%f"),
+            vec![
+                Token::LeftParen,
+                Token::Identifier("a".to_string()),
+                Token::ShiftLeft,
+                Token::Number(2),
+                Token::RightParen,
+                Token::ShiftRight,
+                Token::Identifier("b".to_string()),
+                Token::Pipe,
+                Token::Identifier("c".to_string()),
+                Token::Ampersand,
+                Token::Tilde,
+                Token::Identifier("d".to_string()),
+                Token::Caret,
+                Token::Identifier("e".to_string()),
+                Token::Percent,
+                Token::Identifier("f".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokens("== != < <= > >="),
+            vec![
+                Token::Equal,
+                Token::NotEqual,
+                Token::Less,
+                Token::LessEqual,
+                Token::Greater,
+                Token::GreaterEqual,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_string() {
+        assert_eq!(
+            tokens(r#"db "a;b\"c\0""#),
+            vec![
+                Token::Identifier("db".to_string()),
+                Token::String(b"a;b\"c\0".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_errors() {
+        let error = |line| Lexer::new(line).next().unwrap_err();
+        assert_eq!(error("  0x").column, 3);
+        assert_eq!(error("12ab").message, "invalid number");
+        assert_eq!(error("\"abc").message, "unterminated string");
+        assert_eq!(error("@").message, "unexpected character `@`");
+    }
+
+    #[test]
+    fn read_path() {
+        let mut lexer = Lexer::new("  sprites/ball.bin, 4 ; comment");
+        assert_eq!(lexer.path().unwrap(), Some("sprites/ball.bin".to_string()));
+        assert_eq!(lexer.next().unwrap(), Some(Token::Comma));
+
+        let mut lexer = Lexer::new(r#""my file.bin""#);
+        assert_eq!(lexer.path().unwrap(), Some("my file.bin".to_string()));
+        assert!(lexer.at_end());
+    }
+
+    #[test]
+    fn split_arguments() {
+        let arguments = |line| Lexer::new(line).arguments();
+        assert_eq!(
+            arguments(" r1, (a, b) + 1, \",\", ',' ; comment").unwrap(),
+            vec![
+                ("r1".to_string(), 2),
+                ("(a, b) + 1".to_string(), 6),
+                ("\",\"".to_string(), 18),
+                ("','".to_string(), 23),
+            ]
+        );
+        assert_eq!(arguments("  ; comment").unwrap(), vec![]);
+        assert_eq!(arguments("r1, , r2").unwrap_err().column, 5);
+        assert_eq!(
+            arguments("\"abc").unwrap_err().message,
+            "unterminated string"
+        );
+    }
+
+    #[test]
+    fn substitute_parameters() {
+        let replacements = |name: &str| match name {
+            "x" => Some("r1".to_string()),
+            "ab" => Some("SIZE + 1".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            substitute("x: add x, ab ; x", replacements),
+            "r1: add r1, SIZE + 1 ; x"
+        );
+        assert_eq!(
+            substitute("db \"x\", 'x', 0xab, #ab, xab", replacements),
+            "db \"x\", 'x', 0xab, #ab, xab"
+        );
+    }
+}
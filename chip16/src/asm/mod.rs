@@ -0,0 +1,1705 @@
+// An assembler for the syntax of tchip16, which is also what `disasm` writes.
+//
+// Each line holds an optional `label:` and an instruction or directive, and `;` starts a comment.
+// Mnemonics, directives and the registers `r0` to `rF` are case insensitive, labels are not.
+// Numbers are decimal, hexadecimal with `0x`, `#` or `$`, binary with `0b`, or characters like
+// 'A', and expressions combine them with labels and constants using the operators of C.
+//
+//     name equ expr                  defines a constant, which can be used before it
+//     db expr|"string", ...          emits bytes
+//     dw expr, ...                   emits little endian words
+//     include file                   assembles another file in place
+//     importbin file[, offset[, length]]  emits the contents of a binary file
+//     macro name [param, ...]        defines a macro from the lines up to `endm`, which is then
+//                                    used like an instruction, with an argument for each param
+//     rept count                     assembles the lines up to `endr` count times
+//     if expr / else / endif         assembles the lines where the condition is not 0
+//
+// Files are found relative to the file that names them. The count of `rept` and the condition of
+// `if` can only use constants and labels that are defined before them.
+//
+// Labels that start with a `.` are local to the label before them, so that every routine can have
+// its own `.loop`. Every expansion of a macro has its own local labels as well.
+//
+// Errors do not stop the assembler, which reports every error it finds in a run. An error skips
+// the rest of its line, but the line still takes up its space, so that later labels keep their
+// addresses. Errors in the lines of a macro are shown with the call that they were expanded from.
+
+// Errors carry their source line and expansions, which makes them large, but they are rare.
+#![allow(clippy::result_large_err)]
+
+mod diagnostic;
+mod expr;
+mod lexer;
+mod parser;
+
+pub use self::diagnostic::{AssembleError, AssembleErrors};
+
+use self::diagnostic::suggest;
+use self::expr::{BinaryOperator, Expr, UnaryOperator};
+use self::parser::{Data, Located, Operand, Statement};
+use instruction::{Condition, Format, Instruction, Operands, Operation};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fs, mem};
+use symbols::Symbols;
+
+// This also stops files that include themselves.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// This also stops macros that use themselves.
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+// Nested `rept` blocks multiply, so the lines that all expansions assemble are limited as well. A
+// full program needs at most one line for each byte, and this leaves room for the rest.
+const MAX_EXPANDED_LINES: usize = 0x4_0000;
+
+// Programs are loaded at 0x0000, and can fill the whole address space.
+const MAX_PROGRAM_SIZE: u32 = 0x1_0000;
+
+#[derive(Debug, PartialEq)]
+pub struct Assembly {
+    pub content: Vec<u8>,
+    // Every label, and the line that each instruction and directive was assembled from.
+    pub symbols: Symbols,
+}
+
+#[derive(Default)]
+pub struct Assembler {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::default()
+    }
+
+    // Provides the contents of a file, which are used instead of reading it from disk.
+    pub fn add_file<P: Into<PathBuf>>(&mut self, path: P, contents: Vec<u8>) {
+        self.files.insert(path.into(), contents);
+    }
+
+    pub fn assemble_file<P: AsRef<Path>>(&self, path: P) -> Result<Assembly, AssembleErrors> {
+        let mut program = Program::new(self);
+        if let Err(error) = program.include(path.as_ref(), None) {
+            program.report(error);
+        }
+        program.finish()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, String> {
+        match self.files.get(path) {
+            Some(contents) => Ok(contents.clone()),
+            None => {
+                fs::read(path).map_err(|error| format!("cannot read {}: {}", path.display(), error))
+            }
+        }
+    }
+}
+
+// Assembles source that is not in a file. Any files it includes are found relative to the
+// current directory.
+pub fn assemble(source: &str) -> Result<Assembly, AssembleErrors> {
+    let mut assembler = Assembler::new();
+    assembler.add_file("<source>", source.as_bytes().to_vec());
+    assembler.assemble_file("<source>")
+}
+
+// A line as it is assembled. The lines of a macro have their parameters replaced by the arguments
+// of a call, so they keep the call they were expanded from.
+struct SourceLine {
+    file: usize,
+    number: usize,
+    text: String,
+    expansion: Option<Rc<Expansion>>,
+}
+
+// A macro call or `rept` block.
+struct Expansion {
+    name: String,
+    call: Location,
+}
+
+#[derive(Clone)]
+struct Location {
+    line: Rc<SourceLine>,
+    column: usize,
+}
+
+impl Location {
+    fn at(&self, column: usize) -> Location {
+        Location {
+            line: self.line.clone(),
+            column,
+        }
+    }
+}
+
+enum SymbolValue {
+    Label(u16),
+    Constant(Expr),
+}
+
+struct Symbol {
+    value: SymbolValue,
+    location: Location,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Rc<SourceLine>>,
+    location: Location,
+}
+
+// An `if` block. Only one of its branches is assembled, and none if it is inside a branch that is
+// not assembled.
+struct Conditional {
+    active: bool,
+    // Whether a branch has been assembled, or never will be.
+    taken: bool,
+    has_else: bool,
+    location: Location,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OperandKind {
+    Register,
+    StackPointer,
+    Value,
+}
+
+fn operand_kind(operand: &Operand) -> OperandKind {
+    match *operand {
+        Operand::Register(_) => OperandKind::Register,
+        Operand::StackPointer => OperandKind::StackPointer,
+        Operand::Value(_) => OperandKind::Value,
+    }
+}
+
+// The operands that are written for each format, in order.
+fn operand_kinds(format: Format) -> &'static [OperandKind] {
+    use self::OperandKind::*;
+    match format {
+        Format::None => &[],
+        Format::N | Format::I | Format::CondI => &[Value],
+        Format::R => &[Register],
+        Format::RN | Format::RI => &[Register, Value],
+        Format::RR => &[Register, Register],
+        Format::RRI => &[Register, Register, Value],
+        Format::RRR => &[Register, Register, Register],
+        Format::SpI => &[StackPointer, Value],
+        Format::Flip | Format::Sng => &[Value, Value],
+    }
+}
+
+// How the operands of each format are written, in the notation of the specification.
+fn operand_syntax(format: Format) -> &'static str {
+    match format {
+        Format::None => "",
+        Format::N => "n",
+        Format::I | Format::CondI => "hhll",
+        Format::R => "rX",
+        Format::RN => "rX, n",
+        Format::RI => "rX, hhll",
+        Format::RR => "rX, rY",
+        Format::RRI => "rX, rY, hhll",
+        Format::RRR => "rX, rY, rZ",
+        Format::SpI => "sp, hhll",
+        Format::Flip => "h, v",
+        Format::Sng => "ad, vtsr",
+    }
+}
+
+// Every mnemonic and directive, which unknown names are compared to for suggestions.
+fn mnemonics() -> Vec<String> {
+    let mut mnemonics: Vec<String> = DIRECTIVES.iter().map(|name| name.to_string()).collect();
+    for operation in Operation::ALL {
+        if operation.format() == Format::CondI {
+            for condition in Condition::ALL {
+                mnemonics.push(format!("{}{}", operation.mnemonic(), condition.suffix()));
+            }
+        } else {
+            mnemonics.push(operation.mnemonic().to_string());
+        }
+    }
+    mnemonics
+}
+
+const DIRECTIVES: [&str; 12] = [
+    "db",
+    "dw",
+    "equ",
+    "include",
+    "importbin",
+    "macro",
+    "endm",
+    "rept",
+    "endr",
+    "if",
+    "else",
+    "endif",
+];
+
+// Finds the operation from its mnemonic and the kinds of its operands, since several operations
+// share a mnemonic, such as `add r1, r2` and `add r1, r2, r3`. JX and CX are named with the
+// condition as a suffix. Errors come with a help message, if there is one.
+fn resolve(
+    mnemonic: &str,
+    operands: &[Located<Operand>],
+    macros: &HashMap<String, Rc<Macro>>,
+) -> Result<(Operation, Option<Condition>), (String, Option<String>)> {
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let kinds: Vec<_> = operands
+        .iter()
+        .map(|operand| operand_kind(&operand.value))
+        .collect();
+
+    let mut forms = Vec::new();
+    for &operation in Operation::ALL {
+        let condition = if operation.format() == Format::CondI {
+            match mnemonic
+                .strip_prefix(operation.mnemonic())
+                .and_then(Condition::from_suffix)
+            {
+                Some(condition) => Some(condition),
+                None => continue,
+            }
+        } else if mnemonic == operation.mnemonic() {
+            None
+        } else {
+            continue;
+        };
+
+        if operand_kinds(operation.format()) == kinds.as_slice() {
+            return Ok((operation, condition));
+        }
+        let form = match operand_syntax(operation.format()) {
+            "" => format!("`{}`", mnemonic),
+            syntax => format!("`{} {}`", mnemonic, syntax),
+        };
+        forms.push(form);
+    }
+
+    if let Some((last, rest)) = forms.split_last() {
+        let message = format!("invalid operands for `{}`", mnemonic);
+        let help = if rest.is_empty() {
+            format!("it is written as {}", last)
+        } else {
+            format!("it is written as {} or {}", rest.join(", "), last)
+        };
+        Err((message, Some(help)))
+    } else {
+        let message = format!("unknown instruction `{}`", mnemonic);
+        let mnemonics = mnemonics();
+        let names = mnemonics.iter().chain(macros.keys());
+        let help = suggest(&mnemonic, names.map(String::as_str));
+        Err((message, help))
+    }
+}
+
+// The index of the line that closes a block, in the lines after the one that opens it. Blocks of
+// the same kind can be nested.
+fn block_end(lines: &[Rc<SourceLine>], open: &str, close: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, line) in lines.iter().enumerate() {
+        let keyword = match parser::mnemonic(&line.text) {
+            Some(name) => name.value.to_ascii_lowercase(),
+            None => continue,
+        };
+        if keyword == open {
+            depth += 1;
+        } else if keyword == close {
+            if depth == 0 {
+                return Some(index);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+enum ItemKind {
+    Instruction {
+        operation: Operation,
+        condition: Option<Condition>,
+        operands: Vec<Located<Operand>>,
+    },
+    Bytes(Vec<Located<Data>>),
+    Words(Vec<Located<Expr>>),
+    Binary(Vec<u8>),
+}
+
+// Everything that emits content. Its size is known in the first pass, but the values of its
+// expressions are only known in the second, once every label has been defined.
+struct Item {
+    kind: ItemKind,
+    location: Location,
+}
+
+struct Program<'a> {
+    assembler: &'a Assembler,
+    paths: Vec<PathBuf>,
+    errors: Vec<AssembleError>,
+    symbols: BTreeMap<String, Symbol>,
+    debug_symbols: Symbols,
+    macros: HashMap<String, Rc<Macro>>,
+    items: Vec<Item>,
+    address: u32,
+    depth: usize,
+    expansion_depth: usize,
+    expanded_lines: usize,
+    // The name that local labels are qualified with, and the number of macro calls so far, which
+    // gives every call its own.
+    scope: String,
+    calls: usize,
+    // The constants being evaluated, to find constants that are defined in terms of themselves.
+    resolving: Vec<String>,
+}
+
+impl<'a> Program<'a> {
+    fn new(assembler: &'a Assembler) -> Program<'a> {
+        Program {
+            assembler,
+            paths: Vec::new(),
+            errors: Vec::new(),
+            symbols: BTreeMap::new(),
+            debug_symbols: Symbols::new(),
+            macros: HashMap::new(),
+            items: Vec::new(),
+            address: 0,
+            depth: 0,
+            expansion_depth: 0,
+            expanded_lines: 0,
+            scope: String::new(),
+            calls: 0,
+            resolving: Vec::new(),
+        }
+    }
+
+    // Errors in expanded lines note each call they were expanded from. A macro that calls itself
+    // repeats the same call, which is only noted once.
+    fn error<S: Into<String>>(&self, location: &Location, message: S) -> AssembleError {
+        let line = &location.line;
+        let mut expansions: Vec<diagnostic::Expansion> = Vec::new();
+        let mut expansion = line.expansion.clone();
+        while let Some(outer) = expansion {
+            let call = &outer.call;
+            let note = diagnostic::Expansion {
+                name: outer.name.clone(),
+                path: self.paths[call.line.file].clone(),
+                line: call.line.number,
+                column: call.column,
+                source_line: call.line.text.clone(),
+            };
+            if expansions.last() != Some(&note) {
+                expansions.push(note);
+            }
+            expansion = call.line.expansion.clone();
+        }
+
+        AssembleError {
+            path: self.paths[line.file].clone(),
+            line: line.number,
+            column: location.column,
+            message: message.into(),
+            source_line: line.text.clone(),
+            help: None,
+            expansions,
+        }
+    }
+
+    // Errors are reported once, even if an expression with an error is evaluated many times.
+    fn report(&mut self, error: AssembleError) {
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    // The file and line of a location, to refer to it in another error.
+    fn place(&self, location: &Location) -> String {
+        let path = self.paths[location.line.file].display();
+        format!("{}:{}", path, location.line.number)
+    }
+
+    // Assembles a file in place, where `location` is the include directive that names it.
+    fn include(&mut self, path: &Path, location: Option<&Location>) -> Result<(), AssembleError> {
+        let fail = |program: &Program, message: String| match location {
+            Some(location) => program.error(location, message),
+            None => AssembleError {
+                path: path.to_path_buf(),
+                line: 0,
+                column: 0,
+                message,
+                source_line: String::new(),
+                help: None,
+                expansions: Vec::new(),
+            },
+        };
+
+        if self.depth == MAX_INCLUDE_DEPTH {
+            let message = format!("includes are nested more than {} deep", MAX_INCLUDE_DEPTH);
+            return Err(fail(self, message));
+        }
+        let source = self
+            .assembler
+            .read(path)
+            .map_err(|message| fail(self, message))?;
+        let source = String::from_utf8(source)
+            .map_err(|_| fail(self, format!("{} is not valid UTF-8", path.display())))?;
+
+        let file = self.paths.len();
+        self.paths.push(path.to_path_buf());
+        let lines: Vec<_> = source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| {
+                Rc::new(SourceLine {
+                    file,
+                    number: index + 1,
+                    text: text.to_string(),
+                    expansion: None,
+                })
+            })
+            .collect();
+        self.depth += 1;
+        self.lines(&lines);
+        self.depth -= 1;
+        Ok(())
+    }
+
+    // Assembles the lines of a file, a macro or a `rept` block, where blocks start and end. An
+    // `if` has to end in the same lines as it starts.
+    fn lines(&mut self, lines: &[Rc<SourceLine>]) {
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let location = Location {
+                line: lines[index].clone(),
+                column: 1,
+            };
+            index += 1;
+
+            let mnemonic = parser::mnemonic(&location.line.text);
+            let (keyword, column) = match mnemonic {
+                Some(ref name) => (name.value.to_ascii_lowercase(), name.column),
+                None => (String::new(), 1),
+            };
+            let skipping = conditionals.iter().any(|conditional| !conditional.active);
+            let result = match keyword.as_str() {
+                "if" | "else" | "endif" => {
+                    self.conditional(&keyword, &location.at(column), &mut conditionals)
+                }
+                _ if skipping => Ok(()),
+                "macro" | "rept" => {
+                    let close = if keyword == "macro" { "endm" } else { "endr" };
+                    let end = block_end(&lines[index..], &keyword, close).map(|end| index + end);
+                    let body = &lines[index..end.unwrap_or(lines.len())];
+                    index = end.map_or(lines.len(), |end| end + 1);
+
+                    // The block still takes the rest of the lines, so they are not assembled twice.
+                    if end.is_none() {
+                        let message = format!("`{}` is not closed by `{}`", keyword, close);
+                        let error = self.error(&location.at(column), message);
+                        self.report(error);
+                    }
+                    if keyword == "macro" {
+                        self.define_macro(&location.at(column), body.to_vec())
+                    } else {
+                        self.repeat(&location.at(column), body)
+                    }
+                }
+                "endm" | "endr" => {
+                    let open = if keyword == "endm" { "macro" } else { "rept" };
+                    let message = format!("`{}` without `{}`", keyword, open);
+                    Err(self.error(&location.at(column), message))
+                }
+                _ => match mnemonic {
+                    Some(ref name) if self.macros.contains_key(&name.value) => self.call(&location),
+                    _ => self.line(&location),
+                },
+            };
+            if let Err(error) = result {
+                self.report(error);
+            }
+        }
+
+        for conditional in conditionals {
+            let error = self.error(&conditional.location, "`if` is not closed by `endif`");
+            self.report(error);
+        }
+    }
+
+    // Parses a line that starts or ends a block, which cannot have a label.
+    fn directive(&self, keyword: &str, location: &Location) -> Result<Statement, AssembleError> {
+        let line = parser::parse_line(&location.line.text)
+            .map_err(|error| self.error(&location.at(error.column), error.message))?;
+        if let Some(label) = line.label {
+            let message = format!("`{}` cannot have a label", keyword);
+            return Err(self.error(&location.at(label.column), message));
+        }
+        let mut statement = line
+            .statement
+            .expect("directives are found by their mnemonic");
+        self.qualify_statement(&mut statement);
+        Ok(statement)
+    }
+
+    fn conditional(
+        &mut self,
+        keyword: &str,
+        location: &Location,
+        conditionals: &mut Vec<Conditional>,
+    ) -> Result<(), AssembleError> {
+        if keyword == "if" {
+            let mut conditional = Conditional {
+                active: false,
+                taken: true,
+                has_else: false,
+                location: location.clone(),
+            };
+            // A condition with an error assembles neither branch, rather than guessing.
+            let skipping = conditionals.iter().any(|conditional| !conditional.active);
+            let result = if skipping {
+                Ok(())
+            } else {
+                self.condition(location).map(|value| {
+                    conditional.active = value;
+                    conditional.taken = value;
+                })
+            };
+            conditionals.push(conditional);
+            return result;
+        }
+
+        let conditional = match conditionals.last_mut() {
+            Some(conditional) => conditional,
+            None => return Err(self.error(location, format!("`{}` without `if`", keyword))),
+        };
+        if keyword == "else" {
+            if conditional.has_else {
+                return Err(self.error(location, "`if` already has an `else`"));
+            }
+            conditional.has_else = true;
+            conditional.active = !conditional.taken;
+            conditional.taken = true;
+        } else {
+            conditionals.pop();
+        }
+        self.directive(keyword, location).map(|_| ())
+    }
+
+    fn condition(&mut self, location: &Location) -> Result<bool, AssembleError> {
+        match self.directive("if", location)? {
+            Statement::If(condition) => Ok(self.evaluate(&condition.value, location)? != 0),
+            _ => unreachable!("`if` is found by its mnemonic"),
+        }
+    }
+
+    fn define_macro(
+        &mut self,
+        location: &Location,
+        body: Vec<Rc<SourceLine>>,
+    ) -> Result<(), AssembleError> {
+        let (name, parameters) = match self.directive("macro", location)? {
+            Statement::Macro { name, parameters } => (name, parameters),
+            _ => unreachable!("`macro` is found by its mnemonic"),
+        };
+        let location = location.at(name.column);
+        if mnemonics().contains(&name.value.to_ascii_lowercase()) {
+            let message = format!(
+                "`{}` is an instruction or directive, and cannot be a macro",
+                name.value
+            );
+            return Err(self.error(&location, message));
+        }
+        if let Some(existing) = self.macros.get(&name.value) {
+            let message = format!(
+                "macro `{}` is already defined at {}",
+                name.value,
+                self.place(&existing.location)
+            );
+            return Err(self.error(&location, message));
+        }
+
+        let parameters = parameters
+            .into_iter()
+            .map(|parameter| parameter.value)
+            .collect();
+        let definition = Macro {
+            parameters,
+            body,
+            location,
+        };
+        self.macros.insert(name.value, Rc::new(definition));
+        Ok(())
+    }
+
+    // Assembles the lines of a macro in place, with its parameters replaced by the arguments.
+    fn call(&mut self, location: &Location) -> Result<(), AssembleError> {
+        let call = parser::parse_call(&location.line.text)
+            .map_err(|error| self.error(&location.at(error.column), error.message))?;
+        if let Some(label) = call.label {
+            self.label(label, location)?;
+        }
+
+        let name = call.name.value;
+        let definition = self.macros[&name].clone();
+        let call_location = location.at(call.name.column);
+        if call.arguments.len() != definition.parameters.len() {
+            let message = format!(
+                "`{}` expects {} arguments, found {}",
+                name,
+                definition.parameters.len(),
+                call.arguments.len()
+            );
+            let help = if definition.parameters.is_empty() {
+                format!("it is written as `{}`", name)
+            } else {
+                format!(
+                    "it is written as `{} {}`",
+                    name,
+                    definition.parameters.join(", ")
+                )
+            };
+            return Err(self.error(&call_location, message).with_help(Some(help)));
+        }
+
+        // Local labels in the arguments belong to the caller.
+        let arguments: HashMap<&str, String> = definition
+            .parameters
+            .iter()
+            .map(String::as_str)
+            .zip(call.arguments.iter().map(|argument| {
+                lexer::substitute(&argument.value, |name| {
+                    if name.starts_with('.') {
+                        Some(format!("{}{}", self.scope, name))
+                    } else {
+                        None
+                    }
+                })
+            }))
+            .collect();
+        let expansion = Rc::new(Expansion {
+            name: format!("macro `{}`", name),
+            call: call_location.clone(),
+        });
+        let lines: Vec<_> = definition
+            .body
+            .iter()
+            .map(|line| {
+                Rc::new(SourceLine {
+                    file: line.file,
+                    number: line.number,
+                    text: lexer::substitute(&line.text, |name| arguments.get(name).cloned()),
+                    expansion: Some(expansion.clone()),
+                })
+            })
+            .collect();
+
+        self.calls += 1;
+        let scope = format!("{}.{}", name, self.calls);
+        self.expand(&call_location, &lines, 1, Some(scope))
+    }
+
+    fn repeat(
+        &mut self,
+        location: &Location,
+        body: &[Rc<SourceLine>],
+    ) -> Result<(), AssembleError> {
+        let count = match self.directive("rept", location)? {
+            Statement::Rept(count) => count,
+            _ => unreachable!("`rept` is found by its mnemonic"),
+        };
+        let count = self.value(&count, location, 0, MAX_PROGRAM_SIZE as i64)?;
+
+        let expansion = Rc::new(Expansion {
+            name: "`rept`".to_string(),
+            call: location.clone(),
+        });
+        let lines: Vec<_> = body
+            .iter()
+            .map(|line| {
+                Rc::new(SourceLine {
+                    file: line.file,
+                    number: line.number,
+                    text: line.text.clone(),
+                    expansion: Some(expansion.clone()),
+                })
+            })
+            .collect();
+        self.expand(location, &lines, count, None)
+    }
+
+    // Assembles expanded lines `count` times. Macros have their own scope for local labels, while
+    // `rept` blocks share the scope they are in.
+    fn expand(
+        &mut self,
+        call: &Location,
+        lines: &[Rc<SourceLine>],
+        count: i64,
+        scope: Option<String>,
+    ) -> Result<(), AssembleError> {
+        if self.expansion_depth == MAX_EXPANSION_DEPTH {
+            let message = format!(
+                "macros and `rept` blocks are nested more than {} deep",
+                MAX_EXPANSION_DEPTH
+            );
+            return Err(self.error(call, message));
+        }
+
+        // Once the limit has been reported, nothing more is expanded.
+        if self.expanded_lines > MAX_EXPANDED_LINES {
+            return Ok(());
+        }
+
+        let outer_scope = scope.map(|scope| mem::replace(&mut self.scope, scope));
+        self.expansion_depth += 1;
+        let mut result = Ok(());
+        for _ in 0..count {
+            // A full program has already been reported, and repeating it would only report it
+            // again for every repetition.
+            if self.address > MAX_PROGRAM_SIZE || self.expanded_lines > MAX_EXPANDED_LINES {
+                break;
+            }
+            self.expanded_lines += lines.len();
+            if self.expanded_lines > MAX_EXPANDED_LINES {
+                let message = format!(
+                    "macros and `rept` blocks expand to more than {} lines",
+                    MAX_EXPANDED_LINES
+                );
+                result = Err(self.error(call, message));
+                break;
+            }
+            self.lines(lines);
+        }
+        self.expansion_depth -= 1;
+        if let Some(outer_scope) = outer_scope {
+            self.scope = outer_scope;
+        }
+        result
+    }
+
+    // The first pass, which defines the labels and constants on the line, and sizes its content.
+    fn line(&mut self, location: &Location) -> Result<(), AssembleError> {
+        let line = match parser::parse_line(&location.line.text) {
+            Ok(line) => line,
+            Err(error) => {
+                // Labels are still defined, so that their uses are not errors as well.
+                if let Some(label) = parser::parse_label(&location.line.text) {
+                    self.label(label, location)?;
+                }
+                return Err(self.error(&location.at(error.column), error.message));
+            }
+        };
+
+        if let Some(label) = line.label {
+            self.label(label, location)?;
+        }
+
+        let mut statement = match line.statement {
+            Some(statement) => statement,
+            None => return Ok(()),
+        };
+        self.qualify_statement(&mut statement);
+        let (kind, size) = match statement {
+            Statement::Equ { name, value } => {
+                return self.define(name, SymbolValue::Constant(value.value), location);
+            }
+            Statement::Include(path) => {
+                let path = self.relative_path(&path.value, location);
+                return self.include(&path, Some(location));
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                let (operation, condition) = match resolve(&mnemonic.value, &operands, &self.macros)
+                {
+                    Ok(resolved) => resolved,
+                    Err((message, help)) => {
+                        self.address += 4;
+                        let error = self.error(&location.at(mnemonic.column), message);
+                        return Err(error.with_help(help));
+                    }
+                };
+                let kind = ItemKind::Instruction {
+                    operation,
+                    condition,
+                    operands,
+                };
+                (kind, 4)
+            }
+            Statement::Db(data) => {
+                let size = data
+                    .iter()
+                    .map(|data| match data.value {
+                        Data::Value(_) => 1,
+                        Data::String(ref bytes) => bytes.len() as u32,
+                    })
+                    .sum();
+                (ItemKind::Bytes(data), size)
+            }
+            Statement::Dw(words) => {
+                let size = 2 * words.len() as u32;
+                (ItemKind::Words(words), size)
+            }
+            Statement::ImportBin {
+                path,
+                offset,
+                length,
+            } => {
+                let bytes = self.import_binary(&path, offset, length, location)?;
+                let size = bytes.len() as u32;
+                (ItemKind::Binary(bytes), size)
+            }
+            Statement::Macro { .. }
+            | Statement::EndMacro
+            | Statement::Rept(_)
+            | Statement::EndRept
+            | Statement::If(_)
+            | Statement::Else
+            | Statement::EndIf => unreachable!("blocks are found by their mnemonic"),
+        };
+
+        if size > 0 && self.address < MAX_PROGRAM_SIZE {
+            let path = self.paths[location.line.file].display().to_string();
+            let address = self.address as u16;
+            self.debug_symbols
+                .add_line(address, &path, location.line.number);
+        }
+        self.address += size;
+        if self.address > MAX_PROGRAM_SIZE {
+            return Err(self.error(location, "the program is larger than 64K"));
+        }
+        self.items.push(Item {
+            kind,
+            location: location.clone(),
+        });
+        Ok(())
+    }
+
+    fn label(
+        &mut self,
+        mut name: Located<String>,
+        location: &Location,
+    ) -> Result<(), AssembleError> {
+        if self.address >= MAX_PROGRAM_SIZE {
+            let error = self.error(&location.at(name.column), "the label is outside of memory");
+            return Err(error);
+        }
+        if !name.value.starts_with('.') {
+            self.scope = name.value.clone();
+        }
+        self.qualify(&mut name.value);
+
+        let address = self.address as u16;
+        self.define(name.clone(), SymbolValue::Label(address), location)?;
+        self.debug_symbols.add_label(address, &name.value);
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        name: Located<String>,
+        value: SymbolValue,
+        location: &Location,
+    ) -> Result<(), AssembleError> {
+        let location = location.at(name.column);
+        if parser::register(&name.value).is_some() || name.value.eq_ignore_ascii_case("sp") {
+            let message = format!("`{}` is a register, and cannot be redefined", name.value);
+            return Err(self.error(&location, message));
+        }
+        if let Some(symbol) = self.symbols.get(&name.value) {
+            let message = format!(
+                "`{}` is already defined at {}",
+                name.value,
+                self.place(&symbol.location)
+            );
+            return Err(self.error(&location, message));
+        }
+
+        self.symbols.insert(name.value, Symbol { value, location });
+        Ok(())
+    }
+
+    // Local names start with a `.`, and belong to the label before them, or to the call of the
+    // macro they are in. They are qualified with its name, so `.loop` after `draw:` is
+    // `draw.loop`.
+    fn qualify(&self, name: &mut String) {
+        if name.starts_with('.') {
+            *name = format!("{}{}", self.scope, name);
+        }
+    }
+
+    fn qualify_expr(&self, expr: &mut Expr) {
+        match *expr {
+            Expr::Number(_) => {}
+            Expr::Symbol { ref mut name, .. } => self.qualify(name),
+            Expr::Unary {
+                ref mut operand, ..
+            } => self.qualify_expr(operand),
+            Expr::Binary {
+                ref mut left,
+                ref mut right,
+                ..
+            } => {
+                self.qualify_expr(left);
+                self.qualify_expr(right);
+            }
+        }
+    }
+
+    // Qualifies every local name that a statement defines or uses.
+    fn qualify_statement(&self, statement: &mut Statement) {
+        match *statement {
+            Statement::Instruction {
+                ref mut operands, ..
+            } => {
+                for operand in operands {
+                    if let Operand::Value(ref mut expr) = operand.value {
+                        self.qualify_expr(expr);
+                    }
+                }
+            }
+            Statement::Equ {
+                ref mut name,
+                ref mut value,
+            } => {
+                self.qualify(&mut name.value);
+                self.qualify_expr(&mut value.value);
+            }
+            Statement::Db(ref mut data) => {
+                for data in data {
+                    if let Data::Value(ref mut expr) = data.value {
+                        self.qualify_expr(expr);
+                    }
+                }
+            }
+            Statement::Dw(ref mut words) => {
+                for word in words {
+                    self.qualify_expr(&mut word.value);
+                }
+            }
+            Statement::ImportBin {
+                ref mut offset,
+                ref mut length,
+                ..
+            } => {
+                for expr in offset.iter_mut().chain(length.iter_mut()) {
+                    self.qualify_expr(&mut expr.value);
+                }
+            }
+            Statement::Rept(ref mut expr) | Statement::If(ref mut expr) => {
+                self.qualify_expr(&mut expr.value);
+            }
+            _ => {}
+        }
+    }
+
+    fn relative_path(&self, path: &str, location: &Location) -> PathBuf {
+        let parent = self.paths[location.line.file].parent();
+        parent.unwrap_or_else(|| Path::new("")).join(path)
+    }
+
+    // The offset and length can only use constants and labels that are defined before them,
+    // since they decide the addresses of everything after them.
+    fn import_binary(
+        &mut self,
+        path: &Located<String>,
+        offset: Option<Located<Expr>>,
+        length: Option<Located<Expr>>,
+        location: &Location,
+    ) -> Result<Vec<u8>, AssembleError> {
+        let file = self.relative_path(&path.value, location);
+        let bytes = self
+            .assembler
+            .read(&file)
+            .map_err(|message| self.error(&location.at(path.column), message))?;
+
+        let size = bytes.len() as i64;
+        let offset = match offset {
+            Some(offset) => self.value(&offset, location, 0, size)?,
+            None => 0,
+        };
+        let length = match length {
+            Some(length) => self.value(&length, location, 0, size - offset)?,
+            None => size - offset,
+        };
+        Ok(bytes[offset as usize..(offset + length) as usize].to_vec())
+    }
+
+    // The second pass, which evaluates every expression and emits the content.
+    fn finish(mut self) -> Result<Assembly, AssembleErrors> {
+        let items = mem::take(&mut self.items);
+        let mut content = Vec::with_capacity(self.address as usize);
+        for item in &items {
+            if let Err(error) = self.emit(item, &mut content) {
+                self.report(error);
+            }
+        }
+        if !self.errors.is_empty() {
+            return Err(AssembleErrors(self.errors));
+        }
+        Ok(Assembly {
+            content,
+            symbols: self.debug_symbols,
+        })
+    }
+
+    fn emit(&mut self, item: &Item, content: &mut Vec<u8>) -> Result<(), AssembleError> {
+        let location = &item.location;
+        match item.kind {
+            ItemKind::Instruction {
+                operation,
+                condition,
+                ref operands,
+            } => {
+                let instruction = self.encode(operation, condition, operands, location)?;
+                content.extend_from_slice(&instruction.0.to_le_bytes());
+            }
+            ItemKind::Bytes(ref data) => {
+                for data in data {
+                    match data.value {
+                        Data::String(ref bytes) => content.extend_from_slice(bytes),
+                        Data::Value(ref expr) => {
+                            let value = Located {
+                                value: expr.clone(),
+                                column: data.column,
+                            };
+                            content.push(self.value(&value, location, -0x80, 0xFF)? as u8);
+                        }
+                    }
+                }
+            }
+            ItemKind::Words(ref words) => {
+                for word in words {
+                    let word = self.value(word, location, -0x8000, 0xFFFF)? as u16;
+                    content.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+            ItemKind::Binary(ref bytes) => content.extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+
+    fn encode(
+        &mut self,
+        operation: Operation,
+        condition: Option<Condition>,
+        operands: &[Located<Operand>],
+        location: &Location,
+    ) -> Result<Instruction, AssembleError> {
+        let register = |index: usize| match operands[index].value {
+            Operand::Register(register) => register,
+            _ => unreachable!("the operands were checked by resolve"),
+        };
+
+        let mut fields = Operands::default();
+        match operation.format() {
+            Format::None => {}
+            Format::N => fields.n = self.operand(operands, 0, location, 0, 0xF)? as u8,
+            Format::I => fields.hhll = self.word(operands, 0, location)?,
+            Format::R => fields.x = register(0),
+            Format::RN => {
+                fields.x = register(0);
+                fields.n = self.operand(operands, 1, location, 0, 0xF)? as u8;
+            }
+            Format::RI => {
+                fields.x = register(0);
+                fields.hhll = self.word(operands, 1, location)?;
+            }
+            Format::RR => {
+                fields.x = register(0);
+                fields.y = register(1);
+            }
+            Format::RRI => {
+                fields.x = register(0);
+                fields.y = register(1);
+                fields.hhll = self.word(operands, 2, location)?;
+            }
+            Format::RRR => {
+                fields.x = register(0);
+                fields.y = register(1);
+                fields.z = register(2);
+            }
+            Format::SpI => fields.hhll = self.word(operands, 1, location)?,
+            Format::CondI => {
+                fields.x = condition.expect("conditions are resolved with the mnemonic") as u8;
+                fields.hhll = self.word(operands, 0, location)?;
+            }
+            Format::Flip => {
+                let horizontal = self.operand(operands, 0, location, 0, 1)? as u16;
+                let vertical = self.operand(operands, 1, location, 0, 1)? as u16;
+                fields.hhll = horizontal << 9 | vertical << 8;
+            }
+            Format::Sng => {
+                let ad = self.operand(operands, 0, location, 0, 0xFF)? as u8;
+                fields.y = ad >> 4;
+                fields.x = ad & 0xF;
+                fields.hhll = self.word(operands, 1, location)?;
+            }
+        }
+        Ok(Instruction::encode(operation, fields))
+    }
+
+    // A 16-bit operand, which can be written as signed or unsigned.
+    fn word(
+        &mut self,
+        operands: &[Located<Operand>],
+        index: usize,
+        location: &Location,
+    ) -> Result<u16, AssembleError> {
+        self.operand(operands, index, location, -0x8000, 0xFFFF)
+            .map(|value| value as u16)
+    }
+
+    fn operand(
+        &mut self,
+        operands: &[Located<Operand>],
+        index: usize,
+        location: &Location,
+        min: i64,
+        max: i64,
+    ) -> Result<i64, AssembleError> {
+        match operands[index].value {
+            Operand::Value(ref expr) => {
+                let value = Located {
+                    value: expr.clone(),
+                    column: operands[index].column,
+                };
+                self.value(&value, location, min, max)
+            }
+            _ => unreachable!("the operands were checked by resolve"),
+        }
+    }
+
+    fn value(
+        &mut self,
+        expr: &Located<Expr>,
+        location: &Location,
+        min: i64,
+        max: i64,
+    ) -> Result<i64, AssembleError> {
+        let value = self.evaluate(&expr.value, location)?;
+        if value < min || value > max {
+            let message = format!("{} is out of range, expected {} to {}", value, min, max);
+            return Err(self.error(&location.at(expr.column), message));
+        }
+        Ok(value)
+    }
+
+    fn evaluate(&mut self, expr: &Expr, location: &Location) -> Result<i64, AssembleError> {
+        match *expr {
+            Expr::Number(value) => Ok(value),
+            Expr::Symbol { ref name, column } => self.symbol(name, &location.at(column)),
+            Expr::Unary {
+                operator,
+                ref operand,
+            } => {
+                let value = self.evaluate(operand, location)?;
+                Ok(match operator {
+                    UnaryOperator::Plus => value,
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Not => !value,
+                })
+            }
+            Expr::Binary {
+                operator,
+                ref left,
+                ref right,
+                column,
+            } => {
+                let left = self.evaluate(left, location)?;
+                let right = self.evaluate(right, location)?;
+                operator.apply(left, right).ok_or_else(|| {
+                    let message = match operator {
+                        BinaryOperator::Divide | BinaryOperator::Remainder => "division by zero",
+                        _ => "the shift amount is out of range",
+                    };
+                    self.error(&location.at(column), message)
+                })
+            }
+        }
+    }
+
+    fn symbol(&mut self, name: &str, location: &Location) -> Result<i64, AssembleError> {
+        let (expr, definition) = match self.symbols.get(name) {
+            Some(Symbol {
+                value: SymbolValue::Label(address),
+                ..
+            }) => return Ok(*address as i64),
+            Some(Symbol {
+                value: SymbolValue::Constant(expr),
+                location,
+            }) => (expr.clone(), location.clone()),
+            None => {
+                let error = self.error(location, format!("`{}` is not defined", name));
+                let help = suggest(name, self.symbols.keys().map(String::as_str));
+                return Err(error.with_help(help));
+            }
+        };
+
+        if self.resolving.iter().any(|resolving| resolving == name) {
+            let message = format!("`{}` is defined in terms of itself", name);
+            return Err(self.error(location, message));
+        }
+        self.resolving.push(name.to_string());
+        let value = self.evaluate(&expr, &definition);
+        self.resolving.pop();
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm::trace;
+    use rom::Rom;
+    use std::fs::{self, File};
+
+    fn words(source: &str) -> Vec<u32> {
+        let content = assemble(source).unwrap().content;
+        content
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect()
+    }
+
+    fn errors(source: &str) -> Vec<AssembleError> {
+        assemble(source).unwrap_err().0
+    }
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let errors = errors(source);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        (errors[0].line, errors[0].column, errors[0].message.clone())
+    }
+
+    #[test]
+    fn assemble_every_format() {
+        let source = "
+            nop
+            bgc 0x3
+            spr 0x0F06
+            drw r1, r2, 0x5678
+            drw r1, r2, r3
+            ldi sp, 0xFDF0
+            ldi rA, -1
+            shl rF, 3
+            shl rF, r1
+            jmp r4
+            jnz 0x0100
+            cnc 0x0100
+            flip 1, 0
+            sng 0x3A, 0x5678
+            NOT R1
+        ";
+        assert_eq!(
+            words(source),
+            vec![
+                0x0000_0000,
+                0x0003_0003,
+                0x0F06_0004,
+                0x5678_2105,
+                0x0003_2106,
+                0xFDF0_0021,
+                0xFFFF_0A20,
+                0x0003_0FB0,
+                0x0000_1FB3,
+                0x0000_0416,
+                0x0100_0112,
+                0x0100_0817,
+                0x0200_0008,
+                0x5678_3A0E,
+                0x0000_01E1,
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_labels_and_constants() {
+        let source = "
+            SPEED equ STEP * 2
+            STEP: equ 3
+            start:
+                addi r0, SPEED
+                jmp end
+            data: db 1, 2, 3
+            end:  call start
+                  ldi r1, data + (end - data) / 2
+        ";
+        let assembly = assemble(source).unwrap();
+        let symbols = &assembly.symbols;
+        assert_eq!(symbols.address_of("start"), Some(0x0000));
+        assert_eq!(symbols.address_of("data"), Some(0x0008));
+        assert_eq!(symbols.address_of("end"), Some(0x000B));
+        assert_eq!(symbols.address_of("SPEED"), None);
+        assert_eq!(
+            assembly.content,
+            vec![
+                0x40, 0x00, 0x06, 0x00, 0x10, 0x00, 0x0B, 0x00, 0x01, 0x02, 0x03, 0x14, 0x00, 0x00,
+                0x00, 0x20, 0x01, 0x09, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_line_map() {
+        let mut assembler = Assembler::new();
+        assembler.add_file("main.s", b"start:\n  include draw.s\n  db 1, 2\n".to_vec());
+        assembler.add_file("draw.s", b"draw: cls\n; comment\n  ret".to_vec());
+
+        let symbols = assembler.assemble_file("main.s").unwrap().symbols;
+        let labels: Vec<_> = symbols.labels().collect();
+        assert_eq!(labels, vec![(0, "start"), (0, "draw")]);
+
+        let line = |address| {
+            let source = symbols.source(address).unwrap();
+            (source.path.as_str(), source.line)
+        };
+        assert_eq!(line(0), ("draw.s", 1));
+        assert_eq!(line(4), ("draw.s", 3));
+        assert_eq!(line(8), ("main.s", 3));
+        assert_eq!(symbols.source(9), None);
+    }
+
+    #[test]
+    fn assemble_data() {
+        let source = "db \"AB\", -1, 'c'\ndw 0x1234, -2";
+        assert_eq!(
+            assemble(source).unwrap().content,
+            vec![0x41, 0x42, 0xFF, 0x63, 0x34, 0x12, 0xFE, 0xFF]
+        );
+    }
+
+    #[test]
+    fn assemble_files() {
+        let mut assembler = Assembler::new();
+        let main = b"include lib/util.s\nimportbin lib/font.bin, 1, 2";
+        assembler.add_file("game/main.s", main.to_vec());
+        assembler.add_file("game/lib/util.s", b"util: ret\nimportbin font.bin".to_vec());
+        assembler.add_file("game/lib/font.bin", vec![1, 2, 3, 4]);
+
+        let assembly = assembler.assemble_file("game/main.s").unwrap();
+        assert_eq!(assembly.symbols.address_of("util"), Some(0));
+        assert_eq!(assembly.content, vec![0x15, 0, 0, 0, 1, 2, 3, 4, 2, 3]);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert_eq!(
+            error("nop\n  foo r1"),
+            (2, 3, "unknown instruction `foo`".to_string())
+        );
+        assert_eq!(
+            error("add r1, 5"),
+            (1, 1, "invalid operands for `add`".to_string())
+        );
+        assert_eq!(
+            error("shl r1, 16"),
+            (1, 9, "16 is out of range, expected 0 to 15".to_string())
+        );
+        assert_eq!(
+            error("jmp missing"),
+            (1, 5, "`missing` is not defined".to_string())
+        );
+        assert_eq!(
+            error("a: nop\na: nop"),
+            (2, 1, "`a` is already defined at <source>:1".to_string())
+        );
+        assert_eq!(
+            error("r1: nop"),
+            (
+                1,
+                1,
+                "`r1` is a register, and cannot be redefined".to_string()
+            )
+        );
+        assert_eq!(
+            error("A equ B\nB equ A\ndw A"),
+            (2, 7, "`A` is defined in terms of itself".to_string())
+        );
+        assert_eq!(
+            error("db 1 / (2 - 2)"),
+            (1, 6, "division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn assemble_file_errors() {
+        let mut assembler = Assembler::new();
+        assembler.add_file("loop.s", b"nop\ninclude loop.s".to_vec());
+        let error = &assembler.assemble_file("loop.s").unwrap_err().0[0];
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "includes are nested more than 16 deep");
+        assert_eq!(error.source_line, "include loop.s");
+
+        let error = &assembler.assemble_file("missing.s").unwrap_err().0[0];
+        assert_eq!(error.line, 0);
+        assert!(error
+            .to_string()
+            .starts_with("missing.s: cannot read missing.s"));
+    }
+
+    #[test]
+    fn report_every_error() {
+        let source = "
+            start: ad r1, r2
+                   jmp strat
+            bad:   add r1 r2
+                   jmp bad
+                   db 256, 1 / 0
+                   dw 1, 2, 0x10000
+        ";
+        let found: Vec<_> = errors(source)
+            .iter()
+            .map(|error| (error.line, error.column, error.message.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, 20, "unknown instruction `ad`".to_string()),
+                (4, 27, "expected a `,` or the end of the line".to_string()),
+                (3, 24, "`strat` is not defined".to_string()),
+                (
+                    6,
+                    23,
+                    "256 is out of range, expected -128 to 255".to_string()
+                ),
+                (
+                    7,
+                    29,
+                    "65536 is out of range, expected -32768 to 65535".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_have_help() {
+        let help = |source| errors(source)[0].help.clone();
+        assert_eq!(help("pushal"), Some("did you mean `pushall`?".to_string()));
+        assert_eq!(help("jnzz 0"), Some("did you mean `jnz`?".to_string()));
+        assert_eq!(help("bogus r1"), None);
+        assert_eq!(
+            help("shl r1"),
+            Some("it is written as `shl rX, n` or `shl rX, rY`".to_string())
+        );
+        assert_eq!(help("cls 1"), Some("it is written as `cls`".to_string()));
+        assert_eq!(
+            help("loop: jmp lop"),
+            Some("did you mean `loop`?".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_show_the_source_line() {
+        let error = &errors(
+            "nop
+	ldi r1, r2 ; oops",
+        )[0];
+        assert_eq!(
+            error.to_string(),
+            "<source>:2:2: invalid operands for `ldi`\n\tldi r1, r2 ; oops\n\t^\n\
+             help: it is written as `ldi rX, hhll` or `ldi sp, hhll`"
+        );
+    }
+
+    #[test]
+    fn assemble_macros() {
+        let source = "
+            macro move x, y, value
+                ldi x, value
+                mov y, x
+            endm
+            macro clear
+                CLS
+            endm
+            start: move r1, r2, 2 * (3 + 4)
+                   clear
+        ";
+        assert_eq!(words(source), vec![0x000E_0120, 0x0000_1224, 0x0000_0001]);
+    }
+
+    #[test]
+    fn assemble_local_labels() {
+        let source = "
+            macro wait count
+                ldi r0, count
+            .loop:
+                subi r0, 1
+                jnz .loop
+            endm
+            first:
+                wait 3
+            .loop: jmp .loop
+            second:
+            .loop: jmp first.loop
+                wait .loop
+        ";
+        let assembly = assemble(source).unwrap();
+        let labels: Vec<_> = assembly.symbols.labels().collect();
+        assert_eq!(
+            labels,
+            vec![
+                (0, "first"),
+                (4, "wait.1.loop"),
+                (12, "first.loop"),
+                (16, "second"),
+                (16, "second.loop"),
+                (24, "wait.2.loop"),
+            ]
+        );
+        let words = words(source);
+        assert_eq!(words[2], 0x0004_0112);
+        assert_eq!(words[3], 0x000C_0010);
+        assert_eq!(words[5], 0x0010_0020);
+        assert_eq!(words[7], 0x0018_0112);
+    }
+
+    #[test]
+    fn assemble_conditionals() {
+        let source = "
+            LEVEL equ 2
+            if LEVEL >= 2
+                db 1
+                if LEVEL == 3
+                    db 2
+                else
+                    db 3
+                endif
+            else
+                db 4
+                if 1
+                    db 5
+                endif
+            endif
+            if 0
+            nonsense that is not assembled
+            endif
+        ";
+        assert_eq!(assemble(source).unwrap().content, vec![1, 3]);
+    }
+
+    #[test]
+    fn assemble_repetitions() {
+        let source = "
+            macro fill value
+                rept 3
+                    db value
+                endr
+            endm
+            rept 2
+                fill 7
+                db 0
+            endr
+        ";
+        assert_eq!(
+            assemble(source).unwrap().content,
+            vec![7, 7, 7, 0, 7, 7, 7, 0]
+        );
+    }
+
+    #[test]
+    fn limit_nested_repetitions() {
+        assert_eq!(
+            error("rept 65536\nrept 65536\ndb 0\nendr\nendr"),
+            (3, 1, "the program is larger than 64K".to_string())
+        );
+        assert_eq!(
+            error("rept 65536\nrept 65536\n; nothing\nendr\nendr"),
+            (
+                2,
+                1,
+                "macros and `rept` blocks expand to more than 262144 lines".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn block_errors() {
+        assert_eq!(
+            error("if 1\nnop"),
+            (1, 1, "`if` is not closed by `endif`".to_string())
+        );
+        assert_eq!(error("else"), (1, 1, "`else` without `if`".to_string()));
+        assert_eq!(
+            error("if 1\nelse\nelse\nendif"),
+            (3, 1, "`if` already has an `else`".to_string())
+        );
+        assert_eq!(
+            error("  rept 2\nnop"),
+            (1, 3, "`rept` is not closed by `endr`".to_string())
+        );
+        assert_eq!(error("endm"), (1, 1, "`endm` without `macro`".to_string()));
+        assert_eq!(
+            error("label: if 1\nendif"),
+            (1, 1, "`if` cannot have a label".to_string())
+        );
+        assert_eq!(
+            error("if later\nendif\nlater:"),
+            (1, 4, "`later` is not defined".to_string())
+        );
+        assert_eq!(
+            error("macro add x\nendm"),
+            (
+                1,
+                7,
+                "`add` is an instruction or directive, and cannot be a macro".to_string()
+            )
+        );
+        assert_eq!(
+            error("macro m\nendm\nmacro m\nendm"),
+            (
+                3,
+                7,
+                "macro `m` is already defined at <source>:1".to_string()
+            )
+        );
+        assert_eq!(
+            error("macro m\nm\nendm\nm"),
+            (
+                2,
+                1,
+                "macros and `rept` blocks are nested more than 16 deep".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn macro_errors_show_the_call() {
+        let mut assembler = Assembler::new();
+        let lib = "macro draw x, y\n    drw x, y, sprite\nendm\n";
+        assembler.add_file("lib.s", lib.as_bytes().to_vec());
+        let main = "include lib.s\nstart:\n    draw r1, r2\n    draw r1\n";
+        assembler.add_file("main.s", main.as_bytes().to_vec());
+
+        let errors = assembler.assemble_file("main.s").unwrap_err().0;
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].to_string(),
+            "main.s:4:5: `draw` expects 2 arguments, found 1\n    draw r1\n    ^\n\
+             help: it is written as `draw x, y`"
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "lib.s:2:17: `sprite` is not defined\n    drw r1, r2, sprite\n                ^\n\
+             note: in the expansion of macro `draw` at main.s:3:5\n    draw r1, r2\n    ^"
+        );
+    }
+
+    // Every sample rom must survive a round trip through the disassembler and the assembler.
+    #[test]
+    fn reassemble_sample_roms() {
+        let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms");
+        for entry in fs::read_dir(roms).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "c16") {
+                continue;
+            }
+
+            let rom = Rom::new(File::open(&path).unwrap()).unwrap();
+            let listing = trace(&rom.content, rom.start_address).to_string();
+            let assembly = assemble(&listing).unwrap();
+            assert_eq!(assembly.content, rom.content, "{}", path.display());
+        }
+    }
+}
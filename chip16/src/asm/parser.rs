@@ -0,0 +1,500 @@
+use super::diagnostic::LineError;
+use super::expr::{self, Expr};
+use super::lexer::{Lexer, Token};
+
+// A value with the column it starts at.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Located<T> {
+    pub(crate) value: T,
+    pub(crate) column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Operand {
+    Register(u8),
+    StackPointer,
+    Value(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Data {
+    Value(Expr),
+    String(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Statement {
+    Instruction {
+        mnemonic: Located<String>,
+        operands: Vec<Located<Operand>>,
+    },
+    Equ {
+        name: Located<String>,
+        value: Located<Expr>,
+    },
+    Db(Vec<Located<Data>>),
+    Dw(Vec<Located<Expr>>),
+    Include(Located<String>),
+    ImportBin {
+        path: Located<String>,
+        offset: Option<Located<Expr>>,
+        length: Option<Located<Expr>>,
+    },
+    Macro {
+        name: Located<String>,
+        parameters: Vec<Located<String>>,
+    },
+    EndMacro,
+    Rept(Located<Expr>),
+    EndRept,
+    If(Located<Expr>),
+    Else,
+    EndIf,
+}
+
+// Every line can define a label, followed by an instruction or directive.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Line {
+    pub(crate) label: Option<Located<String>>,
+    pub(crate) statement: Option<Statement>,
+}
+
+// The use of a macro, with the text of each argument.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Call {
+    pub(crate) label: Option<Located<String>>,
+    pub(crate) name: Located<String>,
+    pub(crate) arguments: Vec<Located<String>>,
+}
+
+pub(crate) fn parse_line(text: &str) -> Result<Line, LineError> {
+    let mut lexer = Lexer::new(text);
+    let (label, name) = label_and_name(&mut lexer)?;
+    let mut line = Line {
+        label,
+        statement: None,
+    };
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(line),
+    };
+
+    // Constants are defined by either `name equ value` or `name: equ value`.
+    let statement = if name.value.eq_ignore_ascii_case("equ") {
+        match line.label.take() {
+            Some(constant) => Statement::Equ {
+                name: constant,
+                value: expression(&mut lexer)?,
+            },
+            None => return Err(LineError::new(name.column, "`equ` needs a name")),
+        }
+    } else if is_equ(&lexer.peek()?) {
+        lexer.next()?;
+        Statement::Equ {
+            name,
+            value: expression(&mut lexer)?,
+        }
+    } else {
+        statement(name, &mut lexer)?
+    };
+    line.statement = Some(statement);
+
+    if !lexer.at_end() {
+        return Err(LineError::new(
+            lexer.column(),
+            "expected a `,` or the end of the line",
+        ));
+    }
+    Ok(line)
+}
+
+// The label at the start of a line, even if the rest of the line is not valid.
+pub(crate) fn parse_label(text: &str) -> Option<Located<String>> {
+    let mut lexer = Lexer::new(text);
+    let label = identifier(&mut lexer).ok()??;
+    match lexer.next() {
+        Ok(Some(Token::Colon)) => Some(label),
+        _ => None,
+    }
+}
+
+// The instruction, directive or macro that a line uses, even if the rest of the line is not
+// valid. Constants are defined by `equ`, whichever way they are written.
+pub(crate) fn mnemonic(text: &str) -> Option<Located<String>> {
+    let mut lexer = Lexer::new(text);
+    let (_, name) = label_and_name(&mut lexer).ok()?;
+    let column = lexer.column();
+    match lexer.peek() {
+        Ok(ref token) if is_equ(token) => Some(Located {
+            value: "equ".to_string(),
+            column,
+        }),
+        _ => name,
+    }
+}
+
+// A line that uses a macro. The arguments are kept as text, since they replace the parameters
+// before the lines of the macro are parsed.
+pub(crate) fn parse_call(text: &str) -> Result<Call, LineError> {
+    let mut lexer = Lexer::new(text);
+    let (label, name) = label_and_name(&mut lexer)?;
+    let name =
+        name.ok_or_else(|| LineError::new(lexer.column(), "expected the name of a macro"))?;
+    let arguments = lexer
+        .arguments()?
+        .into_iter()
+        .map(|(value, column)| Located { value, column })
+        .collect();
+    Ok(Call {
+        label,
+        name,
+        arguments,
+    })
+}
+
+// The register named `r0` to `rF`, in either case.
+pub(crate) fn register(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('r'), Some(digit), None) | (Some('R'), Some(digit), None) => {
+            digit.to_digit(16).map(|index| index as u8)
+        }
+        _ => None,
+    }
+}
+
+fn is_equ(token: &Option<Token>) -> bool {
+    match *token {
+        Some(Token::Identifier(ref name)) => name.eq_ignore_ascii_case("equ"),
+        _ => false,
+    }
+}
+
+// The label and the name that follows it, either of which can be missing.
+#[allow(clippy::type_complexity)]
+fn label_and_name(
+    lexer: &mut Lexer,
+) -> Result<(Option<Located<String>>, Option<Located<String>>), LineError> {
+    let name = match identifier(lexer)? {
+        Some(name) => name,
+        None => return Ok((None, None)),
+    };
+    if lexer.peek()? != Some(Token::Colon) {
+        return Ok((None, Some(name)));
+    }
+    lexer.next()?;
+    Ok((Some(name), identifier(lexer)?))
+}
+
+// Returns None at the end of the line.
+fn identifier(lexer: &mut Lexer) -> Result<Option<Located<String>>, LineError> {
+    let column = lexer.column();
+    match lexer.next()? {
+        None => Ok(None),
+        Some(Token::Identifier(value)) => Ok(Some(Located { value, column })),
+        Some(_) => Err(LineError::new(
+            column,
+            "expected a label, instruction or directive",
+        )),
+    }
+}
+
+fn statement(name: Located<String>, lexer: &mut Lexer) -> Result<Statement, LineError> {
+    let statement = match name.value.to_ascii_lowercase().as_str() {
+        "db" => Statement::Db(list(lexer, data)?),
+        "dw" => Statement::Dw(list(lexer, expression)?),
+        "include" => Statement::Include(path(lexer)?),
+        "importbin" => {
+            let path = path(lexer)?;
+            let offset = optional(lexer, expression)?;
+            let length = match offset {
+                Some(_) => optional(lexer, expression)?,
+                None => None,
+            };
+            Statement::ImportBin {
+                path,
+                offset,
+                length,
+            }
+        }
+        "macro" => Statement::Macro {
+            name: name_of(lexer, "expected the name of the macro")?,
+            parameters: if lexer.at_end() {
+                Vec::new()
+            } else {
+                list(lexer, |lexer| {
+                    name_of(lexer, "expected the name of a parameter")
+                })?
+            },
+        },
+        "endm" => Statement::EndMacro,
+        "rept" => Statement::Rept(expression(lexer)?),
+        "endr" => Statement::EndRept,
+        "if" => Statement::If(expression(lexer)?),
+        "else" => Statement::Else,
+        "endif" => Statement::EndIf,
+        _ => Statement::Instruction {
+            mnemonic: name,
+            operands: if lexer.at_end() {
+                Vec::new()
+            } else {
+                list(lexer, operand)?
+            },
+        },
+    };
+    Ok(statement)
+}
+
+// A list of items separated by commas.
+fn list<T, F>(lexer: &mut Lexer, mut item: F) -> Result<Vec<T>, LineError>
+where
+    F: FnMut(&mut Lexer) -> Result<T, LineError>,
+{
+    let mut items = vec![item(lexer)?];
+    while lexer.peek()? == Some(Token::Comma) {
+        lexer.next()?;
+        items.push(item(lexer)?);
+    }
+    Ok(items)
+}
+
+// An item that follows a comma, if there is one.
+fn optional<T, F>(lexer: &mut Lexer, item: F) -> Result<Option<T>, LineError>
+where
+    F: FnOnce(&mut Lexer) -> Result<T, LineError>,
+{
+    if lexer.peek()? != Some(Token::Comma) {
+        return Ok(None);
+    }
+    lexer.next()?;
+    item(lexer).map(Some)
+}
+
+fn name_of(lexer: &mut Lexer, expected: &str) -> Result<Located<String>, LineError> {
+    let column = lexer.column();
+    match lexer.next()? {
+        Some(Token::Identifier(value)) => Ok(Located { value, column }),
+        _ => Err(LineError::new(column, expected)),
+    }
+}
+
+fn expression(lexer: &mut Lexer) -> Result<Located<Expr>, LineError> {
+    let column = lexer.column();
+    let value = expr::parse(lexer)?;
+    Ok(Located { value, column })
+}
+
+fn path(lexer: &mut Lexer) -> Result<Located<String>, LineError> {
+    let column = lexer.column();
+    match lexer.path()? {
+        Some(value) => Ok(Located { value, column }),
+        None => Err(LineError::new(column, "expected a file name")),
+    }
+}
+
+fn data(lexer: &mut Lexer) -> Result<Located<Data>, LineError> {
+    let column = lexer.column();
+    let value = match lexer.peek()? {
+        Some(Token::String(bytes)) => {
+            lexer.next()?;
+            Data::String(bytes)
+        }
+        _ => Data::Value(expr::parse(lexer)?),
+    };
+    Ok(Located { value, column })
+}
+
+fn operand(lexer: &mut Lexer) -> Result<Located<Operand>, LineError> {
+    let column = lexer.column();
+    let value = match lexer.peek()? {
+        Some(Token::Identifier(ref name)) if register(name).is_some() => {
+            lexer.next()?;
+            Operand::Register(register(name).unwrap())
+        }
+        Some(Token::Identifier(ref name)) if name.eq_ignore_ascii_case("sp") => {
+            lexer.next()?;
+            Operand::StackPointer
+        }
+        _ => Operand::Value(expr::parse(lexer)?),
+    };
+    Ok(Located { value, column })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn located<T>(value: T, column: usize) -> Located<T> {
+        Located { value, column }
+    }
+
+    fn name(value: &str, column: usize) -> Located<String> {
+        located(value.to_string(), column)
+    }
+
+    #[test]
+    fn parse_label_and_instruction() {
+        let line = parse_line("loop: ldi sp, 0xFDF0 ; set up the stack").unwrap();
+        assert_eq!(line.label, Some(name("loop", 1)));
+        assert_eq!(
+            line.statement,
+            Some(Statement::Instruction {
+                mnemonic: name("ldi", 7),
+                operands: vec![
+                    located(Operand::StackPointer, 11),
+                    located(Operand::Value(Expr::Number(0xFDF0)), 15),
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_empty_lines() {
+        assert_eq!(parse_line("").unwrap(), Line::default());
+        assert_eq!(parse_line("   ; comment").unwrap(), Line::default());
+
+        let line = parse_line("label:").unwrap();
+        assert_eq!(line.label, Some(name("label", 1)));
+        assert_eq!(line.statement, None);
+    }
+
+    #[test]
+    fn parse_equ() {
+        let expected = Some(Statement::Equ {
+            name: name("SIZE", 1),
+            value: located(Expr::Number(16), 11),
+        });
+        assert_eq!(parse_line("SIZE  equ 16").unwrap().statement, expected);
+        assert_eq!(parse_line("SIZE: EQU 16").unwrap().statement, expected);
+        assert_eq!(parse_line("  equ 16").unwrap_err().column, 3);
+    }
+
+    #[test]
+    fn parse_data() {
+        assert_eq!(
+            parse_line("db \"hi\", 0").unwrap().statement,
+            Some(Statement::Db(vec![
+                located(Data::String(b"hi".to_vec()), 4),
+                located(Data::Value(Expr::Number(0)), 10),
+            ]))
+        );
+        assert_eq!(
+            parse_line("dw 1, 2").unwrap().statement,
+            Some(Statement::Dw(vec![
+                located(Expr::Number(1), 4),
+                located(Expr::Number(2), 7),
+            ]))
+        );
+        assert!(parse_line("db").is_err());
+    }
+
+    #[test]
+    fn parse_files() {
+        assert_eq!(
+            parse_line("include lib/util.s").unwrap().statement,
+            Some(Statement::Include(name("lib/util.s", 9)))
+        );
+        assert_eq!(
+            parse_line("importbin \"font.bin\", 16, 32")
+                .unwrap()
+                .statement,
+            Some(Statement::ImportBin {
+                path: name("font.bin", 11),
+                offset: Some(located(Expr::Number(16), 23)),
+                length: Some(located(Expr::Number(32), 27)),
+            })
+        );
+        assert_eq!(
+            parse_line("importbin font.bin").unwrap().statement,
+            Some(Statement::ImportBin {
+                path: name("font.bin", 11),
+                offset: None,
+                length: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_blocks() {
+        let statement = |text| parse_line(text).unwrap().statement.unwrap();
+        assert_eq!(
+            statement("macro draw x, y"),
+            Statement::Macro {
+                name: name("draw", 7),
+                parameters: vec![name("x", 12), name("y", 15)],
+            }
+        );
+        assert_eq!(
+            statement("MACRO clear"),
+            Statement::Macro {
+                name: name("clear", 7),
+                parameters: vec![],
+            }
+        );
+        assert_eq!(statement("endm"), Statement::EndMacro);
+        assert_eq!(
+            statement("rept 4"),
+            Statement::Rept(located(Expr::Number(4), 6))
+        );
+        assert_eq!(statement("endr"), Statement::EndRept);
+        assert_eq!(
+            statement("if 1"),
+            Statement::If(located(Expr::Number(1), 4))
+        );
+        assert_eq!(statement("else"), Statement::Else);
+        assert_eq!(statement("endif"), Statement::EndIf);
+        assert_eq!(
+            parse_line("macro 5").unwrap_err().message,
+            "expected the name of the macro"
+        );
+    }
+
+    #[test]
+    fn parse_macro_call() {
+        assert_eq!(
+            parse_call("start: draw r1, 2 * (3 + 4) ; comment").unwrap(),
+            Call {
+                label: Some(name("start", 1)),
+                name: name("draw", 8),
+                arguments: vec![name("r1", 13), name("2 * (3 + 4)", 17)],
+            }
+        );
+        assert_eq!(parse_call("clear").unwrap().arguments, vec![]);
+    }
+
+    #[test]
+    fn find_mnemonic() {
+        assert_eq!(mnemonic("loop: if x"), Some(name("if", 7)));
+        assert_eq!(mnemonic("  endm"), Some(name("endm", 3)));
+        assert_eq!(mnemonic("SIZE equ 3"), Some(name("equ", 6)));
+        assert_eq!(mnemonic("label:"), None);
+        assert_eq!(mnemonic("5: nop"), None);
+    }
+
+    #[test]
+    fn parse_label_of_invalid_line() {
+        assert_eq!(parse_label("  loop: add r1 r2"), Some(name("loop", 3)));
+        assert_eq!(parse_label("add r1 r2"), None);
+        assert_eq!(parse_label("5: nop"), None);
+    }
+
+    #[test]
+    fn parse_registers() {
+        assert_eq!(register("r0"), Some(0));
+        assert_eq!(register("RF"), Some(15));
+        assert_eq!(register("rg"), None);
+        assert_eq!(register("r10"), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text| parse_line(text).unwrap_err();
+        assert_eq!(error("add r1 r2").column, 8);
+        assert_eq!(
+            error("add r1 r2").message,
+            "expected a `,` or the end of the line"
+        );
+        assert_eq!(error("5: nop").column, 1);
+        assert_eq!(error("include").message, "expected a file name");
+    }
+}
@@ -0,0 +1,418 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{self, Write};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+// Envelope timings in milliseconds, indexed by the nibbles of SNG.
+#[rustfmt::skip]
+const ATTACK_TIMES: [u32; 16] = [
+    2, 8, 16, 24, 38, 56, 68, 80, 100, 250, 500, 800, 1000, 3000, 5000, 8000,
+];
+
+#[rustfmt::skip]
+const DECAY_RELEASE_TIMES: [u32; 16] = [
+    6, 24, 48, 72, 114, 168, 204, 240, 300, 750, 1500, 2400, 3000, 9000, 15000, 24000,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Triangle,
+    Sawtooth,
+    Pulse,
+    Noise,
+}
+
+impl From<u8> for Waveform {
+    // Only four waveforms are defined, the upper bits of the nibble are ignored.
+    fn from(nibble: u8) -> Waveform {
+        match nibble & 0x3 {
+            0 => Waveform::Triangle,
+            1 => Waveform::Sawtooth,
+            2 => Waveform::Pulse,
+            _ => Waveform::Noise,
+        }
+    }
+}
+
+// The sound generator parameters set by SNG. Each field is the raw 4-bit value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Envelope {
+    pub attack: u8,
+    pub decay: u8,
+    pub sustain: u8,
+    pub release: u8,
+    pub volume: u8,
+    pub waveform: Waveform,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope {
+            attack: 0,
+            decay: 0,
+            sustain: 15,
+            release: 0,
+            volume: 15,
+            waveform: Waveform::Triangle,
+        }
+    }
+}
+
+impl Envelope {
+    // The level of the envelope, between 0 and 1, after `time` milliseconds of a tone that is
+    // held for `duration` milliseconds. Returns None once the release has finished.
+    fn level(&self, time: f32, duration: f32) -> Option<f32> {
+        let attack = ATTACK_TIMES[self.attack as usize % 16] as f32;
+        let decay = DECAY_RELEASE_TIMES[self.decay as usize % 16] as f32;
+        let release = DECAY_RELEASE_TIMES[self.release as usize % 16] as f32;
+        let sustain = self.sustain as f32 / 15.0;
+
+        let held = |time: f32| {
+            if time < attack {
+                time / attack
+            } else if time < attack + decay {
+                1.0 - (1.0 - sustain) * (time - attack) / decay
+            } else {
+                sustain
+            }
+        };
+
+        let level = if time < duration {
+            held(time)
+        } else if time < duration + release {
+            held(duration) * (1.0 - (time - duration) / release)
+        } else {
+            return None;
+        };
+
+        Some(level * self.volume as f32 / 15.0)
+    }
+}
+
+struct Tone {
+    envelope: Envelope,
+    frequency: f32,
+    duration: f32,
+    elapsed: u32,
+    phase: f32,
+}
+
+pub struct Audio {
+    pub envelope: Envelope,
+    sample_rate: u32,
+    tone: Option<Tone>,
+    noise: u16,
+    noise_sample: f32,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Audio {
+    pub fn new(sample_rate: u32) -> Audio {
+        Audio {
+            envelope: Envelope::default(),
+            sample_rate,
+            tone: None,
+            noise: 0xACE1,
+            noise_sample: 0.0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.tone.is_some()
+    }
+
+    // Starts a tone shaped by the envelope set by SNG, replacing any tone that is still playing.
+    pub fn play(&mut self, frequency: u16, duration: u16) {
+        let envelope = self.envelope;
+        self.play_with(envelope, frequency, duration);
+    }
+
+    // Starts one of the fixed SND1-SND3 tones. These always use the default envelope, so a rom
+    // that calls SNG for its SNP sounds does not change its beeps.
+    pub fn play_fixed(&mut self, frequency: u16, duration: u16) {
+        self.play_with(Envelope::default(), frequency, duration);
+    }
+
+    // The envelope is copied into the tone, so SNG only affects tones started after it.
+    fn play_with(&mut self, envelope: Envelope, frequency: u16, duration: u16) {
+        self.tone = Some(Tone {
+            envelope,
+            frequency: frequency as f32,
+            duration: duration as f32,
+            elapsed: 0,
+            phase: 0.0,
+        });
+    }
+
+    pub fn stop(&mut self) {
+        self.tone = None;
+    }
+
+    // Renders mono samples between -1 and 1. Silence is rendered once the tone has finished.
+    pub fn render(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+
+    pub fn render_i16(&mut self, buffer: &mut [i16]) {
+        for sample in buffer.iter_mut() {
+            *sample = (self.next_sample() * i16::MAX as f32) as i16;
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let sample_rate = self.sample_rate as f32;
+        let (envelope, level, phase, wrapped) = match self.tone {
+            Some(ref mut tone) => {
+                let time = tone.elapsed as f32 * 1000.0 / sample_rate;
+                let level = tone.envelope.level(time, tone.duration);
+
+                let phase = tone.phase;
+                tone.phase += tone.frequency / sample_rate;
+                let wrapped = tone.phase >= 1.0;
+                tone.phase -= tone.phase.floor();
+                tone.elapsed += 1;
+
+                (tone.envelope, level, phase, wrapped)
+            }
+            None => return 0.0,
+        };
+
+        let level = match level {
+            Some(level) => level,
+            None => {
+                self.tone = None;
+                return 0.0;
+            }
+        };
+
+        let sample = match envelope.waveform {
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Pulse if phase < 0.5 => 1.0,
+            Waveform::Pulse => -1.0,
+            Waveform::Noise => {
+                if wrapped {
+                    self.step_noise();
+                }
+                self.noise_sample
+            }
+        };
+
+        sample * level
+    }
+
+    // A 16-bit linear feedback shift register, so noise is the same on every run.
+    fn step_noise(&mut self) {
+        let bit = (self.noise ^ (self.noise >> 2) ^ (self.noise >> 3) ^ (self.noise >> 5)) & 1;
+        self.noise = (self.noise >> 1) | (bit << 15);
+        self.noise_sample = self.noise as f32 / 32767.5 - 1.0;
+    }
+}
+
+// Writes mono 16-bit PCM samples as a WAV file.
+pub fn write_wav<W: Write>(mut writer: W, sample_rate: u32, samples: &[i16]) -> io::Result<()> {
+    let data_size = samples.len() as u32 * 2;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_u32::<LittleEndian>(36 + data_size)?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_u32::<LittleEndian>(16)?;
+    writer.write_u16::<LittleEndian>(1)?;
+    writer.write_u16::<LittleEndian>(1)?;
+    writer.write_u32::<LittleEndian>(sample_rate)?;
+    writer.write_u32::<LittleEndian>(sample_rate * 2)?;
+    writer.write_u16::<LittleEndian>(2)?;
+    writer.write_u16::<LittleEndian>(16)?;
+    writer.write_all(b"data")?;
+    writer.write_u32::<LittleEndian>(data_size)?;
+    for &sample in samples {
+        writer.write_i16::<LittleEndian>(sample)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(audio: &mut Audio, length: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0; length];
+        audio.render(&mut buffer);
+        buffer
+    }
+
+    fn pulse() -> Audio {
+        let mut audio = Audio::new(8_000);
+        audio.envelope.waveform = Waveform::Pulse;
+        audio
+    }
+
+    #[test]
+    fn silent_when_idle() {
+        let mut audio = Audio::default();
+        assert!(render(&mut audio, 64).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn tone_duration_includes_release() {
+        let mut audio = pulse();
+        audio.play(500, 10);
+
+        // 10ms of tone and 6ms of release at 8kHz.
+        let samples = render(&mut audio, 200);
+        assert!(samples[..127].iter().any(|&sample| sample != 0.0));
+        assert!(samples[128..].iter().all(|&sample| sample == 0.0));
+        assert!(!audio.is_playing());
+    }
+
+    #[test]
+    fn pulse_frequency() {
+        let mut audio = pulse();
+        audio.play(1000, 100);
+
+        // Skip the attack, after which one period is exactly 8 samples.
+        let samples = render(&mut audio, 64);
+        let period = &samples[32..40];
+        assert!(period[..4].iter().all(|&sample| sample == 1.0));
+        assert!(period[4..].iter().all(|&sample| sample == -1.0));
+        assert_eq!(&samples[40..48], period);
+    }
+
+    #[test]
+    fn attack_ramps_up() {
+        let mut audio = pulse();
+        audio.envelope.attack = 1;
+        audio.play(500, 100);
+
+        // An 8ms attack is 64 samples long.
+        let samples = render(&mut audio, 80);
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[32], 0.5);
+        assert_eq!(samples[64], 1.0);
+    }
+
+    #[test]
+    fn volume_and_sustain() {
+        let mut audio = pulse();
+        audio.envelope.volume = 6;
+        audio.envelope.sustain = 5;
+        audio.play(500, 100);
+
+        // After a 2ms attack and 6ms decay the level is (5 / 15) * (6 / 15).
+        let samples = render(&mut audio, 128);
+        assert!((samples[80] - 2.0 / 15.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stop_is_immediate() {
+        let mut audio = pulse();
+        audio.play(500, 1000);
+        render(&mut audio, 10);
+
+        audio.stop();
+        assert!(render(&mut audio, 10).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn noise_is_deterministic() {
+        let mut a = Audio::new(8_000);
+        let mut b = Audio::new(8_000);
+        a.envelope.waveform = Waveform::Noise;
+        b.envelope.waveform = Waveform::Noise;
+        a.play(1000, 50);
+        b.play(1000, 50);
+
+        let samples = render(&mut a, 400);
+        assert_eq!(samples, render(&mut b, 400));
+        assert!(samples.iter().any(|&sample| sample > 0.0));
+        assert!(samples.iter().any(|&sample| sample < 0.0));
+    }
+
+    #[test]
+    fn fixed_tones_ignore_the_envelope() {
+        let mut audio = Audio::new(8_000);
+        audio.envelope = Envelope {
+            attack: 15,
+            volume: 1,
+            waveform: Waveform::Noise,
+            ..Envelope::default()
+        };
+        audio.play_fixed(1000, 100);
+
+        let mut fixed = Audio::new(8_000);
+        fixed.play(1000, 100);
+        assert_eq!(render(&mut audio, 400), render(&mut fixed, 400));
+    }
+
+    #[test]
+    fn envelope_is_kept_by_a_playing_tone() {
+        let mut audio = pulse();
+        audio.play(1000, 100);
+        audio.envelope.volume = 0;
+
+        let samples = render(&mut audio, 40);
+        assert_eq!(samples[32], 1.0);
+    }
+
+    #[test]
+    fn render_i16() {
+        let mut audio = pulse();
+        audio.play(1000, 100);
+
+        let mut buffer = [0; 40];
+        audio.render_i16(&mut buffer);
+        assert_eq!(buffer[32], i16::MAX);
+        assert_eq!(buffer[36], -i16::MAX);
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut wav = Vec::new();
+        write_wav(&mut wav, 8_000, &[0, 1, -1]).unwrap();
+
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[24..28], &[0x40, 0x1F, 0x00, 0x00]);
+        assert_eq!(&wav[40..44], &[6, 0, 0, 0]);
+        assert_eq!(&wav[44..], &[0, 0, 1, 0, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn wav_bytes() {
+        let mut wav = Vec::new();
+        write_wav(&mut wav, 44_100, &[0x1234, -2, i16::MIN, i16::MAX]).unwrap();
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            b'R', b'I', b'F', b'F', 44, 0, 0, 0,
+            b'W', b'A', b'V', b'E', b'f', b'm', b't', b' ',
+            16, 0, 0, 0, // fmt chunk size
+            1, 0, // PCM
+            1, 0, // mono
+            0x44, 0xAC, 0x00, 0x00, // 44100 samples per second
+            0x88, 0x58, 0x01, 0x00, // 88200 bytes per second
+            2, 0, // block alignment
+            16, 0, // bits per sample
+            b'd', b'a', b't', b'a', 8, 0, 0, 0,
+            0x34, 0x12, 0xFE, 0xFF, 0x00, 0x80, 0xFF, 0x7F,
+        ];
+        assert_eq!(wav, expected);
+    }
+}
@@ -0,0 +1,96 @@
+extern crate chip16;
+#[macro_use]
+extern crate failure;
+
+use chip16::{Assembler, Assembly, Rom, RomFormat, Symbols, Version};
+use failure::{err_msg, Error};
+use std::env;
+use std::fs::File;
+use std::process;
+
+const USAGE: &str =
+    "usage: c16asm <source> <output> [--raw] [--version <major.minor>] [--start <label | address>]";
+
+const DEFAULT_VERSION: Version = Version(1, 1);
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let source = args.next().ok_or_else(|| err_msg(USAGE))?;
+    let output = args.next().ok_or_else(|| err_msg(USAGE))?;
+
+    let mut format = RomFormat::Chip16;
+    let mut version = None;
+    let mut start = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--raw" => format = RomFormat::Raw,
+            "--version" => {
+                let value = args.next().ok_or_else(|| err_msg(USAGE))?;
+                version = Some(value.parse()?);
+            }
+            "--start" => start = Some(args.next().ok_or_else(|| err_msg(USAGE))?),
+            _ => return Err(err_msg(USAGE)),
+        }
+    }
+
+    let assembly = match Assembler::new().assemble_file(&source) {
+        Ok(assembly) => assembly,
+        Err(errors) => {
+            for error in &errors.0 {
+                eprintln!("error: {}\n", error);
+            }
+            let count = errors.0.len();
+            let plural = if count == 1 { "" } else { "s" };
+            bail!(
+                "{} could not be assembled, {} error{}",
+                source,
+                count,
+                plural
+            );
+        }
+    };
+    let start_address = match start {
+        Some(start) => parse_start(&assembly, &start)?,
+        None => 0,
+    };
+
+    let mut rom = Rom::from_content(assembly.content);
+    if format == RomFormat::Raw {
+        ensure!(
+            version.is_none() && start_address == 0,
+            "raw roms cannot have a version or start address"
+        );
+    } else {
+        rom.format = format;
+        rom.version = Some(version.unwrap_or(DEFAULT_VERSION));
+        rom.start_address = start_address;
+    }
+    rom.save(&output)?;
+
+    // The symbols are written next to the rom, where the disassembler and debugger find them. They
+    // are only written once the rom is, so they never describe a different rom.
+    assembly
+        .symbols
+        .write(File::create(Symbols::sidecar_path(&output))?)
+}
+
+// The start address is either a label, or a number in decimal or hexadecimal with `0x`.
+fn parse_start(assembly: &Assembly, start: &str) -> Result<u16, Error> {
+    if let Some(address) = assembly.symbols.address_of(start) {
+        return Ok(address);
+    }
+
+    let address = if let Some(hex) = start.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        start.parse().ok()
+    };
+    address.ok_or_else(|| format_err!("`{}` is not a label or address", start))
+}
@@ -0,0 +1,49 @@
+extern crate chip16;
+#[macro_use]
+extern crate failure;
+
+use chip16::{Console, Cpu, Debugger, ProfilePolicy, Rom, RomLoadOptions, Symbols};
+use failure::{err_msg, Error};
+use std::env;
+use std::fs::File;
+use std::io;
+use std::process;
+
+const USAGE: &str = "usage: c16dbg <rom>";
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let filename = args.next().ok_or_else(|| err_msg(USAGE))?;
+    ensure!(args.next().is_none(), USAGE);
+
+    let rom = Rom::with_options(File::open(&filename)?, RomLoadOptions::lenient())?;
+    if !rom.has_valid_checksum() {
+        eprintln!("warning: the rom checksum is invalid");
+    }
+    // Roms are loaded the same way as by the emulator, so opcodes outside of the rom's profile
+    // are reported instead of stopping it.
+    let mut cpu = Cpu::new();
+    cpu.load(&rom)?;
+    cpu.profile_policy = ProfilePolicy::Warn;
+    let mut debugger = Debugger::new(cpu);
+    debugger.symbols = Symbols::load_sidecar(&filename)?.unwrap_or_default();
+
+    println!(
+        "{}, {} bytes. Type `help` for the commands.",
+        filename,
+        rom.content.len()
+    );
+    let mut console = Console::new(debugger);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    console.show_position(&mut stdout)?;
+    console.run(stdin.lock(), &mut stdout)
+}
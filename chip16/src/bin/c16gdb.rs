@@ -0,0 +1,51 @@
+extern crate chip16;
+extern crate failure;
+
+use chip16::{Cpu, Debugger, GdbStub, ProfilePolicy, Rom, RomLoadOptions, Symbols};
+use failure::{err_msg, Error};
+use std::env;
+use std::fs::File;
+use std::net::TcpListener;
+use std::process;
+
+const USAGE: &str = "usage: c16gdb <rom> [--port <port>]";
+
+const DEFAULT_PORT: u16 = 1616;
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let filename = args.next().ok_or_else(|| err_msg(USAGE))?;
+    let mut port = DEFAULT_PORT;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().ok_or_else(|| err_msg(USAGE))?.parse()?,
+            _ => return Err(err_msg(USAGE)),
+        }
+    }
+
+    let rom = Rom::with_options(File::open(&filename)?, RomLoadOptions::lenient())?;
+    if !rom.has_valid_checksum() {
+        eprintln!("warning: the rom checksum is invalid");
+    }
+    // Roms are loaded the same way as by the emulator, so opcodes outside of the rom's profile
+    // do not stop it.
+    let mut cpu = Cpu::new();
+    cpu.load(&rom)?;
+    cpu.profile_policy = ProfilePolicy::Warn;
+    let mut debugger = Debugger::new(cpu);
+    debugger.symbols = Symbols::load_sidecar(&filename)?.unwrap_or_default();
+
+    // Only connections from this machine are accepted, as the stub can read and write anything.
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("waiting for gdb on {}", listener.local_addr()?);
+    let (stream, address) = listener.accept()?;
+    println!("gdb connected from {}", address);
+    GdbStub::new(debugger).serve(stream)
+}
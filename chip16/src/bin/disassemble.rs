@@ -2,24 +2,77 @@ extern crate byteorder;
 extern crate chip16;
 
 use byteorder::{LittleEndian, ReadBytesExt};
-use chip16::{Instruction, Operation, Rom};
+use chip16::{
+    disassemble_or_data, disassemble_with_labels, trace_with_symbols, Instruction, Rom,
+    RomLoadOptions, Symbols,
+};
 use std::env;
 use std::fs::File;
 
+const USAGE: &str = "usage: disassemble <rom> [--linear]";
+
 fn main() {
-    let filename = env::args().nth(1).unwrap();
-    let file = File::open(filename).unwrap();
-    let rom = Rom::new(file).unwrap();
+    let mut args = env::args().skip(1);
+    let filename = args.next().expect(USAGE);
+    let linear = match args.next() {
+        Some(ref arg) if arg == "--linear" => true,
+        Some(_) => panic!("{}", USAGE),
+        None => false,
+    };
+
+    let file = File::open(&filename).unwrap();
+    let rom = Rom::with_options(file, RomLoadOptions::lenient()).unwrap();
+    if !rom.has_valid_checksum() {
+        eprintln!("warning: the rom checksum is invalid");
+    }
+
+    // The assembler writes the symbols next to the rom, but most roms do not have any. Broken
+    // symbols only lose the labels, so the rom is still disassembled.
+    let symbols = match Symbols::load_sidecar(&filename) {
+        Ok(symbols) => symbols.unwrap_or_default(),
+        Err(error) => {
+            eprintln!("warning: {}, the symbols are ignored", error);
+            Symbols::new()
+        }
+    };
+
+    if linear {
+        print_linear(&rom, &symbols);
+    } else {
+        print!(
+            "{}",
+            trace_with_symbols(&rom.content, rom.start_address, symbols)
+        );
+    }
+}
 
+// Disassembles every word of the rom, including any sprites and palettes.
+fn print_linear(rom: &Rom, symbols: &Symbols) {
+    // Roms are always loaded at 0x0000, the start address is only where execution begins.
     let mut program = &rom.content[..];
-    let mut address = rom.start_address;
+    let mut address = 0u16;
     while let Ok(data) = program.read_u32::<LittleEndian>() {
+        if let Some(label) = symbols.label(address) {
+            println!("{}:", label);
+        }
+
         let instruction = Instruction::new(data);
-        if let Some(operation) = instruction.decode_operation() {
-            println!("{:04x}: {:08x} {:?}", address, data, operation);
-        } else {
-            println!("{:04x}: {:08x}", address, data);
+        let text = disassemble_with_labels(&instruction, |target| {
+            symbols.label(target).map(String::from)
+        })
+        .unwrap_or_else(|| disassemble_or_data(&instruction));
+        match symbols.source(address) {
+            Some(source) => println!(
+                "{:<24}; {:04x}: {:08x} {}:{}",
+                text, address, data, source.path, source.line
+            ),
+            None => println!("{:<24}; {:04x}: {:08x}", text, address, data),
         }
-        address += 4;
+        address = address.wrapping_add(4);
+    }
+
+    // Roms do not have to be a multiple of four bytes long.
+    for byte in program {
+        println!("db 0x{:02X}", byte);
     }
 }
@@ -0,0 +1,90 @@
+extern crate chip16;
+#[macro_use]
+extern crate failure;
+
+use chip16::{Rom, RomFormat, RomLoadOptions, Version};
+use failure::{err_msg, Error};
+use std::env;
+use std::fs::File;
+use std::process;
+
+const USAGE: &str = "usage: romtool <input> <output> [--raw | --chip16] [--version <major.minor>] [--start <address>]";
+
+// Raw roms are converted to chip16 roms by default, and chip16 roms stay chip16 roms.
+const DEFAULT_VERSION: Version = Version(1, 1);
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let input = args.next().ok_or_else(|| err_msg(USAGE))?;
+    let output = args.next().ok_or_else(|| err_msg(USAGE))?;
+
+    let mut rom = Rom::with_options(File::open(input)?, RomLoadOptions::lenient())?;
+    if !rom.has_valid_checksum() {
+        eprintln!("warning: the rom checksum is invalid, it will be rewritten");
+    }
+
+    let mut format = RomFormat::Chip16;
+    let mut version = None;
+    let mut start_address = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--raw" => format = RomFormat::Raw,
+            "--chip16" => format = RomFormat::Chip16,
+            "--version" => {
+                let value = args.next().ok_or_else(|| err_msg(USAGE))?;
+                version = Some(value.parse()?);
+            }
+            "--start" => {
+                let value = args.next().ok_or_else(|| err_msg(USAGE))?;
+                start_address = Some(parse_address(&value)?);
+            }
+            _ => return Err(err_msg(USAGE)),
+        }
+    }
+
+    if format == RomFormat::Raw {
+        ensure_raw(&rom, version, start_address)?;
+        rom.version = None;
+    } else {
+        rom.version = version.or(rom.version).or(Some(DEFAULT_VERSION));
+        rom.start_address = start_address.unwrap_or(rom.start_address);
+    }
+
+    rom.format = format;
+    rom.size = rom.content.len() as u32;
+    rom.save(output)
+}
+
+// Raw roms have no header, so they always start at 0x0000 and have no version.
+fn ensure_raw(
+    rom: &Rom,
+    version: Option<Version>,
+    start_address: Option<u16>,
+) -> Result<(), Error> {
+    ensure!(
+        version.is_none() && start_address.is_none(),
+        "raw roms cannot have a version or start address"
+    );
+    ensure!(
+        rom.start_address == 0,
+        "the rom starts at {:04x}, but raw roms must start at 0000",
+        rom.start_address
+    );
+    Ok(())
+}
+
+fn parse_address(value: &str) -> Result<u16, Error> {
+    let address = if let Some(hex) = value.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)?
+    } else {
+        value.parse()?
+    };
+    Ok(address)
+}
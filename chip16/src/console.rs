@@ -0,0 +1,694 @@
+use cpu::{Cpu, CLOCK_RATE};
+use debugger::{Comparison, Debugger, RegisterCondition, Stop, WatchKind, Watchpoint};
+use disasm::{disassemble_or_data, disassemble_with_labels};
+use error::EmulationError;
+use failure::Error;
+use instruction::Instruction;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+break <address> [if <condition>]  stop before the instruction at an address
+break if <condition>              stop wherever a condition becomes true, such as `r1 == 5`
+break                             list the breakpoints, watchpoints and conditions
+watch [read | write | access | execute] <address> [<length>]
+                                  stop when memory is used, writes by default
+delete <address>                  delete the breakpoints and watchpoints at an address
+delete if <condition>             delete a condition
+delete                            delete everything
+step [<count>]                    execute instructions, following calls
+next                              execute an instruction, running calls to their return
+finish                            run until the current routine returns
+continue [<seconds>]              run until something stops the cpu, or for 10 seconds
+regs                              show the registers
+flags                             show the flags
+mem <address> <length>            show memory
+disas [<address>] [<count>]       disassemble instructions, from the program counter by default
+set <register> <value>            set r0 to rF, pc or sp
+poke <address> <byte>...          write bytes to memory
+quit
+
+Addresses and values are labels, numbers, or hexadecimal with `0x`. An empty line repeats the
+last command.";
+
+// How long the cpu runs before the debugger takes back control, in seconds of emulated time. A rom
+// that is still running by then has most likely hung, and it stops wherever it is looping.
+const RUN_SECONDS: u64 = 10;
+
+// The number of instructions that `disas` shows by default.
+const DISASSEMBLY_LENGTH: u16 = 8;
+
+// The command line of `c16dbg`. Commands are read one per line, so a script can be used as well
+// as a terminal.
+pub struct Console {
+    pub debugger: Debugger,
+}
+
+impl Console {
+    pub fn new(debugger: Debugger) -> Console {
+        Console { debugger }
+    }
+
+    // Prompts for and runs commands until `quit` or the end of the input. Errors in a command are
+    // written to the output, only failing to read or write stops the console.
+    pub fn run<R: BufRead, W: Write>(&mut self, mut input: R, output: &mut W) -> Result<(), Error> {
+        let mut last = String::new();
+        loop {
+            write!(output, "(c16dbg) ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+            if !line.trim().is_empty() {
+                last = line.trim().to_string();
+            }
+            match self.command(&last, output) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(error) => writeln!(output, "error: {}", error)?,
+            }
+        }
+    }
+
+    // Runs a command, and returns false if it was `quit`.
+    pub fn command<W: Write>(&mut self, line: &str, output: &mut W) -> Result<bool, Error> {
+        let debugger = &mut self.debugger;
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(true),
+        };
+        let args: Vec<&str> = words.collect();
+        let limit = RUN_SECONDS * CLOCK_RATE as u64;
+
+        match name {
+            "break" | "b" => breakpoint(output, debugger, &args)?,
+            "watch" | "w" => watch(debugger, &args)?,
+            "delete" | "d" => delete(debugger, &args)?,
+            "step" | "s" => {
+                let count = match args.first() {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                let mut stop = Ok(Stop::Step);
+                for _ in 0..count {
+                    stop = debugger.step_into();
+                    if stop != Ok(Stop::Step) {
+                        break;
+                    }
+                }
+                report(output, debugger, stop)?;
+            }
+            "next" | "n" => {
+                let stop = debugger.step_over(limit);
+                report(output, debugger, stop)?;
+            }
+            "finish" | "f" => {
+                let stop = debugger.step_out(limit);
+                report(output, debugger, stop)?;
+            }
+            "continue" | "c" => {
+                let limit = match args.first() {
+                    Some(seconds) => u64::from(parse_number(seconds)?) * CLOCK_RATE as u64,
+                    None => limit,
+                };
+                let stop = debugger.run(limit);
+                report(output, debugger, stop)?;
+            }
+            "regs" | "r" => show_registers(output, &debugger.cpu)?,
+            "flags" => show_flags(output, &debugger.cpu)?,
+            "mem" | "m" => {
+                ensure!(args.len() == 2, "it is written as `mem <address> <length>`");
+                let address = parse_value(debugger, args[0])?;
+                let length = parse_number(args[1])?;
+                show_memory(output, &debugger.cpu, address, length)?;
+            }
+            "disas" => {
+                ensure!(
+                    args.len() <= 2,
+                    "it is written as `disas [<address>] [<count>]`"
+                );
+                let address = match args.first() {
+                    Some(address) => parse_value(debugger, address)?,
+                    None => debugger.cpu.program_counter,
+                };
+                let count = match args.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => DISASSEMBLY_LENGTH,
+                };
+                for index in 0..count {
+                    let address = address.wrapping_add(index.wrapping_mul(4));
+                    show_instruction(output, debugger, address)?;
+                }
+            }
+            "set" => {
+                ensure!(args.len() == 2, "it is written as `set <register> <value>`");
+                let value = parse_value(debugger, args[1])?;
+                let cpu = &mut debugger.cpu;
+                match args[0].to_lowercase().as_str() {
+                    "pc" => cpu.program_counter = value,
+                    "sp" => cpu.stack_pointer = value,
+                    register => *cpu.registers.get_mut(parse_register(register)?) = value,
+                }
+            }
+            "poke" => {
+                ensure!(
+                    args.len() >= 2,
+                    "it is written as `poke <address> <byte>...`"
+                );
+                let address = parse_value(debugger, args[0])?;
+                let bytes = args[1..]
+                    .iter()
+                    .map(|byte| {
+                        let value = parse_value(debugger, byte)?;
+                        ensure!(value <= 0xFF, "`{}` does not fit in a byte", byte);
+                        Ok(value as u8)
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                debugger
+                    .cpu
+                    .memory
+                    .write_slice(address, &bytes)
+                    .ok_or_else(|| format_err!("the bytes run past the end of memory"))?;
+            }
+            "help" | "h" | "?" => writeln!(output, "{}", HELP)?,
+            "quit" | "q" => return Ok(false),
+            _ => bail!("unknown command `{}`, try `help`", name),
+        }
+        Ok(true)
+    }
+
+    pub fn show_position<W: Write>(&self, output: &mut W) -> Result<(), Error> {
+        show_instruction(output, &self.debugger, self.debugger.cpu.program_counter)
+    }
+}
+
+fn breakpoint<W: Write>(
+    output: &mut W,
+    debugger: &mut Debugger,
+    args: &[&str],
+) -> Result<(), Error> {
+    match args.split_first() {
+        None => show_breakpoints(output, debugger)?,
+        Some((&"if", condition)) => {
+            let condition = parse_condition(debugger, condition)?;
+            debugger.add_condition(condition);
+        }
+        Some((address, rest)) => {
+            let address = parse_value(debugger, address)?;
+            match rest.split_first() {
+                None => debugger.add_breakpoint(address),
+                Some((&"if", condition)) => {
+                    let condition = parse_condition(debugger, condition)?;
+                    debugger.add_conditional_breakpoint(address, condition);
+                }
+                Some(_) => bail!("it is written as `break <address> [if <condition>]`"),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn watch(debugger: &mut Debugger, args: &[&str]) -> Result<(), Error> {
+    let (kind, args) = match args.split_first() {
+        Some((&"read", rest)) => (WatchKind::Read, rest),
+        Some((&"write", rest)) => (WatchKind::Write, rest),
+        Some((&"access", rest)) => (WatchKind::Access, rest),
+        Some((&"execute", rest)) => (WatchKind::Execute, rest),
+        _ => (WatchKind::Write, args),
+    };
+    let (start, length) = match *args {
+        [address] => (parse_value(debugger, address)?, 1),
+        [address, length] => (parse_value(debugger, address)?, parse_number(length)?),
+        _ => {
+            bail!("it is written as `watch [read | write | access | execute] <address> [<length>]`")
+        }
+    };
+    ensure!(length > 0, "a watchpoint must watch at least one byte");
+    let end = start
+        .checked_add(length - 1)
+        .ok_or_else(|| format_err!("the watchpoint runs past the end of memory"))?;
+    debugger.add_watchpoint(Watchpoint { kind, start, end });
+    Ok(())
+}
+
+fn delete(debugger: &mut Debugger, args: &[&str]) -> Result<(), Error> {
+    match args.split_first() {
+        None => {
+            let breakpoints: Vec<_> = debugger.breakpoints().map(|(address, _)| address).collect();
+            for address in breakpoints {
+                debugger.remove_breakpoint(address);
+            }
+            for watchpoint in debugger.watchpoints().to_vec() {
+                debugger.remove_watchpoint(&watchpoint);
+            }
+            for condition in debugger.conditions().to_vec() {
+                debugger.remove_condition(&condition);
+            }
+        }
+        Some((&"if", condition)) => {
+            let condition = parse_condition(debugger, condition)?;
+            ensure!(
+                debugger.remove_condition(&condition),
+                "there is no condition `{}`",
+                condition
+            );
+        }
+        Some((address, [])) => {
+            let address = parse_value(debugger, address)?;
+            let mut deleted = debugger.remove_breakpoint(address);
+            let watchpoints: Vec<_> = debugger
+                .watchpoints()
+                .iter()
+                .filter(|watchpoint| watchpoint.contains(address))
+                .cloned()
+                .collect();
+            for watchpoint in watchpoints {
+                deleted |= debugger.remove_watchpoint(&watchpoint);
+            }
+            ensure!(
+                deleted,
+                "there is no breakpoint or watchpoint at {}",
+                debugger.describe(address)
+            );
+        }
+        Some(_) => bail!("it is written as `delete [<address> | if <condition>]`"),
+    }
+    Ok(())
+}
+
+// Writes why the cpu stopped, any opcodes it executed outside of its profile, and the instruction
+// that it stopped at.
+fn report<W: Write>(
+    output: &mut W,
+    debugger: &mut Debugger,
+    stop: Result<Stop, EmulationError>,
+) -> Result<(), Error> {
+    for warning in debugger.cpu.take_warnings() {
+        writeln!(output, "warning: {}", warning)?;
+    }
+    match stop {
+        Ok(Stop::Step) => {}
+        Ok(Stop::Breakpoint(address)) => {
+            writeln!(output, "breakpoint at {}", debugger.describe(address))?
+        }
+        Ok(Stop::Watchpoint {
+            watchpoint,
+            address,
+        }) => writeln!(
+            output,
+            "{} watchpoint on {} ({:04x})",
+            watchpoint.kind,
+            debugger.describe(address),
+            address
+        )?,
+        Ok(Stop::Condition(condition)) => writeln!(output, "{} is now true", condition)?,
+        Ok(Stop::CycleLimit) => writeln!(output, "still running when the time ran out")?,
+        Err(error) => writeln!(output, "error: {}", error)?,
+    }
+    show_instruction(output, debugger, debugger.cpu.program_counter)
+}
+
+// Disassembles the instruction at an address, marking it if the program counter is there.
+fn show_instruction<W: Write>(
+    output: &mut W,
+    debugger: &Debugger,
+    address: u16,
+) -> Result<(), Error> {
+    let symbols = &debugger.symbols;
+    if let Some(label) = symbols.label(address) {
+        writeln!(output, "{}:", label)?;
+    }
+    let word = match debugger.cpu.memory.read_u32(address) {
+        Some(word) => word,
+        None => {
+            writeln!(output, "   {:04x}  past the end of memory", address)?;
+            return Ok(());
+        }
+    };
+    let instruction = Instruction::new(word);
+    let text = disassemble_with_labels(&instruction, |target| {
+        symbols.label(target).map(String::from)
+    })
+    .unwrap_or_else(|| disassemble_or_data(&instruction));
+    let marker = if address == debugger.cpu.program_counter {
+        "=>"
+    } else {
+        "  "
+    };
+    match debugger.source(address) {
+        Some(source) => writeln!(
+            output,
+            "{} {:04x}  {:<24}; {:08x} {}:{}",
+            marker, address, text, word, source.path, source.line
+        )?,
+        None => writeln!(
+            output,
+            "{} {:04x}  {:<24}; {:08x}",
+            marker, address, text, word
+        )?,
+    }
+    Ok(())
+}
+
+fn show_registers<W: Write>(output: &mut W, cpu: &Cpu) -> Result<(), Error> {
+    for row in 0..4u8 {
+        let registers: Vec<_> = (row * 4..row * 4 + 4)
+            .map(|register| format!("r{:X} {:04x}", register, cpu.registers.get(register)))
+            .collect();
+        writeln!(output, "{}", registers.join("  "))?;
+    }
+    writeln!(
+        output,
+        "pc {:04x}  sp {:04x}  cycles {}",
+        cpu.program_counter, cpu.stack_pointer, cpu.cycles
+    )?;
+    Ok(())
+}
+
+fn show_flags<W: Write>(output: &mut W, cpu: &Cpu) -> Result<(), Error> {
+    let flags = &cpu.flags;
+    writeln!(
+        output,
+        "carry {}  zero {}  overflow {}  negative {}",
+        flags.carry as u8, flags.zero as u8, flags.overflow as u8, flags.negative as u8
+    )?;
+    Ok(())
+}
+
+// Shows sixteen bytes on each line, with their characters where they are printable.
+fn show_memory<W: Write>(
+    output: &mut W,
+    cpu: &Cpu,
+    address: u16,
+    length: u16,
+) -> Result<(), Error> {
+    let end = (address as usize + length as usize).min(0x10000);
+    for line in (address as usize..end).step_by(16) {
+        let bytes: Vec<u8> = (line..end.min(line + 16))
+            .filter_map(|index| cpu.memory.read_u8(index))
+            .collect();
+        let hex: Vec<_> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(output, "{:04x}  {:<47}  {}", line, hex.join(" "), text)?;
+    }
+    Ok(())
+}
+
+fn show_breakpoints<W: Write>(output: &mut W, debugger: &Debugger) -> Result<(), Error> {
+    for (address, condition) in debugger.breakpoints() {
+        match condition {
+            Some(condition) => writeln!(
+                output,
+                "break {:04x} {} if {}",
+                address,
+                debugger.describe(address),
+                condition
+            )?,
+            None => writeln!(
+                output,
+                "break {:04x} {}",
+                address,
+                debugger.describe(address)
+            )?,
+        }
+    }
+    for watchpoint in debugger.watchpoints() {
+        writeln!(
+            output,
+            "watch {} {:04x}-{:04x} {}",
+            watchpoint.kind,
+            watchpoint.start,
+            watchpoint.end,
+            debugger.describe(watchpoint.start)
+        )?;
+    }
+    for condition in debugger.conditions() {
+        writeln!(output, "break if {}", condition)?;
+    }
+    Ok(())
+}
+
+// A number in decimal or hexadecimal with `0x`.
+fn parse_number(text: &str) -> Result<u16, Error> {
+    let number = if let Some(hex) = text.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    };
+    number.ok_or_else(|| format_err!("`{}` is not a number", text))
+}
+
+// A label or a number.
+fn parse_value(debugger: &Debugger, text: &str) -> Result<u16, Error> {
+    if let Some(address) = debugger.symbols.address_of(text) {
+        return Ok(address);
+    }
+    parse_number(text).map_err(|_| format_err!("`{}` is not a label or number", text))
+}
+
+fn parse_register(text: &str) -> Result<u8, Error> {
+    let register = text
+        .strip_prefix('r')
+        .or_else(|| text.strip_prefix('R'))
+        .and_then(|number| u8::from_str_radix(number, 16).ok())
+        .filter(|&register| register < 16);
+    register.ok_or_else(|| format_err!("`{}` is not a register, they are r0 to rF", text))
+}
+
+// A condition such as `r1 == 5`, which can be written with or without spaces.
+fn parse_condition(debugger: &Debugger, words: &[&str]) -> Result<RegisterCondition, Error> {
+    let text = words.concat();
+    let comparisons = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessEqual),
+        (">=", Comparison::GreaterEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+    let (index, symbol, comparison) = comparisons
+        .iter()
+        .find_map(|&(symbol, comparison)| {
+            text.find(symbol).map(|index| (index, symbol, comparison))
+        })
+        .ok_or_else(|| format_err!("expected a condition such as `r1 == 5`"))?;
+    Ok(RegisterCondition {
+        register: parse_register(&text[..index])?,
+        comparison,
+        value: parse_value(debugger, &text[index + symbol.len()..])?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+    use profile::{ExecutionProfile, ProfilePolicy};
+    use rom::Rom;
+
+    const PROGRAM: &str = "
+        start:   ldi r0, 0
+                 call add_one
+                 call add_one
+                 stm r0, result
+        done:    jmp done
+        add_one: addi r0, 1
+                 ret
+        result:  dw 0
+    ";
+
+    fn console(source: &str) -> Console {
+        let assembly = assemble(source).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.load(&Rom::from_content(assembly.content)).unwrap();
+        let mut debugger = Debugger::new(cpu);
+        debugger.symbols = assembly.symbols;
+        Console::new(debugger)
+    }
+
+    // Runs a script, and returns what the console wrote without its prompts.
+    fn script(console: &mut Console, input: &str) -> String {
+        let mut output = Vec::new();
+        console.run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap().replace("(c16dbg) ", "")
+    }
+
+    fn error(console: &mut Console, line: &str) -> String {
+        console
+            .command(line, &mut Vec::new())
+            .unwrap_err()
+            .to_string()
+    }
+
+    fn condition(register: u8, comparison: Comparison, value: u16) -> RegisterCondition {
+        RegisterCondition {
+            register,
+            comparison,
+            value,
+        }
+    }
+
+    #[test]
+    fn parse_values() {
+        let debugger = console(PROGRAM).debugger;
+        assert_eq!(parse_value(&debugger, "add_one").unwrap(), 0x0014);
+        assert_eq!(parse_value(&debugger, "0x1F").unwrap(), 0x001F);
+        assert_eq!(parse_value(&debugger, "31").unwrap(), 31);
+        assert_eq!(
+            parse_value(&debugger, "nowhere").unwrap_err().to_string(),
+            "`nowhere` is not a label or number"
+        );
+        assert!(parse_number("0x10000").is_err());
+        assert_eq!(parse_register("RA").unwrap(), 0xA);
+        assert!(parse_register("r16").is_err());
+    }
+
+    #[test]
+    fn parse_conditions() {
+        let debugger = console(PROGRAM).debugger;
+        assert_eq!(
+            parse_condition(&debugger, &["r1", "==", "5"]).unwrap(),
+            condition(1, Comparison::Equal, 5)
+        );
+        assert_eq!(
+            parse_condition(&debugger, &["rF<=result"]).unwrap(),
+            condition(0xF, Comparison::LessEqual, 0x001C)
+        );
+        assert_eq!(
+            parse_condition(&debugger, &["r2", ">", "0x10"]).unwrap(),
+            condition(2, Comparison::Greater, 0x10)
+        );
+        assert_eq!(
+            parse_condition(&debugger, &["r1", "=", "5"])
+                .unwrap_err()
+                .to_string(),
+            "expected a condition such as `r1 == 5`"
+        );
+        assert_eq!(
+            parse_condition(&debugger, &["rG", "==", "1"])
+                .unwrap_err()
+                .to_string(),
+            "`rG` is not a register, they are r0 to rF"
+        );
+    }
+
+    #[test]
+    fn stop_at_breakpoints() {
+        let mut console = console(PROGRAM);
+        let output = script(
+            &mut console,
+            "break add_one\ncontinue\nregs\n\nbreak\nquit\n",
+        );
+        assert_eq!(
+            output,
+            "breakpoint at add_one\n\
+             add_one:\n\
+             => 0014  addi r0, 0x0001         ; 00010040 <source>:7\n\
+             r0 0000  r1 0000  r2 0000  r3 0000\n\
+             r4 0000  r5 0000  r6 0000  r7 0000\n\
+             r8 0000  r9 0000  rA 0000  rB 0000\n\
+             rC 0000  rD 0000  rE 0000  rF 0000\n\
+             pc 0014  sp fdf2  cycles 2\n\
+             r0 0000  r1 0000  r2 0000  r3 0000\n\
+             r4 0000  r5 0000  r6 0000  r7 0000\n\
+             r8 0000  r9 0000  rA 0000  rB 0000\n\
+             rC 0000  rD 0000  rE 0000  rF 0000\n\
+             pc 0014  sp fdf2  cycles 2\n\
+             break 0014 add_one\n"
+        );
+    }
+
+    #[test]
+    fn watch_and_delete() {
+        let mut console = console(PROGRAM);
+        let output = script(&mut console, "watch result 2\ncontinue\nmem result 2\n");
+        assert!(output.starts_with("write watchpoint on result (001c)\n"));
+        assert_eq!(
+            output,
+            "write watchpoint on result (001c)\n\
+             done:\n\
+             => 0010  jmp done                ; 00100010 <source>:6\n\
+             001c  02 00                                            ..\n\n"
+        );
+
+        assert_eq!(
+            error(&mut console, "watch result 0"),
+            "a watchpoint must watch at least one byte"
+        );
+        assert_eq!(
+            error(&mut console, "watch 0xFFFF 2"),
+            "the watchpoint runs past the end of memory"
+        );
+        assert_eq!(
+            error(&mut console, "watch write result 2 3"),
+            "it is written as `watch [read | write | access | execute] <address> [<length>]`"
+        );
+
+        // A watchpoint is deleted by any address that it covers.
+        console
+            .command("watch read 0x100 4", &mut Vec::new())
+            .unwrap();
+        console.command("delete 0x102", &mut Vec::new()).unwrap();
+        assert_eq!(console.debugger.watchpoints().len(), 1);
+        assert_eq!(
+            error(&mut console, "delete 0x102"),
+            "there is no breakpoint or watchpoint at result+230"
+        );
+
+        console
+            .command("break if r0 == 2", &mut Vec::new())
+            .unwrap();
+        assert_eq!(
+            error(&mut console, "delete if r0 == 3"),
+            "there is no condition `r0 == 0x0003`"
+        );
+        console.command("delete if r0==2", &mut Vec::new()).unwrap();
+        assert!(console.debugger.conditions().is_empty());
+
+        console.command("break start", &mut Vec::new()).unwrap();
+        console.command("delete", &mut Vec::new()).unwrap();
+        assert_eq!(console.debugger.breakpoints().count(), 0);
+        assert!(console.debugger.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn errors_do_not_stop_the_script() {
+        let mut console = console(PROGRAM);
+        let output = script(&mut console, "jump\nset r1 0x1234\nset rG 1\nregs\n");
+        assert!(output.starts_with(
+            "error: unknown command `jump`, try `help`\n\
+             error: `rg` is not a register, they are r0 to rF\n\
+             r0 0000  r1 1234"
+        ));
+        // The end of the input quits.
+        assert!(output.ends_with("cycles 0\n\n"));
+    }
+
+    #[test]
+    fn report_unsupported_opcodes() {
+        let mut console = console("muli r0, 2\nmuli r1, 3\n");
+        console.debugger.cpu.profile = Some(ExecutionProfile::V1_0);
+        console.debugger.cpu.profile_policy = ProfilePolicy::Warn;
+
+        // Each opcode is only reported once.
+        let output = script(&mut console, "step\nstep\n");
+        assert_eq!(
+            output
+                .lines()
+                .filter(|line| line.starts_with("warning"))
+                .collect::<Vec<_>>(),
+            ["warning: 00020090 at 0000 is not defined by the 1.0 specification"]
+        );
+    }
+}
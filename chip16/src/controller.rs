@@ -0,0 +1,77 @@
+// Controllers are mapped into I/O memory, one 16-bit port each.
+pub const CONTROLLER_ONE_ADDRESS: u16 = 0xFFF0;
+pub const CONTROLLER_TWO_ADDRESS: u16 = 0xFFF2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Port {
+    One,
+    Two,
+}
+
+impl Port {
+    pub fn address(self) -> u16 {
+        match self {
+            Port::One => CONTROLLER_ONE_ADDRESS,
+            Port::Two => CONTROLLER_TWO_ADDRESS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Controller {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub select: bool,
+    pub start: bool,
+    pub a: bool,
+    pub b: bool,
+}
+
+impl From<u8> for Controller {
+    fn from(byte: u8) -> Controller {
+        Controller {
+            up: byte & 0x01 != 0,
+            down: byte & 0x02 != 0,
+            left: byte & 0x04 != 0,
+            right: byte & 0x08 != 0,
+            select: byte & 0x10 != 0,
+            start: byte & 0x20 != 0,
+            a: byte & 0x40 != 0,
+            b: byte & 0x80 != 0,
+        }
+    }
+}
+
+impl From<Controller> for u8 {
+    fn from(controller: Controller) -> u8 {
+        (controller.up as u8)
+            | (controller.down as u8) << 1
+            | (controller.left as u8) << 2
+            | (controller.right as u8) << 3
+            | (controller.select as u8) << 4
+            | (controller.start as u8) << 5
+            | (controller.a as u8) << 6
+            | (controller.b as u8) << 7
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_layout() {
+        let controller = Controller {
+            up: true,
+            right: true,
+            start: true,
+            b: true,
+            ..Controller::default()
+        };
+        assert_eq!(u8::from(controller), 0b1010_1001);
+        assert_eq!(Controller::from(0b1010_1001), controller);
+        assert_eq!(u8::from(Controller::from(0xFF)), 0xFF);
+    }
+}
@@ -1,49 +1,190 @@
+use audio::{Audio, Envelope, Waveform};
+use controller::{Controller, Port};
+use error::{EmulationError, Fault};
+use failure::Error;
 use flags::Flags;
 use graphics::Graphics;
-use instruction::{Condition, Instruction, Operation};
+use instruction::Condition::*;
+use instruction::{Condition, DecodedInstruction, Instruction, Operation};
 use memory::Memory;
+use palette::PALETTE_DATA_SIZE;
+use profile::{ExecutionProfile, ProfilePolicy};
 use rand::{thread_rng, Rng, ThreadRng};
-use register::{Register, RegisterFile};
+use register::RegisterFile;
+use rom::Rom;
+use std::collections::HashSet;
+use std::mem;
+
+// The stack is 512 bytes long and grows upwards towards the I/O ports.
+pub const STACK_START: u16 = 0xFDF0;
+pub const STACK_END: u16 = 0xFFF0;
+
+// Each instruction takes the cycles listed in the instruction set table, of the 1 MHz clock.
+pub const CLOCK_RATE: u32 = 1_000_000;
+pub const FRAME_RATE: u32 = 60;
+pub const CYCLES_PER_FRAME: u32 = CLOCK_RATE / FRAME_RATE;
+
+#[derive(Debug, PartialEq)]
+pub struct FrameStats {
+    pub cycles: u32,
+    // True if the rom executed VBLNK and stalled until the end of the frame.
+    pub waited: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StepOutcome {
+    Continue,
+    // The rom executed VBLNK, so nothing more should run until the next frame.
+    WaitVblnk,
+}
+
+// Calls the handler of each instruction, with the operands named in the instruction set table.
+macro_rules! dispatch {
+    ($(
+        $opcode:literal => $operation:ident $variant:ident { $($operand:ident),* } $handler:ident
+        $mnemonic:literal $format:ident $version:ident $flags:literal $cycles:literal;
+    )*) => {
+        impl Cpu {
+            fn dispatch(&mut self, decoded: DecodedInstruction) -> Result<(), Fault> {
+                match decoded {
+                    $(DecodedInstruction::$variant { $($operand),* } => self.$handler($($operand),*),)*
+                }
+            }
+        }
+    };
+}
+
+isa!(dispatch);
 
 pub struct Cpu {
     pub memory: Memory,
     pub graphics: Graphics,
+    pub audio: Audio,
     pub registers: RegisterFile,
     pub program_counter: u16,
     pub stack_pointer: u16,
     pub flags: Flags,
+    // The cycles executed since the cpu was reset.
+    pub cycles: u64,
+
+    // Opcodes outside of the profile are handled by the policy. Without a profile, every opcode
+    // is executed, which is the case for raw roms since they do not declare a version.
+    pub profile: Option<ExecutionProfile>,
+    pub profile_policy: ProfilePolicy,
 
     wait_vblnk: bool,
+    controllers: [Controller; 2],
+    warnings: Vec<EmulationError>,
+    warned_opcodes: HashSet<u8>,
 
     rng: ThreadRng,
 }
 
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
+    }
+}
+
 impl Cpu {
     pub fn new() -> Cpu {
         Cpu {
             memory: Memory::new(),
             graphics: Graphics::new(),
+            audio: Audio::default(),
             registers: RegisterFile::new(),
             program_counter: 0,
-            stack_pointer: 0,
+            stack_pointer: STACK_START,
             flags: Flags::new(),
+            cycles: 0,
+
+            profile: None,
+            profile_policy: ProfilePolicy::default(),
 
             wait_vblnk: false,
+            controllers: [Controller::default(); 2],
+            warnings: Vec::new(),
+            warned_opcodes: HashSet::new(),
 
             rng: thread_rng(),
         }
     }
 
-    pub fn step(&mut self) {
-        let data = self.memory.read_u32(self.program_counter);
-        let instruction = Instruction::new(data);
+    pub fn reset(&mut self) {
+        self.memory.clear();
+        self.graphics = Graphics::new();
+        self.audio = Audio::new(self.audio.sample_rate());
+        self.registers.reset();
+        self.program_counter = 0;
+        self.stack_pointer = STACK_START;
+        self.flags = Flags::new();
+        self.cycles = 0;
+
+        self.wait_vblnk = false;
+        self.controllers = [Controller::default(); 2];
+        self.warnings.clear();
+        self.warned_opcodes.clear();
+    }
+
+    // Resets the cpu and copies the rom into memory at 0x0000. Roms are not allowed to overlap
+    // the stack or the I/O ports. The profile is chosen from the version the rom declares.
+    pub fn load(&mut self, rom: &Rom) -> Result<(), Error> {
+        ensure!(
+            rom.content.len() <= STACK_START as usize,
+            "the rom is {} bytes, which overlaps the stack and I/O ports at {:04x}",
+            rom.content.len(),
+            STACK_START
+        );
+        ensure!(
+            (rom.start_address as usize) < rom.content.len(),
+            "the start address {:04x} is outside of the rom",
+            rom.start_address
+        );
+
+        self.reset();
+        self.memory.write_slice(0u16, &rom.content).unwrap();
+        self.program_counter = rom.start_address;
+        self.profile = rom.version.map(ExecutionProfile::from);
+        Ok(())
+    }
+
+    // The state is only visible to the rom once it reaches VBLNK, as on the real hardware.
+    pub fn set_controller(&mut self, port: Port, controller: Controller) {
+        self.controllers[port as usize] = controller;
+    }
+
+    // Runs until the rom waits for vblank or the frame's cycles are used up. Each call begins a
+    // new frame, so a rom that is waiting for vblank continues from where it stalled.
+    pub fn run_frame(&mut self) -> Result<FrameStats, EmulationError> {
+        self.wait_vblnk = false;
+
+        let start = self.cycles;
+        while self.cycles - start < CYCLES_PER_FRAME as u64 && !self.wait_vblnk {
+            self.step()?;
+        }
+
+        Ok(FrameStats {
+            cycles: (self.cycles - start) as u32,
+            waited: self.wait_vblnk,
+        })
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome, EmulationError> {
+        let pc = self.program_counter;
+        let word = self
+            .memory
+            .read_u32(pc)
+            .ok_or(EmulationError::PcOverflow { pc })?;
 
-        self.program_counter.checked_add(4).unwrap();
+        self.execute(Instruction::new(word))
+    }
 
-        self.execute(instruction);
+    // Warnings are only recorded with `ProfilePolicy::Warn`, once for each opcode.
+    pub fn take_warnings(&mut self) -> Vec<EmulationError> {
+        mem::take(&mut self.warnings)
     }
 
-    pub fn test(&mut self, condition: Condition) -> bool {
+    pub fn test(&self, condition: Condition) -> bool {
         match condition {
             Z => self.flags.zero,
             NZ => !self.flags.zero,
@@ -63,158 +204,1369 @@ impl Cpu {
         }
     }
 
-    pub fn execute(&mut self, instruction: Instruction) {
-        let operation = instruction.decode_operation().unwrap();
+    fn push_u16(&mut self, value: u16) -> Result<(), Fault> {
+        if self.stack_pointer < STACK_START || self.stack_pointer > STACK_END - 2 {
+            return Err(Fault::StackOverflow);
+        }
+
+        self.write_u16(self.stack_pointer, value)?;
+        self.stack_pointer += 2;
+        Ok(())
+    }
+
+    fn pop_u16(&mut self) -> Result<u16, Fault> {
+        if self.stack_pointer < STACK_START + 2 || self.stack_pointer > STACK_END {
+            return Err(Fault::StackUnderflow);
+        }
+
+        self.stack_pointer -= 2;
+        self.read_u16(self.stack_pointer)
+    }
+
+    fn read_u16(&self, address: u16) -> Result<u16, Fault> {
+        self.memory
+            .read_u16(address)
+            .ok_or(Fault::MemoryOutOfBounds(address))
+    }
+
+    fn write_u16(&mut self, address: u16, value: u16) -> Result<(), Fault> {
+        self.memory
+            .write_u16(address, value)
+            .ok_or(Fault::MemoryOutOfBounds(address))
+    }
+
+    // Executes the instruction as though it was fetched from the current program counter.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, EmulationError> {
+        let pc = self.program_counter;
+        let word = instruction.0;
+
+        let decoded = instruction
+            .decode()
+            .ok_or(EmulationError::InvalidOpcode { pc, word })?;
+        let operation = decoded.operation();
+
+        if let Some(profile) = self.profile {
+            if !profile.supports(&operation) {
+                let error = EmulationError::UnsupportedOpcode { pc, word, profile };
+                match self.profile_policy {
+                    ProfilePolicy::Strict => return Err(error),
+                    ProfilePolicy::Warn => {
+                        if self.warned_opcodes.insert(instruction.ii()) {
+                            self.warnings.push(error);
+                        }
+                    }
+                }
+            }
+        }
 
-        let execution = match operation {
-            NOP => Cpu::nop,
-            CLS => Cpu::cls,
-            VBLNK => Cpu::vblnk,
-            SPR => Cpu::spr,
+        self.program_counter = pc.checked_add(4).ok_or(EmulationError::PcOverflow { pc })?;
+
+        let result = self.dispatch(decoded);
+
+        let outcome = if operation == Operation::VBLNK {
+            StepOutcome::WaitVblnk
+        } else {
+            StepOutcome::Continue
         };
 
-        execution(self, instruction);
+        // A faulting instruction leaves the program counter on itself, like an invalid opcode does,
+        // so that the error and a debugger both point at it.
+        if let Err(fault) = result {
+            self.program_counter = pc;
+            return Err(fault.into_error(pc, word, self.stack_pointer));
+        }
+        self.cycles += operation.cycles() as u64;
+
+        Ok(outcome)
     }
 
-    fn nop(&mut self, instruction: Instruction) {}
+    fn nop(&mut self) -> Result<(), Fault> {
+        Ok(())
+    }
 
-    fn cls(&mut self, instruction: Instruction) {
+    fn cls(&mut self) -> Result<(), Fault> {
         self.graphics.clear();
+        Ok(())
     }
 
-    fn vblnk(&mut self, instruction: Instruction) {
-        // TODO: Implement for maze.c16.
+    fn vblnk(&mut self) -> Result<(), Fault> {
         self.wait_vblnk = true;
+
+        for &port in &[Port::One, Port::Two] {
+            let controller = u8::from(self.controllers[port as usize]);
+            self.write_u16(port.address(), controller as u16)?;
+        }
+        Ok(())
+    }
+
+    fn bgc(&mut self, n: u8) -> Result<(), Fault> {
+        self.graphics.background_layer = n;
+        Ok(())
+    }
+
+    fn spr(&mut self, width: u8, height: u8) -> Result<(), Fault> {
+        self.graphics.sprite_width = width;
+        self.graphics.sprite_height = height;
+        Ok(())
     }
 
-    fn bgc(&mut self, instruction: Instruction) {}
+    fn drwi(&mut self, x: u8, y: u8, addr: u16) -> Result<(), Fault> {
+        self.draw(x, y, addr)
+    }
 
-    fn spr(&mut self, instruction: Instruction) {
-        self.graphics.sprite_width = instruction.ll();
-        self.graphics.sprite_height = instruction.hh();
+    fn drwr(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let z = *self.registers.get(z);
+        self.draw(x, y, z)
     }
 
-    fn drwi(&mut self, instruction: Instruction) {
-        // let sprite_data = self.memory.0c
-        // self.graphics.draw(instruction.x(), instruction.y(), )
+    // NOTE: This is NOT an cpu operation, but it is used by drwi and drwr.
+    fn draw(&mut self, x: u8, y: u8, address: u16) -> Result<(), Fault> {
+        let x = *self.registers.get(x) as i16;
+        let y = *self.registers.get(y) as i16;
+        let sprite_data = self
+            .memory
+            .read_slice(address, self.graphics.sprite_size())
+            .ok_or(Fault::MemoryOutOfBounds(address))?;
+
+        self.flags.carry = self.graphics.draw_sprite(x, y, sprite_data);
+        Ok(())
     }
 
-    fn drwr(&mut self, instruction: Instruction) {}
+    fn rnd(&mut self, x: u8, max: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.rng.gen_range(0, max as u32 + 1) as u16;
+        Ok(())
+    }
 
-    fn rnd(&mut self, instruction: Instruction) {
-        let x = self.registers.get_mut(instruction.x());
-        *x = self.rng.gen_range(0, instruction.hhll());
+    fn flip(&mut self, horizontal: bool, vertical: bool) -> Result<(), Fault> {
+        self.graphics.horizontal_flip = horizontal;
+        self.graphics.vertical_flip = vertical;
+        Ok(())
     }
 
-    fn flip(&mut self, instruction: Instruction) {}
-    fn snd0(&mut self, instruction: Instruction) {}
-    fn snd1(&mut self, instruction: Instruction) {}
-    fn snd2(&mut self, instruction: Instruction) {}
-    fn snd3(&mut self, instruction: Instruction) {}
-    fn snp(&mut self, instruction: Instruction) {}
-    fn sng(&mut self, instruction: Instruction) {}
+    fn snd0(&mut self) -> Result<(), Fault> {
+        self.audio.stop();
+        Ok(())
+    }
 
-    fn jmpi(&mut self, instruction: Instruction) {
-        self.program_counter = instruction.hhll();
+    fn snd1(&mut self, duration: u16) -> Result<(), Fault> {
+        self.audio.play_fixed(500, duration);
+        Ok(())
     }
 
-    fn jmc(&mut self, instruction: Instruction) {}
+    fn snd2(&mut self, duration: u16) -> Result<(), Fault> {
+        self.audio.play_fixed(1000, duration);
+        Ok(())
+    }
 
-    fn jx(&mut self, instruction: Instruction) {
-        let condition = instruction.decode_condition().unwrap();
-        if self.test(condition) {
-            self.program_counter = instruction.hhll();
+    fn snd3(&mut self, duration: u16) -> Result<(), Fault> {
+        self.audio.play_fixed(1500, duration);
+        Ok(())
+    }
+
+    fn snp(&mut self, x: u8, duration: u16) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let frequency = self.read_u16(x)?;
+        self.audio.play(frequency, duration);
+        Ok(())
+    }
+
+    fn sng(&mut self, ad: u8, vtsr: u16) -> Result<(), Fault> {
+        self.audio.envelope = Envelope {
+            attack: ad >> 4,
+            decay: ad & 0xF,
+            sustain: (vtsr >> 4) as u8 & 0xF,
+            release: vtsr as u8 & 0xF,
+            volume: (vtsr >> 12) as u8,
+            waveform: Waveform::from((vtsr >> 8) as u8),
+        };
+        Ok(())
+    }
+
+    fn jmpi(&mut self, addr: u16) -> Result<(), Fault> {
+        self.program_counter = addr;
+        Ok(())
+    }
+
+    // JMC was replaced by JX in later versions of the specification, it is the same as JX B.
+    fn jmc(&mut self, addr: u16) -> Result<(), Fault> {
+        if self.flags.carry {
+            self.program_counter = addr;
         }
+        Ok(())
     }
 
-    fn jme(&mut self, instruction: Instruction) {
-        if self.registers.get(instruction.x()) == self.registers.get(instruction.y()) {
-            self.program_counter = instruction.hhll();
+    fn jx(&mut self, cond: Condition, addr: u16) -> Result<(), Fault> {
+        if self.test(cond) {
+            self.program_counter = addr;
         }
+        Ok(())
     }
 
-    fn calli(&mut self, instruction: Instruction) {}
-    fn ret(&mut self, instruction: Instruction) {}
-    fn jmpr(&mut self, instruction: Instruction) {}
-    fn cx(&mut self, instruction: Instruction) {}
-    fn callr(&mut self, instruction: Instruction) {}
+    fn jme(&mut self, x: u8, y: u8, addr: u16) -> Result<(), Fault> {
+        if self.registers.get(x) == self.registers.get(y) {
+            self.program_counter = addr;
+        }
+        Ok(())
+    }
 
-    fn ldir(&mut self, instruction: Instruction) {
-        let x = self.registers.get_mut(instruction.x());
-        *x = instruction.hhll();
+    fn calli(&mut self, addr: u16) -> Result<(), Fault> {
+        let program_counter = self.program_counter;
+        self.push_u16(program_counter)?;
+        self.program_counter = addr;
+        Ok(())
     }
 
-    fn ldis(&mut self, instruction: Instruction) {}
-    fn ldmi(&mut self, instruction: Instruction) {}
-    fn ldmr(&mut self, instruction: Instruction) {}
-    fn mov(&mut self, instruction: Instruction) {}
-    fn stmi(&mut self, instruction: Instruction) {}
-    fn stmr(&mut self, instruction: Instruction) {}
+    fn ret(&mut self) -> Result<(), Fault> {
+        self.program_counter = self.pop_u16()?;
+        Ok(())
+    }
+
+    fn jmpr(&mut self, x: u8) -> Result<(), Fault> {
+        self.program_counter = *self.registers.get(x);
+        Ok(())
+    }
+
+    fn cx(&mut self, cond: Condition, addr: u16) -> Result<(), Fault> {
+        if self.test(cond) {
+            let program_counter = self.program_counter;
+            self.push_u16(program_counter)?;
+            self.program_counter = addr;
+        }
+        Ok(())
+    }
+
+    fn callr(&mut self, x: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let program_counter = self.program_counter;
+        self.push_u16(program_counter)?;
+        self.program_counter = x;
+        Ok(())
+    }
+
+    fn ldir(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = imm;
+        Ok(())
+    }
+
+    fn ldis(&mut self, imm: u16) -> Result<(), Fault> {
+        self.stack_pointer = imm;
+        Ok(())
+    }
+
+    fn ldmi(&mut self, x: u8, addr: u16) -> Result<(), Fault> {
+        let value = self.read_u16(addr)?;
+        *self.registers.get_mut(x) = value;
+        Ok(())
+    }
+
+    fn ldmr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let value = self.read_u16(y)?;
+        *self.registers.get_mut(x) = value;
+        Ok(())
+    }
 
-    fn addi(&mut self, instruction: Instruction) {
-        let x = self.registers.get_mut(instruction.x());
-        *x = self.flags.add(*x, instruction.hhll());
+    fn mov(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        *self.registers.get_mut(x) = y;
+        Ok(())
     }
 
-    fn addr2(&mut self, instruction: Instruction) {
-        let y = *self.registers.get_mut(instruction.y());
-        let x = self.registers.get_mut(instruction.x());
+    fn stmi(&mut self, x: u8, addr: u16) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        self.write_u16(addr, x)?;
+        Ok(())
+    }
+
+    fn stmr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        self.write_u16(y, x)?;
+        Ok(())
+    }
+
+    fn addi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.add(*x, imm);
+        Ok(())
+    }
+
+    fn addr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
         *x = self.flags.add(*x, y);
+        Ok(())
     }
 
-    fn addr3(&mut self, instruction: Instruction) {
-        let x = *self.registers.get_mut(instruction.x());
-        let y = *self.registers.get_mut(instruction.y());
-        let z = self.registers.get_mut(instruction.z());
+    fn addr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
         *z = self.flags.add(x, y);
+        Ok(())
+    }
+
+    fn subi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.sub(*x, imm);
+        Ok(())
+    }
+
+    fn subr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.sub(*x, y);
+        Ok(())
+    }
+
+    fn subr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.sub(x, y);
+        Ok(())
+    }
+
+    fn cmpi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        self.flags.sub(x, imm);
+        Ok(())
+    }
+
+    fn cmpr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        self.flags.sub(x, y);
+        Ok(())
+    }
+
+    fn andi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.and(*x, imm);
+        Ok(())
+    }
+
+    fn andr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.and(*x, y);
+        Ok(())
+    }
+
+    fn andr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.and(x, y);
+        Ok(())
+    }
+
+    fn tsti(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        self.flags.and(x, imm);
+        Ok(())
+    }
+
+    fn tstr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        self.flags.and(x, y);
+        Ok(())
+    }
+
+    fn ori(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.or(*x, imm);
+        Ok(())
+    }
+
+    fn orr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.or(*x, y);
+        Ok(())
+    }
+
+    fn orr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.or(x, y);
+        Ok(())
+    }
+
+    fn xori(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.xor(*x, imm);
+        Ok(())
+    }
+
+    fn xorr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.xor(*x, y);
+        Ok(())
+    }
+
+    fn xorr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.xor(x, y);
+        Ok(())
+    }
+
+    fn muli(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.mul(*x, imm);
+        Ok(())
+    }
+
+    fn mulr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.mul(*x, y);
+        Ok(())
+    }
+
+    fn mulr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.mul(x, y);
+        Ok(())
+    }
+
+    fn divi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.div(*x, imm).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn divr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.div(*x, y).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn divr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.div(x, y).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn modi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.modulo(*x, imm).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn modr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.modulo(*x, y).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn modr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.modulo(x, y).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn remi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.rem(*x, imm).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn remr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.rem(*x, y).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn remr3(&mut self, x: u8, y: u8, z: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        let y = *self.registers.get(y);
+        let z = self.registers.get_mut(z);
+        *z = self.flags.rem(x, y).ok_or(Fault::DivideByZero)?;
+        Ok(())
+    }
+
+    fn shln(&mut self, x: u8, n: u8) -> Result<(), Fault> {
+        let n = n as u16;
+        let x = self.registers.get_mut(x);
+        *x = self.flags.shl(*x, n);
+        Ok(())
+    }
+
+    fn shrn(&mut self, x: u8, n: u8) -> Result<(), Fault> {
+        let n = n as u16;
+        let x = self.registers.get_mut(x);
+        *x = self.flags.shr(*x, n);
+        Ok(())
+    }
+
+    fn sarn(&mut self, x: u8, n: u8) -> Result<(), Fault> {
+        let n = n as u16;
+        let x = self.registers.get_mut(x);
+        *x = self.flags.sar(*x, n);
+        Ok(())
+    }
+
+    fn shlr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.shl(*x, y);
+        Ok(())
+    }
+
+    fn shrr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.shr(*x, y);
+        Ok(())
+    }
+
+    fn sarr(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.sar(*x, y);
+        Ok(())
+    }
+
+    fn push(&mut self, x: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        self.push_u16(x)?;
+        Ok(())
+    }
+
+    fn pop(&mut self, x: u8) -> Result<(), Fault> {
+        let value = self.pop_u16()?;
+        *self.registers.get_mut(x) = value;
+        Ok(())
+    }
+
+    fn pushall(&mut self) -> Result<(), Fault> {
+        for index in 0..16u8 {
+            let value = *self.registers.get(index);
+            self.push_u16(value)?;
+        }
+        Ok(())
+    }
+
+    fn popall(&mut self) -> Result<(), Fault> {
+        for index in (0..16u8).rev() {
+            let value = self.pop_u16()?;
+            *self.registers.get_mut(index) = value;
+        }
+        Ok(())
+    }
+
+    fn pushf(&mut self) -> Result<(), Fault> {
+        let flags = u8::from(&self.flags);
+        self.push_u16(flags as u16)?;
+        Ok(())
     }
 
-    fn subi(&mut self, instruction: Instruction) {}
-    fn subr2(&mut self, instruction: Instruction) {}
-    fn subr3(&mut self, instruction: Instruction) {}
-    fn cmpi(&mut self, instruction: Instruction) {}
-    fn cmpr(&mut self, instruction: Instruction) {}
-    fn andi(&mut self, instruction: Instruction) {}
-    fn andr2(&mut self, instruction: Instruction) {}
-    fn andr3(&mut self, instruction: Instruction) {}
-    fn tsti(&mut self, instruction: Instruction) {}
-    fn tstr(&mut self, instruction: Instruction) {}
-    fn ori(&mut self, instruction: Instruction) {}
-    fn orr2(&mut self, instruction: Instruction) {}
-    fn orr3(&mut self, instruction: Instruction) {}
-    fn xori(&mut self, instruction: Instruction) {}
-    fn xorr2(&mut self, instruction: Instruction) {}
-    fn xorr3(&mut self, instruction: Instruction) {}
-    fn muli(&mut self, instruction: Instruction) {}
-    fn mulr2(&mut self, instruction: Instruction) {}
-    fn mulr3(&mut self, instruction: Instruction) {}
-    fn divi(&mut self, instruction: Instruction) {}
-    fn divr2(&mut self, instruction: Instruction) {}
-    fn divr3(&mut self, instruction: Instruction) {}
-    fn modi(&mut self, instruction: Instruction) {}
-    fn modr2(&mut self, instruction: Instruction) {}
-    fn modr3(&mut self, instruction: Instruction) {}
-    fn remi(&mut self, instruction: Instruction) {}
-    fn remr2(&mut self, instruction: Instruction) {}
-    fn remr3(&mut self, instruction: Instruction) {}
-    fn shln(&mut self, instruction: Instruction) {}
-    fn shrn(&mut self, instruction: Instruction) {}
-    fn sarn(&mut self, instruction: Instruction) {}
-    fn shlr(&mut self, instruction: Instruction) {}
-    fn shrr(&mut self, instruction: Instruction) {}
-    fn sarr(&mut self, instruction: Instruction) {}
-    fn push(&mut self, instruction: Instruction) {}
-    fn pop(&mut self, instruction: Instruction) {}
-    fn pushall(&mut self, instruction: Instruction) {}
-    fn popall(&mut self, instruction: Instruction) {}
-    fn pushf(&mut self, instruction: Instruction) {}
-    fn popf(&mut self, instruction: Instruction) {}
-    fn pali(&mut self, instruction: Instruction) {}
-    fn palr(&mut self, instruction: Instruction) {}
-    fn noti(&mut self, instruction: Instruction) {}
-    fn notr1(&mut self, instruction: Instruction) {}
-    fn notr2(&mut self, instruction: Instruction) {}
-    fn negi(&mut self, instruction: Instruction) {}
-    fn negr1(&mut self, instruction: Instruction) {}
-    fn negr2(&mut self, instruction: Instruction) {}
+    fn popf(&mut self) -> Result<(), Fault> {
+        let value = self.pop_u16()?;
+        self.flags = Flags::from(value as u8);
+        Ok(())
+    }
+
+    fn pali(&mut self, addr: u16) -> Result<(), Fault> {
+        self.load_palette(addr)
+    }
+
+    fn palr(&mut self, x: u8) -> Result<(), Fault> {
+        let x = *self.registers.get(x);
+        self.load_palette(x)
+    }
+
+    // NOTE: This is NOT an cpu operation, but it is used by pali and palr.
+    fn load_palette(&mut self, address: u16) -> Result<(), Fault> {
+        let palette_data = self
+            .memory
+            .read_slice(address, PALETTE_DATA_SIZE)
+            .ok_or(Fault::MemoryOutOfBounds(address))?;
+
+        self.graphics.palette.load(palette_data);
+        Ok(())
+    }
+
+    fn noti(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.not(imm);
+        Ok(())
+    }
+
+    fn notr1(&mut self, x: u8) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.not(*x);
+        Ok(())
+    }
+
+    fn notr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.not(y);
+        Ok(())
+    }
+
+    fn negi(&mut self, x: u8, imm: u16) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.neg(imm);
+        Ok(())
+    }
+
+    fn negr1(&mut self, x: u8) -> Result<(), Fault> {
+        let x = self.registers.get_mut(x);
+        *x = self.flags.neg(*x);
+        Ok(())
+    }
+
+    fn negr2(&mut self, x: u8, y: u8) -> Result<(), Fault> {
+        let y = *self.registers.get(y);
+        let x = self.registers.get_mut(x);
+        *x = self.flags.neg(y);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio::write_wav;
+    use crc::crc32;
+    use palette::Color;
+    use rom::{RomFormat, Version};
+
+    fn execute(cpu: &mut Cpu, word: u32) -> Result<StepOutcome, EmulationError> {
+        cpu.execute(Instruction::new(word))
+    }
+
+    #[test]
+    fn step_advances_program_counter() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_u32(0x0000u16, 0x1234_0120);
+
+        assert_eq!(cpu.step(), Ok(StepOutcome::Continue));
+        assert_eq!(cpu.program_counter, 0x0004);
+        assert_eq!(*cpu.registers.get(1u8), 0x1234);
+    }
+
+    #[test]
+    fn step_reports_vblnk() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_u32(0x0000u16, 0x0000_0002);
+
+        assert_eq!(cpu.step(), Ok(StepOutcome::WaitVblnk));
+    }
+
+    #[test]
+    fn run_frame_without_vblnk() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_u32(0x0000u16, 0x0000_0010);
+
+        let stats = cpu.run_frame().unwrap();
+        assert_eq!(
+            stats,
+            FrameStats {
+                cycles: CYCLES_PER_FRAME,
+                waited: false,
+            }
+        );
+    }
+
+    #[test]
+    fn run_frame_stalls_on_vblnk() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_u32(0x0000u16, 0x0001_0140);
+        cpu.memory.write_u32(0x0004u16, 0x0000_0002);
+        cpu.memory.write_u32(0x0008u16, 0x0000_0010);
+
+        let stats = cpu.run_frame().unwrap();
+        assert_eq!(
+            stats,
+            FrameStats {
+                cycles: 2,
+                waited: true,
+            }
+        );
+        assert_eq!(cpu.program_counter, 0x0008);
+        assert_eq!(*cpu.registers.get(1u8), 0x0001);
+
+        let stats = cpu.run_frame().unwrap();
+        assert_eq!(stats.cycles, 3);
+        assert_eq!(*cpu.registers.get(1u8), 0x0002);
+        assert_eq!(cpu.program_counter, 0x0008);
+        assert_eq!(cpu.cycles, 5);
+    }
+
+    fn versioned_rom(content: Vec<u8>, version: Version) -> Rom {
+        Rom {
+            format: RomFormat::Chip16,
+            version: Some(version),
+            ..rom(content, 0)
+        }
+    }
+
+    #[test]
+    fn load_selects_profile() {
+        let mut cpu = Cpu::new();
+        cpu.load(&versioned_rom(vec![0; 4], Version(1, 2))).unwrap();
+        assert_eq!(cpu.profile, Some(ExecutionProfile::V1_1));
+
+        cpu.load(&rom(vec![0; 4], 0)).unwrap();
+        assert_eq!(cpu.profile, None);
+    }
+
+    #[test]
+    fn unsupported_opcode_is_an_error() {
+        let mut cpu = Cpu::new();
+        // MOD r0, r1 was added in 1.3.
+        cpu.load(&versioned_rom(vec![0xA4, 0x10, 0x00, 0x00], Version(1, 0)))
+            .unwrap();
+
+        assert_eq!(
+            cpu.step(),
+            Err(EmulationError::UnsupportedOpcode {
+                pc: 0x0000,
+                word: 0x0000_10A4,
+                profile: ExecutionProfile::V1_0,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn unsupported_opcode_is_a_warning() {
+        let mut cpu = Cpu::new();
+        cpu.load(&versioned_rom(
+            vec![0xE4, 0x00, 0x00, 0x00, 0xE4, 0x00, 0x00, 0x00],
+            Version(1, 1),
+        ))
+        .unwrap();
+        cpu.profile_policy = ProfilePolicy::Warn;
+        *cpu.registers.get_mut(0u8) = 1;
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(*cpu.registers.get(0u8), 1);
+        assert_eq!(
+            cpu.take_warnings(),
+            vec![EmulationError::UnsupportedOpcode {
+                pc: 0x0000,
+                word: 0x0000_00E4,
+                profile: ExecutionProfile::V1_1,
+            }]
+        );
+        assert!(cpu.take_warnings().is_empty());
+    }
+
+    fn rom(content: Vec<u8>, start_address: u16) -> Rom {
+        Rom {
+            format: RomFormat::Raw,
+            version: None,
+            size: content.len() as u32,
+            start_address,
+            checksum: None,
+            computed_checksum: 0,
+            content,
+        }
+    }
+
+    #[test]
+    fn load_rom() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(4u8) = 0x1234;
+        cpu.stack_pointer = 0xFE00;
+        cpu.graphics.background_layer = 0x3;
+        cpu.memory.write_u8(0x0010u16, 0xAA);
+
+        cpu.load(&rom(
+            vec![0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00],
+            0x0004,
+        ))
+        .unwrap();
+        assert_eq!(cpu.program_counter, 0x0004);
+        assert_eq!(cpu.stack_pointer, STACK_START);
+        assert_eq!(cpu.memory.read_u32(0x0000u16), Some(0x0000_0001));
+        assert_eq!(cpu.memory.read_u8(0x0010u16), Some(0x00));
+        assert_eq!(*cpu.registers.get(4u8), 0x0000);
+        assert_eq!(cpu.graphics.background_layer, 0x0);
+        assert_eq!(cpu.step(), Ok(StepOutcome::WaitVblnk));
+    }
+
+    #[test]
+    fn load_rom_up_to_the_stack() {
+        let mut cpu = Cpu::new();
+        assert!(cpu.load(&rom(vec![0; STACK_START as usize], 0)).is_ok());
+        assert!(cpu
+            .load(&rom(vec![0; STACK_START as usize + 1], 0))
+            .is_err());
+        assert!(cpu.load(&rom(vec![0; 4], 4)).is_err());
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let mut cpu = Cpu::new();
+        cpu.program_counter = 0x0100;
+        cpu.memory.write_u32(0x0100u16, 0xDEAD_BEFF);
+
+        assert_eq!(
+            cpu.step(),
+            Err(EmulationError::InvalidOpcode {
+                pc: 0x0100,
+                word: 0xDEAD_BEFF,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0100);
+    }
+
+    #[test]
+    fn reserved_bits_are_an_invalid_opcode() {
+        let mut cpu = Cpu::new();
+        // ADD r1, r2 with a non-zero Z nibble.
+        cpu.memory.write_u32(0x0000u16, 0x0003_2141);
+
+        assert_eq!(
+            cpu.step(),
+            Err(EmulationError::InvalidOpcode {
+                pc: 0x0000,
+                word: 0x0003_2141,
+            })
+        );
+    }
+
+    #[test]
+    fn program_counter_overflow() {
+        let mut cpu = Cpu::new();
+        cpu.program_counter = 0xFFFC;
+        assert_eq!(cpu.step(), Err(EmulationError::PcOverflow { pc: 0xFFFC }));
+
+        cpu.program_counter = 0xFFFE;
+        assert_eq!(cpu.step(), Err(EmulationError::PcOverflow { pc: 0xFFFE }));
+    }
+
+    fn flags(carry: bool, zero: bool, overflow: bool, negative: bool) -> Flags {
+        Flags {
+            carry,
+            zero,
+            overflow,
+            negative,
+        }
+    }
+
+    #[test]
+    fn add_instructions() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(1u8) = 0xFFFF;
+        execute(&mut cpu, 0x0001_0140).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0x0000);
+        assert_eq!(cpu.flags, flags(true, true, false, false));
+
+        *cpu.registers.get_mut(2u8) = 0x7FFF;
+        *cpu.registers.get_mut(3u8) = 0x0001;
+        execute(&mut cpu, 0x0000_3241).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0x8000);
+        assert_eq!(cpu.flags, flags(false, false, true, true));
+
+        execute(&mut cpu, 0x0004_3242).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0x8000);
+        assert_eq!(*cpu.registers.get(4u8), 0x8001);
+        assert_eq!(cpu.flags, flags(false, false, false, true));
+    }
+
+    #[test]
+    fn subtract_and_compare_instructions() {
+        let mut cpu = Cpu::new();
+        execute(&mut cpu, 0x0001_0150).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0xFFFF);
+        assert_eq!(cpu.flags, flags(true, false, false, true));
+
+        *cpu.registers.get_mut(2u8) = 0x8000;
+        *cpu.registers.get_mut(3u8) = 0x0001;
+        execute(&mut cpu, 0x0000_3254).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0x8000);
+        assert_eq!(cpu.flags, flags(false, false, true, false));
+
+        execute(&mut cpu, 0x0001_0353).unwrap();
+        assert_eq!(*cpu.registers.get(3u8), 0x0001);
+        assert_eq!(cpu.flags, flags(false, true, false, false));
+    }
+
+    #[test]
+    fn logic_instructions() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(1u8) = 0xF0F0;
+        execute(&mut cpu, 0x0F0F_0160).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0x0000);
+        assert_eq!(cpu.flags, flags(false, true, false, false));
+
+        *cpu.registers.get_mut(2u8) = 0x8000;
+        execute(&mut cpu, 0x0001_0270).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0x8001);
+        assert_eq!(cpu.flags, flags(false, false, false, true));
+
+        execute(&mut cpu, 0x0000_2281).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0x0000);
+        assert_eq!(cpu.flags, flags(false, true, false, false));
+
+        *cpu.registers.get_mut(3u8) = 0x00FF;
+        execute(&mut cpu, 0x8000_0363).unwrap();
+        assert_eq!(*cpu.registers.get(3u8), 0x00FF);
+        assert_eq!(cpu.flags, flags(false, true, false, false));
+
+        execute(&mut cpu, 0x00FF_01E0).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0xFF00);
+        assert_eq!(cpu.flags, flags(false, false, false, true));
+
+        execute(&mut cpu, 0x0000_03E4).unwrap();
+        assert_eq!(*cpu.registers.get(3u8), 0xFF01);
+        assert_eq!(cpu.flags, flags(false, false, false, true));
+    }
+
+    #[test]
+    fn multiply_and_divide_instructions() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(1u8) = 0x0100;
+        execute(&mut cpu, 0x0100_0190).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0x0000);
+        assert_eq!(cpu.flags, flags(true, true, false, false));
+
+        *cpu.registers.get_mut(2u8) = 0x0007;
+        execute(&mut cpu, 0x0002_02A0).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0x0003);
+        assert_eq!(cpu.flags, flags(true, false, false, false));
+
+        *cpu.registers.get_mut(3u8) = 0xFFF9;
+        *cpu.registers.get_mut(4u8) = 0xFFF9;
+        // Modulo and remainder leave the carry from the division before them.
+        execute(&mut cpu, 0x0003_03A3).unwrap();
+        assert_eq!(*cpu.registers.get(3u8), 0x0002);
+        assert_eq!(cpu.flags, flags(true, false, false, false));
+
+        execute(&mut cpu, 0x0003_04A6).unwrap();
+        assert_eq!(*cpu.registers.get(4u8), 0xFFFF);
+        assert_eq!(cpu.flags, flags(true, false, false, true));
+    }
+
+    #[test]
+    fn shift_instructions() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(1u8) = 0x8001;
+        execute(&mut cpu, 0x0001_01B0).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0x0002);
+        assert_eq!(cpu.flags, flags(false, false, false, false));
+
+        *cpu.registers.get_mut(2u8) = 0x8000;
+        execute(&mut cpu, 0x0004_02B2).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0xF800);
+        assert_eq!(cpu.flags, flags(false, false, false, true));
+
+        *cpu.registers.get_mut(3u8) = 0x8000;
+        *cpu.registers.get_mut(4u8) = 0x0010;
+        execute(&mut cpu, 0x0000_43B4).unwrap();
+        assert_eq!(*cpu.registers.get(3u8), 0x0000);
+        assert_eq!(cpu.flags, flags(false, true, false, false));
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let mut cpu = Cpu::new();
+        assert_eq!(
+            execute(&mut cpu, 0x0000_01A0),
+            Err(EmulationError::DivideByZero {
+                pc: 0x0000,
+                word: 0x0000_01A0,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0000);
+
+        cpu.program_counter = 0x0004;
+        assert_eq!(
+            execute(&mut cpu, 0x0000_21A4),
+            Err(EmulationError::DivideByZero {
+                pc: 0x0004,
+                word: 0x0000_21A4,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0004);
+    }
+
+    #[test]
+    fn memory_out_of_bounds() {
+        let mut cpu = Cpu::new();
+        assert_eq!(
+            execute(&mut cpu, 0xFFFF_0122),
+            Err(EmulationError::MemoryOutOfBounds {
+                pc: 0x0000,
+                word: 0xFFFF_0122,
+                address: 0xFFFF,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn random_includes_upper_bound() {
+        let mut cpu = Cpu::new();
+        execute(&mut cpu, 0x0000_0107).unwrap();
+        assert_eq!(*cpu.registers.get(1u8), 0);
+    }
+
+    #[test]
+    fn draw_sets_carry_on_collision() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_u8(0x0200u16, 0x11);
+        *cpu.registers.get_mut(0u8) = 0xFFFF;
+        execute(&mut cpu, 0x0101_0004).unwrap();
+
+        execute(&mut cpu, 0x0200_1005).unwrap();
+        assert!(!cpu.flags.carry);
+        assert_eq!(cpu.graphics.read_pixel(0, 0), 1);
+
+        execute(&mut cpu, 0x0200_1005).unwrap();
+        assert!(cpu.flags.carry);
+    }
+
+    #[test]
+    fn load_palette() {
+        let mut cpu = Cpu::new();
+        for index in 0..48u16 {
+            cpu.memory.write_u8(0x0300 + index, 0xFF - index as u8);
+        }
+        *cpu.registers.get_mut(2u8) = 0x0303;
+
+        execute(&mut cpu, 0x0300_00D0).unwrap();
+        assert_eq!(cpu.graphics.palette.get(0), Color::new(0xFF, 0xFE, 0xFD));
+
+        execute(&mut cpu, 0x0000_02D1).unwrap();
+        assert_eq!(cpu.graphics.palette.get(0), Color::new(0xFC, 0xFB, 0xFA));
+        assert_eq!(
+            execute(&mut cpu, 0xFFF0_00D0),
+            Err(EmulationError::MemoryOutOfBounds {
+                pc: 0x0008,
+                word: 0xFFF0_00D0,
+                address: 0xFFF0,
+            })
+        );
+    }
+
+    #[test]
+    fn background_color() {
+        let mut cpu = Cpu::new();
+        execute(&mut cpu, 0x000A_0003).unwrap();
+        assert_eq!(cpu.graphics.background_layer, 0xA);
+    }
+
+    #[test]
+    fn sound_generation() {
+        let mut cpu = Cpu::new();
+        execute(&mut cpu, 0x8F42_130E).unwrap();
+        assert_eq!(
+            cpu.audio.envelope,
+            Envelope {
+                attack: 1,
+                decay: 3,
+                sustain: 4,
+                release: 2,
+                volume: 8,
+                waveform: Waveform::Noise,
+            }
+        );
+
+        cpu.memory.write_u16(0x0400u16, 440);
+        *cpu.registers.get_mut(5u8) = 0x0400;
+        execute(&mut cpu, 0x0064_050D).unwrap();
+        assert!(cpu.audio.is_playing());
+
+        execute(&mut cpu, 0x0000_0009).unwrap();
+        assert!(!cpu.audio.is_playing());
+
+        execute(&mut cpu, 0x0010_000B).unwrap();
+        assert!(cpu.audio.is_playing());
+    }
+
+    // Renders a fixed sequence of sound instructions into a WAV file and compares its digest, so
+    // any change to the envelopes or the waveforms shows up here. A deliberate change to how sound
+    // is rendered has to update the digest.
+    #[test]
+    fn sound_golden_wav() {
+        let mut cpu = Cpu::new();
+        cpu.audio.set_sample_rate(8_000);
+        cpu.memory.write_u16(0x0400u16, 440);
+        *cpu.registers.get_mut(5u8) = 0x0400;
+
+        // SND1 and SND3 for 20ms, then SNG with each waveform followed by SNP r5 for 30ms. Each is
+        // rendered for 50ms, which is cut short by the next tone during the release.
+        let sequence: &[&[u32]] = &[
+            &[0x0014_000A],
+            &[0x0014_000C],
+            &[0xF0A1_120E, 0x001E_050D],
+            &[0xC183_010E, 0x001E_050D],
+            &[0xA262_200E, 0x001E_050D],
+            &[0x8F42_130E, 0x001E_050D],
+        ];
+        let mut samples = Vec::new();
+        for instructions in sequence {
+            for &word in instructions.iter() {
+                execute(&mut cpu, word).unwrap();
+            }
+            let mut buffer = [0; 400];
+            cpu.audio.render_i16(&mut buffer);
+            samples.extend_from_slice(&buffer);
+        }
+
+        for tone in samples.chunks(400) {
+            assert!(tone.iter().any(|&sample| sample > 0));
+            assert!(tone.iter().any(|&sample| sample < 0));
+        }
+
+        let mut wav = Vec::new();
+        write_wav(&mut wav, 8_000, &samples).unwrap();
+        assert_eq!(wav.len(), 44 + 2 * 6 * 400);
+        assert_eq!(crc32::checksum_ieee(&wav), 0x3CE6_EF74);
+    }
+
+    #[test]
+    fn controllers_are_written_on_vblnk() {
+        let mut cpu = Cpu::new();
+        let controller = Controller {
+            left: true,
+            a: true,
+            ..Controller::default()
+        };
+        cpu.set_controller(Port::Two, controller);
+        assert_eq!(cpu.memory.read_u16(0xFFF2u16), Some(0x0000));
+
+        execute(&mut cpu, 0x0000_0002).unwrap();
+        assert_eq!(cpu.memory.read_u16(0xFFF0u16), Some(0x0000));
+        assert_eq!(cpu.memory.read_u16(0xFFF2u16), Some(0x0044));
+    }
+
+    #[rustfmt::skip]
+    const CONDITION_TRUTH_TABLE: [([u8; 4], [u8; 15]); 16] = [
+        // C  Z  O  N      Z NZ  N NN  P  O NO  A AE  B BE  G GE  L LE
+        ([0, 0, 0, 0], [0, 1, 0, 1, 1, 0, 1, 1, 1, 0, 0, 1, 1, 0, 0]),
+        ([0, 0, 0, 1], [0, 1, 1, 0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 1, 1]),
+        ([0, 0, 1, 0], [0, 1, 0, 1, 1, 1, 0, 1, 1, 0, 0, 0, 0, 1, 1]),
+        ([0, 0, 1, 1], [0, 1, 1, 0, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 0]),
+        ([0, 1, 0, 0], [1, 0, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1]),
+        ([0, 1, 0, 1], [1, 0, 1, 0, 0, 0, 1, 0, 1, 0, 1, 0, 0, 1, 1]),
+        ([0, 1, 1, 0], [1, 0, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 1, 1]),
+        ([0, 1, 1, 1], [1, 0, 1, 0, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1]),
+        ([1, 0, 0, 0], [0, 1, 0, 1, 1, 0, 1, 0, 0, 1, 1, 1, 1, 0, 0]),
+        ([1, 0, 0, 1], [0, 1, 1, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0, 1, 1]),
+        ([1, 0, 1, 0], [0, 1, 0, 1, 1, 1, 0, 0, 0, 1, 1, 0, 0, 1, 1]),
+        ([1, 0, 1, 1], [0, 1, 1, 0, 0, 1, 0, 0, 0, 1, 1, 1, 1, 0, 0]),
+        ([1, 1, 0, 0], [1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 1, 0, 1, 0, 1]),
+        ([1, 1, 0, 1], [1, 0, 1, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0, 1, 1]),
+        ([1, 1, 1, 0], [1, 0, 0, 1, 0, 1, 0, 0, 0, 1, 1, 0, 0, 1, 1]),
+        ([1, 1, 1, 1], [1, 0, 1, 0, 0, 1, 0, 0, 0, 1, 1, 0, 1, 0, 1]),
+    ];
+
+    #[test]
+    fn condition_truth_table() {
+        for &(flags, expected) in CONDITION_TRUTH_TABLE.iter() {
+            let mut cpu = Cpu::new();
+            cpu.flags = Flags {
+                carry: flags[0] == 1,
+                zero: flags[1] == 1,
+                overflow: flags[2] == 1,
+                negative: flags[3] == 1,
+            };
+
+            for (nibble, &taken) in expected.iter().enumerate() {
+                let word = 0x0100_0012 | (nibble as u32) << 8;
+                cpu.program_counter = 0x0000;
+                execute(&mut cpu, word).unwrap();
+
+                let target = if taken == 1 { 0x0100 } else { 0x0004 };
+                assert_eq!(
+                    cpu.program_counter, target,
+                    "condition {:x} with flags {:?}",
+                    nibble, cpu.flags
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn conditions_after_compare() {
+        let values: [u16; 6] = [0x0000, 0x0001, 0x7FFF, 0x8000, 0x8001, 0xFFFF];
+        for &a in values.iter() {
+            for &b in values.iter() {
+                let mut cpu = Cpu::new();
+                cpu.flags.sub(a, b);
+
+                let (signed_a, signed_b) = (a as i16, b as i16);
+                assert_eq!(cpu.test(Condition::A), a > b);
+                assert_eq!(cpu.test(Condition::AE), a >= b);
+                assert_eq!(cpu.test(Condition::B), a < b);
+                assert_eq!(cpu.test(Condition::BE), a <= b);
+                assert_eq!(cpu.test(Condition::G), signed_a > signed_b);
+                assert_eq!(cpu.test(Condition::GE), signed_a >= signed_b);
+                assert_eq!(cpu.test(Condition::L), signed_a < signed_b);
+                assert_eq!(cpu.test(Condition::LE), signed_a <= signed_b);
+                assert_eq!(cpu.test(Condition::Z), a == b);
+                assert_eq!(cpu.test(Condition::NZ), a != b);
+            }
+        }
+    }
+
+    #[test]
+    fn reserved_condition() {
+        let mut cpu = Cpu::new();
+        assert_eq!(
+            execute(&mut cpu, 0x0100_0F12),
+            Err(EmulationError::InvalidOpcode {
+                pc: 0x0000,
+                word: 0x0100_0F12,
+            })
+        );
+    }
+
+    #[test]
+    fn conditional_call() {
+        let mut cpu = Cpu::new();
+        cpu.flags.zero = true;
+
+        execute(&mut cpu, 0x0300_0117).unwrap();
+        assert_eq!(cpu.program_counter, 0x0004);
+        assert_eq!(cpu.stack_pointer, STACK_START);
+
+        execute(&mut cpu, 0x0300_0017).unwrap();
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.memory.read_u16(STACK_START), Some(0x0008));
+    }
+
+    #[test]
+    fn jump_register_and_carry() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(7u8) = 0x0480;
+        execute(&mut cpu, 0x0000_0716).unwrap();
+        assert_eq!(cpu.program_counter, 0x0480);
+
+        execute(&mut cpu, 0x0200_0011).unwrap();
+        assert_eq!(cpu.program_counter, 0x0484);
+
+        cpu.flags.carry = true;
+        execute(&mut cpu, 0x0200_0011).unwrap();
+        assert_eq!(cpu.program_counter, 0x0200);
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut cpu = Cpu::new();
+        cpu.program_counter = 0x0100;
+
+        execute(&mut cpu, 0x0200_0014).unwrap();
+        assert_eq!(cpu.program_counter, 0x0200);
+        assert_eq!(cpu.stack_pointer, STACK_START + 2);
+        assert_eq!(cpu.memory.read_u16(STACK_START), Some(0x0104));
+
+        execute(&mut cpu, 0x0000_0015).unwrap();
+        assert_eq!(cpu.program_counter, 0x0104);
+        assert_eq!(cpu.stack_pointer, STACK_START);
+    }
+
+    #[test]
+    fn call_register() {
+        let mut cpu = Cpu::new();
+        cpu.program_counter = 0x0010;
+        *cpu.registers.get_mut(3u8) = 0x0400;
+
+        execute(&mut cpu, 0x0000_0318).unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.memory.read_u16(STACK_START), Some(0x0014));
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let mut cpu = Cpu::new();
+        *cpu.registers.get_mut(1u8) = 0xBEEF;
+
+        execute(&mut cpu, 0x0000_01C0).unwrap();
+        execute(&mut cpu, 0x0000_02C1).unwrap();
+        assert_eq!(*cpu.registers.get(2u8), 0xBEEF);
+        assert_eq!(cpu.stack_pointer, STACK_START);
+    }
+
+    #[test]
+    fn push_all_and_pop_all() {
+        let mut cpu = Cpu::new();
+        for index in 0..16u8 {
+            *cpu.registers.get_mut(index) = index as u16 * 0x0101;
+        }
+
+        execute(&mut cpu, 0x0000_00C2).unwrap();
+        assert_eq!(cpu.stack_pointer, STACK_START + 32);
+        assert_eq!(cpu.memory.read_u16(STACK_START + 30), Some(0x0F0F));
+
+        cpu.registers.reset();
+        execute(&mut cpu, 0x0000_00C3).unwrap();
+        assert_eq!(cpu.stack_pointer, STACK_START);
+        for index in 0..16u8 {
+            assert_eq!(*cpu.registers.get(index), index as u16 * 0x0101);
+        }
+    }
+
+    #[test]
+    fn push_and_pop_flags() {
+        let mut cpu = Cpu::new();
+        cpu.flags.carry = true;
+        cpu.flags.negative = true;
+
+        execute(&mut cpu, 0x0000_00C4).unwrap();
+        assert_eq!(cpu.memory.read_u16(STACK_START), Some(0x0082));
+
+        cpu.flags = Flags::new();
+        execute(&mut cpu, 0x0000_00C5).unwrap();
+        assert!(cpu.flags.carry && cpu.flags.negative);
+        assert!(!cpu.flags.zero && !cpu.flags.overflow);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut cpu = Cpu::new();
+        cpu.stack_pointer = STACK_END - 2;
+
+        execute(&mut cpu, 0x0000_00C0).unwrap();
+        assert_eq!(
+            execute(&mut cpu, 0x0000_00C0),
+            Err(EmulationError::StackOverflow {
+                pc: 0x0004,
+                sp: STACK_END,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0004);
+        assert_eq!(cpu.memory.read_u16(STACK_END), Some(0x0000));
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let mut cpu = Cpu::new();
+        cpu.program_counter = 0x0100;
+
+        assert_eq!(
+            execute(&mut cpu, 0x0000_0015),
+            Err(EmulationError::StackUnderflow {
+                pc: 0x0100,
+                sp: STACK_START,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x0100);
+    }
 }
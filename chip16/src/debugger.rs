@@ -0,0 +1,577 @@
+use cpu::Cpu;
+use error::EmulationError;
+use instruction::{Instruction, Operation};
+use memory::{Access, MemoryAccess};
+use std::collections::BTreeMap;
+use std::fmt;
+use symbols::{SourceLocation, Symbols};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    fn holds(self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterEqual => left >= right,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match *self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+// A condition on a register, such as `r1 == 5`. Values are compared as unsigned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegisterCondition {
+    pub register: u8,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl RegisterCondition {
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        let register = *cpu.registers.get(self.register);
+        self.comparison.holds(register, self.value)
+    }
+}
+
+impl fmt::Display for RegisterCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "r{:X} {} 0x{:04x}",
+            self.register, self.comparison, self.value
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Either a read or a write.
+    Access,
+    Execute,
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+            WatchKind::Execute => "execute",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Watches every address from `start` to `end`, inclusive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Watchpoint {
+    // The first watched address of the bytes from `address`, if any are watched.
+    fn overlap(&self, address: u16, length: usize) -> Option<u16> {
+        if length == 0 {
+            return None;
+        }
+        let first = self.start.max(address);
+        let last = (self.end as usize).min(address as usize + length - 1);
+        if first as usize <= last {
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+
+    fn watches(&self, access: &MemoryAccess) -> Option<u16> {
+        let kind = match access.access {
+            Access::Read => WatchKind::Read,
+            Access::Write => WatchKind::Write,
+        };
+        if self.kind == kind || self.kind == WatchKind::Access {
+            self.overlap(access.address, access.length)
+        } else {
+            None
+        }
+    }
+}
+
+// Why the debugger stopped running the cpu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    // The step finished.
+    Step,
+    Breakpoint(u16),
+    // A watched address was read or written, or is about to be executed.
+    Watchpoint {
+        watchpoint: Watchpoint,
+        address: u16,
+    },
+    // A register condition became true.
+    Condition(RegisterCondition),
+    // The cycles that the cpu was given to run for were used up.
+    CycleLimit,
+}
+
+// Runs a cpu under the control of breakpoints, watchpoints and register conditions.
+//
+// Breakpoints and execute watchpoints stop before the instruction at their address is executed,
+// while read and write watchpoints stop after the instruction that accessed memory. Register
+// conditions stop after the instruction that made them true. Running always executes the
+// instruction at the program counter first, so that running from a breakpoint does not stop at
+// it again.
+pub struct Debugger {
+    pub cpu: Cpu,
+    // Addresses are described with the labels and source lines of the rom, if it has them.
+    pub symbols: Symbols,
+    breakpoints: BTreeMap<u16, Option<RegisterCondition>>,
+    watchpoints: Vec<Watchpoint>,
+    conditions: Vec<RegisterCondition>,
+}
+
+impl Debugger {
+    pub fn new(cpu: Cpu) -> Debugger {
+        Debugger {
+            cpu,
+            symbols: Symbols::new(),
+            breakpoints: BTreeMap::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, None);
+    }
+
+    // A breakpoint that only stops when the condition holds.
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: RegisterCondition) {
+        self.breakpoints.insert(address, Some(condition));
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    // Every breakpoint, in address order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (u16, Option<RegisterCondition>)> + '_ {
+        self.breakpoints
+            .iter()
+            .map(|(&address, &condition)| (address, condition))
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|other| other != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Stops wherever the condition becomes true.
+    pub fn add_condition(&mut self, condition: RegisterCondition) {
+        if !self.conditions.contains(&condition) {
+            self.conditions.push(condition);
+        }
+    }
+
+    pub fn remove_condition(&mut self, condition: &RegisterCondition) -> bool {
+        let count = self.conditions.len();
+        self.conditions.retain(|other| other != condition);
+        self.conditions.len() != count
+    }
+
+    pub fn conditions(&self) -> &[RegisterCondition] {
+        &self.conditions
+    }
+
+    // Executes a single instruction, following calls into the routine they call.
+    pub fn step_into(&mut self) -> Result<Stop, EmulationError> {
+        let (_, stop) = self.execute()?;
+        Ok(stop.unwrap_or(Stop::Step))
+    }
+
+    // Executes a single instruction, except that a call runs until it returns to the next one.
+    pub fn step_over(&mut self, cycles: u64) -> Result<Stop, EmulationError> {
+        if !self.at_call() {
+            return self.step_into();
+        }
+        let return_address = self.cpu.program_counter.wrapping_add(4);
+        let stack_pointer = self.cpu.stack_pointer;
+        self.run_until(cycles, |cpu, _| {
+            cpu.program_counter == return_address && cpu.stack_pointer == stack_pointer
+        })
+    }
+
+    // Runs until the routine that is executing returns to its caller.
+    pub fn step_out(&mut self, cycles: u64) -> Result<Stop, EmulationError> {
+        let stack_pointer = self.cpu.stack_pointer;
+        self.run_until(cycles, |cpu, operation| {
+            operation == Operation::RET && cpu.stack_pointer < stack_pointer
+        })
+    }
+
+    // Runs until something stops the cpu, or `cycles` cycles have been executed.
+    pub fn run(&mut self, cycles: u64) -> Result<Stop, EmulationError> {
+        self.run_until(cycles, |_, _| false)
+    }
+
+    // Names an address after the closest label before it, such as `draw_paddle+8`, or in
+    // hexadecimal if there is none.
+    pub fn describe(&self, address: u16) -> String {
+        match self.symbols.label_before(address) {
+            Some((label, name)) if label == address => name.to_string(),
+            Some((label, name)) => format!("{}+{}", name, address - label),
+            None => format!("{:04x}", address),
+        }
+    }
+
+    // The line of source that the instruction at an address was assembled from.
+    pub fn source(&self, address: u16) -> Option<&SourceLocation> {
+        self.symbols.source(address)
+    }
+
+    fn at_call(&self) -> bool {
+        let operation = self
+            .cpu
+            .memory
+            .read_u32(self.cpu.program_counter)
+            .and_then(|word| Instruction::new(word).decode())
+            .map(|decoded| decoded.operation());
+        match operation {
+            Some(operation) => {
+                operation == Operation::CALLI
+                    || operation == Operation::CALLR
+                    || operation == Operation::CX
+            }
+            None => false,
+        }
+    }
+
+    fn run_until<F>(&mut self, cycles: u64, mut done: F) -> Result<Stop, EmulationError>
+    where
+        F: FnMut(&Cpu, Operation) -> bool,
+    {
+        let start = self.cpu.cycles;
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some(stop) = self.breakpoint() {
+                    return Ok(stop);
+                }
+            }
+            if self.cpu.cycles - start >= cycles {
+                return Ok(Stop::CycleLimit);
+            }
+            first = false;
+
+            let (operation, stop) = self.execute()?;
+            if let Some(stop) = stop {
+                return Ok(stop);
+            }
+            if done(&self.cpu, operation) {
+                return Ok(Stop::Step);
+            }
+        }
+    }
+
+    // The breakpoint or execute watchpoint that stops the cpu at the program counter, if any.
+    fn breakpoint(&self) -> Option<Stop> {
+        let pc = self.cpu.program_counter;
+        if let Some(condition) = self.breakpoints.get(&pc) {
+            if condition.is_none_or(|condition| condition.holds(&self.cpu)) {
+                return Some(Stop::Breakpoint(pc));
+            }
+        }
+        self.watchpoints
+            .iter()
+            .filter(|watchpoint| watchpoint.kind == WatchKind::Execute)
+            .find_map(|&watchpoint| {
+                let address = watchpoint.overlap(pc, 4)?;
+                Some(Stop::Watchpoint {
+                    watchpoint,
+                    address,
+                })
+            })
+    }
+
+    // Executes the instruction at the program counter, and returns its operation and the
+    // watchpoint or condition that it set off, if any.
+    fn execute(&mut self) -> Result<(Operation, Option<Stop>), EmulationError> {
+        let pc = self.cpu.program_counter;
+        let word = self
+            .cpu
+            .memory
+            .read_u32(pc)
+            .ok_or(EmulationError::PcOverflow { pc })?;
+        let held: Vec<_> = self
+            .conditions
+            .iter()
+            .map(|condition| condition.holds(&self.cpu))
+            .collect();
+
+        // Only the accesses of the instruction are watched, and not the fetch above.
+        let instruction = Instruction::new(word);
+        let operation = instruction.decode().map(|decoded| decoded.operation());
+        self.cpu.memory.set_tracing(true);
+        let result = self.cpu.execute(instruction);
+        self.cpu.memory.set_tracing(false);
+        let accesses = self.cpu.memory.take_accesses();
+        result?;
+        let operation = operation.expect("executed instructions can be decoded");
+
+        for access in &accesses {
+            for &watchpoint in &self.watchpoints {
+                if let Some(address) = watchpoint.watches(access) {
+                    let stop = Stop::Watchpoint {
+                        watchpoint,
+                        address,
+                    };
+                    return Ok((operation, Some(stop)));
+                }
+            }
+        }
+        for (condition, held) in self.conditions.iter().zip(held) {
+            if !held && condition.holds(&self.cpu) {
+                return Ok((operation, Some(Stop::Condition(*condition))));
+            }
+        }
+        Ok((operation, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+    use cpu::STACK_START;
+    use rom::Rom;
+
+    const PROGRAM: &str = "
+        start:   ldi r0, 0
+                 call add_one
+                 call add_one
+                 stm r0, result
+        done:    jmp done
+        add_one: addi r0, 1
+                 ret
+        result:  dw 0
+    ";
+
+    fn debugger() -> Debugger {
+        let assembly = assemble(PROGRAM).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.load(&Rom::from_content(assembly.content)).unwrap();
+        let mut debugger = Debugger::new(cpu);
+        debugger.symbols = assembly.symbols;
+        debugger
+    }
+
+    fn address(debugger: &Debugger, label: &str) -> u16 {
+        debugger.symbols.address_of(label).unwrap()
+    }
+
+    fn condition(register: u8, comparison: Comparison, value: u16) -> RegisterCondition {
+        RegisterCondition {
+            register,
+            comparison,
+            value,
+        }
+    }
+
+    fn watchpoint(kind: WatchKind, start: u16, end: u16) -> Watchpoint {
+        Watchpoint { kind, start, end }
+    }
+
+    #[test]
+    fn stop_at_breakpoints() {
+        let mut debugger = debugger();
+        let add_one = address(&debugger, "add_one");
+        debugger.add_breakpoint(add_one);
+
+        assert_eq!(debugger.run(1000), Ok(Stop::Breakpoint(add_one)));
+        assert_eq!(*debugger.cpu.registers.get(0u8), 0);
+        assert_eq!(debugger.run(1000), Ok(Stop::Breakpoint(add_one)));
+        assert_eq!(*debugger.cpu.registers.get(0u8), 1);
+        assert_eq!(debugger.run(1000), Ok(Stop::CycleLimit));
+        assert_eq!(debugger.cpu.program_counter, address(&debugger, "done"));
+
+        assert!(debugger.remove_breakpoint(add_one));
+        assert!(!debugger.remove_breakpoint(add_one));
+    }
+
+    #[test]
+    fn stop_at_conditional_breakpoints() {
+        let mut debugger = debugger();
+        let add_one = address(&debugger, "add_one");
+        debugger.add_conditional_breakpoint(add_one, condition(0, Comparison::Equal, 1));
+
+        assert_eq!(debugger.run(1000), Ok(Stop::Breakpoint(add_one)));
+        assert_eq!(*debugger.cpu.registers.get(0u8), 1);
+        assert_eq!(
+            debugger.breakpoints().collect::<Vec<_>>(),
+            vec![(add_one, Some(condition(0, Comparison::Equal, 1)))]
+        );
+    }
+
+    #[test]
+    fn stop_at_watchpoints() {
+        let mut debugger = debugger();
+        let result = address(&debugger, "result");
+        let write = watchpoint(WatchKind::Write, result, result);
+        debugger.add_watchpoint(write);
+        assert_eq!(
+            debugger.run(1000),
+            Ok(Stop::Watchpoint {
+                watchpoint: write,
+                address: result
+            })
+        );
+        assert_eq!(debugger.cpu.program_counter, address(&debugger, "done"));
+
+        // Calls write the return address to the stack.
+        let mut debugger = self::debugger();
+        let stack = watchpoint(WatchKind::Access, STACK_START, STACK_START + 1);
+        debugger.add_watchpoint(stack);
+        assert_eq!(
+            debugger.run(1000),
+            Ok(Stop::Watchpoint {
+                watchpoint: stack,
+                address: STACK_START
+            })
+        );
+        assert_eq!(debugger.cpu.program_counter, address(&debugger, "add_one"));
+    }
+
+    #[test]
+    fn watch_execution_but_not_fetches() {
+        let mut debugger = debugger();
+        let add_one = address(&debugger, "add_one");
+        debugger.add_watchpoint(watchpoint(WatchKind::Read, add_one, add_one + 7));
+        assert_eq!(debugger.run(1000), Ok(Stop::CycleLimit));
+
+        let mut debugger = self::debugger();
+        let execute = watchpoint(WatchKind::Execute, add_one + 6, add_one + 6);
+        debugger.add_watchpoint(execute);
+        assert_eq!(
+            debugger.run(1000),
+            Ok(Stop::Watchpoint {
+                watchpoint: execute,
+                address: add_one + 6
+            })
+        );
+        assert_eq!(debugger.cpu.program_counter, add_one + 4);
+        assert!(debugger.remove_watchpoint(&execute));
+        assert_eq!(debugger.watchpoints(), &[]);
+    }
+
+    #[test]
+    fn stop_when_a_condition_becomes_true() {
+        let mut debugger = debugger();
+        let twice = condition(0, Comparison::GreaterEqual, 2);
+        debugger.add_condition(twice);
+        assert_eq!(debugger.run(1000), Ok(Stop::Condition(twice)));
+        assert_eq!(
+            debugger.cpu.program_counter,
+            address(&debugger, "add_one") + 4
+        );
+
+        // It has to become false before it stops again.
+        assert_eq!(debugger.run(1000), Ok(Stop::CycleLimit));
+    }
+
+    #[test]
+    fn step_into_over_and_out() {
+        let mut debugger = debugger();
+        let add_one = address(&debugger, "add_one");
+
+        assert_eq!(debugger.step_into(), Ok(Stop::Step));
+        assert_eq!(debugger.step_over(1000), Ok(Stop::Step));
+        assert_eq!(debugger.cpu.program_counter, 0x0008);
+        assert_eq!(*debugger.cpu.registers.get(0u8), 1);
+
+        assert_eq!(debugger.step_into(), Ok(Stop::Step));
+        assert_eq!(debugger.cpu.program_counter, add_one);
+        assert_eq!(debugger.step_out(1000), Ok(Stop::Step));
+        assert_eq!(debugger.cpu.program_counter, 0x000C);
+        assert_eq!(*debugger.cpu.registers.get(0u8), 2);
+
+        // Anything else is a single step.
+        assert_eq!(debugger.step_over(1000), Ok(Stop::Step));
+        assert_eq!(debugger.cpu.program_counter, 0x0010);
+    }
+
+    #[test]
+    fn step_over_stops_inside_calls() {
+        let mut debugger = debugger();
+        let add_one = address(&debugger, "add_one");
+        debugger.add_breakpoint(add_one + 4);
+
+        debugger.step_into().unwrap();
+        assert_eq!(debugger.step_over(1000), Ok(Stop::Breakpoint(add_one + 4)));
+        debugger.step_into().unwrap();
+        assert_eq!(debugger.step_over(0), Ok(Stop::CycleLimit));
+    }
+
+    #[test]
+    fn display_conditions() {
+        assert_eq!(
+            condition(0xA, Comparison::LessEqual, 0x1F).to_string(),
+            "rA <= 0x001f"
+        );
+        assert_eq!(WatchKind::Execute.to_string(), "execute");
+    }
+
+    #[test]
+    fn describe_addresses() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.describe(0x0000), "start");
+        assert_eq!(debugger.describe(0x0008), "start+8");
+        assert_eq!(debugger.describe(0x0018), "add_one+4");
+        assert_eq!(debugger.source(0x0004).map(|source| source.line), Some(3));
+
+        debugger.symbols = Symbols::new();
+        assert_eq!(debugger.describe(0x0018), "0018");
+    }
+}
@@ -0,0 +1,558 @@
+use byteorder::{ByteOrder, LittleEndian};
+use instruction::DecodedInstruction::{Calli, Cx, Jmc, Jme, Jmpi, Jx};
+use instruction::Operation::*;
+use instruction::{Format, Instruction, Operation};
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use symbols::Symbols;
+
+// The number of bytes on each line of a data block.
+const DATA_LINE_LENGTH: usize = 8;
+
+// Formats the instruction in the syntax of the tchip16 assembler, which mash16 also uses.
+// Returns None if the word is not a valid instruction, or uses bits that the operation ignores.
+pub fn disassemble(instruction: &Instruction) -> Option<String> {
+    disassemble_with_labels(instruction, |_| None)
+}
+
+// The same as `disassemble`, except branch targets are named by `label` where it returns a name.
+pub fn disassemble_with_labels<F>(instruction: &Instruction, label: F) -> Option<String>
+where
+    F: Fn(u16) -> Option<String>,
+{
+    let operation = instruction.decode()?.operation();
+    let mnemonic = operation.mnemonic();
+    let format = operation.format();
+
+    let hhll = match branch_target(instruction) {
+        Some(target) => label(target).unwrap_or_else(|| format!("0x{:04X}", target)),
+        None => format!("0x{:04X}", instruction.hhll()),
+    };
+
+    let text = match format {
+        Format::None => mnemonic.to_string(),
+        Format::N => format!("{} 0x{:X}", mnemonic, instruction.n()),
+        Format::I => format!("{} {}", mnemonic, hhll),
+        Format::R => format!("{} r{:X}", mnemonic, instruction.x()),
+        Format::RN => format!(
+            "{} r{:X}, 0x{:X}",
+            mnemonic,
+            instruction.x(),
+            instruction.n()
+        ),
+        Format::RI => format!("{} r{:X}, {}", mnemonic, instruction.x(), hhll),
+        Format::RR => format!(
+            "{} r{:X}, r{:X}",
+            mnemonic,
+            instruction.x(),
+            instruction.y()
+        ),
+        Format::RRI => format!(
+            "{} r{:X}, r{:X}, {}",
+            mnemonic,
+            instruction.x(),
+            instruction.y(),
+            hhll
+        ),
+        Format::RRR => format!(
+            "{} r{:X}, r{:X}, r{:X}",
+            mnemonic,
+            instruction.x(),
+            instruction.y(),
+            instruction.z()
+        ),
+        Format::SpI => format!("{} sp, {}", mnemonic, hhll),
+        Format::CondI => format!(
+            "{}{} {}",
+            mnemonic,
+            instruction.decode_condition()?.suffix(),
+            hhll
+        ),
+        Format::Flip => format!(
+            "{} {}, {}",
+            mnemonic,
+            (instruction.hh() >> 1) & 1,
+            instruction.hh() & 1
+        ),
+        Format::Sng => format!(
+            "{} 0x{:X}{:X}, 0x{:04X}",
+            mnemonic,
+            instruction.a(),
+            instruction.d(),
+            instruction.hhll()
+        ),
+    };
+
+    Some(text)
+}
+
+// The address that a jump or call instruction can transfer control to.
+pub fn branch_target(instruction: &Instruction) -> Option<u16> {
+    match instruction.decode()? {
+        Jmpi { addr }
+        | Jmc { addr }
+        | Jx { addr, .. }
+        | Jme { addr, .. }
+        | Calli { addr }
+        | Cx { addr, .. } => Some(addr),
+        _ => None,
+    }
+}
+
+// NOTE: This is NOT an cpu operation, but it is used by `trace` to find the next instructions.
+fn falls_through(operation: &Operation) -> bool {
+    !matches!(*operation, JMPI | RET | JMPR)
+}
+
+// Words that are not instructions are formatted as data, in memory order, so they can still be
+// reassembled.
+pub fn disassemble_or_data(instruction: &Instruction) -> String {
+    disassemble(instruction).unwrap_or_else(|| {
+        let bytes = instruction.0.to_le_bytes();
+        format!(
+            "db 0x{:02X}, 0x{:02X}, 0x{:02X}, 0x{:02X}",
+            bytes[0], bytes[1], bytes[2], bytes[3]
+        )
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Entry {
+    Label(u16),
+    Instruction {
+        address: u16,
+        word: u32,
+        text: String,
+    },
+    Data {
+        address: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+// A disassembly of a whole rom, which reassembles to the same bytes. Labels are named by the
+// symbols where they have a name, and instructions show the source line they came from.
+#[derive(Debug, PartialEq)]
+pub struct Listing {
+    pub start_address: u16,
+    pub entries: Vec<Entry>,
+    pub symbols: Symbols,
+}
+
+impl Listing {
+    pub fn label(&self, address: u16) -> String {
+        self.symbols
+            .label(address)
+            .map(String::from)
+            .unwrap_or_else(|| label_name(address))
+    }
+}
+
+pub fn label_name(address: u16) -> String {
+    format!("label_{:04X}", address)
+}
+
+// Follows the control flow of the rom from the start address, so that only reachable bytes are
+// disassembled as instructions. Everything else, such as sprites and palettes, becomes data.
+pub fn trace(content: &[u8], start_address: u16) -> Listing {
+    trace_with_symbols(content, start_address, Symbols::new())
+}
+
+// The same as `trace`, except every label in the symbols is also placed in the listing, such as
+// the labels of sprites that control flow never reaches.
+pub fn trace_with_symbols(content: &[u8], start_address: u16, symbols: Symbols) -> Listing {
+    let instruction_at = |address: usize| {
+        content
+            .get(address..address + 4)
+            .map(|bytes| Instruction::new(LittleEndian::read_u32(bytes)))
+            .filter(|instruction| disassemble(instruction).is_some())
+    };
+
+    let mut is_code = vec![false; content.len()];
+    let mut starts = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut pending = VecDeque::new();
+    pending.push_back(start_address as usize);
+    targets.insert(start_address as usize);
+
+    while let Some(address) = pending.pop_front() {
+        if starts.contains(&address) {
+            continue;
+        }
+
+        // Stop at invalid words, and at instructions that would overlap ones already found.
+        let instruction = match instruction_at(address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        if is_code[address..address + 4].iter().any(|&code| code) {
+            continue;
+        }
+
+        starts.insert(address);
+        for code in &mut is_code[address..address + 4] {
+            *code = true;
+        }
+
+        if let Some(target) = branch_target(&instruction) {
+            targets.insert(target as usize);
+            pending.push_back(target as usize);
+        }
+        if instruction
+            .decode()
+            .is_some_and(|decoded| falls_through(&decoded.operation()))
+        {
+            pending.push_back(address + 4);
+        }
+    }
+
+    targets.extend(symbols.labels().map(|(address, _)| address as usize));
+
+    // Labels can only be placed at the start of an instruction, or anywhere within data.
+    let labels: BTreeSet<usize> = targets
+        .into_iter()
+        .filter(|&target| target < content.len() && (starts.contains(&target) || !is_code[target]))
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut address = 0;
+    while address < content.len() {
+        if labels.contains(&address) {
+            entries.push(Entry::Label(address as u16));
+        }
+
+        if starts.contains(&address) {
+            let instruction = instruction_at(address).unwrap();
+            let text = disassemble_with_labels(&instruction, |target| {
+                if labels.contains(&(target as usize)) {
+                    Some(
+                        symbols
+                            .label(target)
+                            .map_or_else(|| label_name(target), String::from),
+                    )
+                } else {
+                    None
+                }
+            });
+            entries.push(Entry::Instruction {
+                address: address as u16,
+                word: instruction.0,
+                text: text.unwrap(),
+            });
+            address += 4;
+            continue;
+        }
+
+        // Data runs until the next instruction or label, in lines of at most 8 bytes.
+        let mut end = address + 1;
+        while end < content.len()
+            && end - address < DATA_LINE_LENGTH
+            && !is_code[end]
+            && !labels.contains(&end)
+        {
+            end += 1;
+        }
+        entries.push(Entry::Data {
+            address: address as u16,
+            bytes: content[address..end].to_vec(),
+        });
+        address = end;
+    }
+
+    Listing {
+        start_address,
+        entries,
+        symbols,
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "; start address {}", self.label(self.start_address))?;
+        for entry in &self.entries {
+            let address = match *entry {
+                Entry::Label(address) => {
+                    writeln!(f, "\n{}:", self.label(address))?;
+                    continue;
+                }
+                Entry::Instruction {
+                    address,
+                    word,
+                    ref text,
+                } => {
+                    write!(f, "    {:<28}; {:04x}: {:08x}", text, address, word)?;
+                    address
+                }
+                Entry::Data { address, ref bytes } => {
+                    let bytes: Vec<String> =
+                        bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                    // A full line of data is 46 characters, and is still followed by a space.
+                    write!(f, "    db {:<47}; {:04x}", bytes.join(", "), address)?;
+                    address
+                }
+            };
+
+            match self.symbols.source(address) {
+                Some(source) => writeln!(f, " {}:{}", source.path, source.line)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(word: u32) -> Option<String> {
+        disassemble(&Instruction::new(word))
+    }
+
+    #[test]
+    fn formats_operands() {
+        assert_eq!(text(0x0000_0001).unwrap(), "cls");
+        assert_eq!(text(0x0003_0003).unwrap(), "bgc 0x3");
+        assert_eq!(text(0x0404_0004).unwrap(), "spr 0x0404");
+        assert_eq!(text(0x1234_A505).unwrap(), "drw r5, rA, 0x1234");
+        assert_eq!(text(0x000C_BA06).unwrap(), "drw rA, rB, rC");
+        assert_eq!(text(0x0220_0020).unwrap(), "ldi r0, 0x0220");
+        assert_eq!(text(0xFDF0_0021).unwrap(), "ldi sp, 0xFDF0");
+        assert_eq!(text(0x0000_F124).unwrap(), "mov r1, rF");
+        assert_eq!(text(0x0003_0FB0).unwrap(), "shl rF, 0x3");
+        assert_eq!(text(0x0000_0216).unwrap(), "jmp r2");
+        assert_eq!(text(0x0000_00C2).unwrap(), "pushall");
+    }
+
+    #[test]
+    fn formats_conditions() {
+        assert_eq!(text(0x0100_0012).unwrap(), "jz 0x0100");
+        assert_eq!(text(0x0100_0112).unwrap(), "jnz 0x0100");
+        assert_eq!(text(0x0100_0E17).unwrap(), "cle 0x0100");
+        assert_eq!(text(0x0100_0F12), None);
+    }
+
+    #[test]
+    fn formats_flip_and_sng() {
+        assert_eq!(text(0x0000_0008).unwrap(), "flip 0, 0");
+        assert_eq!(text(0x0100_0008).unwrap(), "flip 0, 1");
+        assert_eq!(text(0x0200_0008).unwrap(), "flip 1, 0");
+        assert_eq!(text(0x5678_3A0E).unwrap(), "sng 0x3A, 0x5678");
+    }
+
+    #[test]
+    fn rejects_unused_bits() {
+        assert_eq!(text(0x0000_0100), None);
+        assert_eq!(text(0x0001_1016), None);
+        assert_eq!(text(0x0400_0008), None);
+        assert_eq!(text(0x0000_00FF), None);
+    }
+
+    #[test]
+    fn formats_labels() {
+        let label = |address| Some(label_name(address));
+        let text = |word| disassemble_with_labels(&Instruction::new(word), label).unwrap();
+        assert_eq!(text(0x0100_0010), "jmp label_0100");
+        assert_eq!(text(0x0100_0112), "jnz label_0100");
+        assert_eq!(text(0x0100_2113), "jme r1, r2, label_0100");
+        assert_eq!(text(0x0100_0014), "call label_0100");
+        assert_eq!(text(0x0100_0020), "ldi r0, 0x0100");
+        assert_eq!(text(0x0100_2105), "drw r1, r2, 0x0100");
+    }
+
+    fn bytes(words: &[u32]) -> Vec<u8> {
+        let mut content = vec![0; words.len() * 4];
+        LittleEndian::write_u32_into(words, &mut content);
+        content
+    }
+
+    #[test]
+    fn trace_separates_code_and_data() {
+        let content = bytes(&[
+            0x000C_0014, // call label_000C
+            0x0004_0010, // jmp label_0004
+            0xFFFF_FFFF, // data
+            0x0014_0005, // drw r0, r0, 0x0014
+            0x0000_0015, // ret
+            0x1234_5678, // data
+        ]);
+
+        let listing = trace(&content, 0x0000);
+        assert_eq!(
+            listing.entries,
+            vec![
+                Entry::Label(0x0000),
+                Entry::Instruction {
+                    address: 0x0000,
+                    word: 0x000C_0014,
+                    text: "call label_000C".to_string(),
+                },
+                Entry::Label(0x0004),
+                Entry::Instruction {
+                    address: 0x0004,
+                    word: 0x0004_0010,
+                    text: "jmp label_0004".to_string(),
+                },
+                Entry::Data {
+                    address: 0x0008,
+                    bytes: vec![0xFF; 4],
+                },
+                Entry::Label(0x000C),
+                Entry::Instruction {
+                    address: 0x000C,
+                    word: 0x0014_0005,
+                    text: "drw r0, r0, 0x0014".to_string(),
+                },
+                Entry::Instruction {
+                    address: 0x0010,
+                    word: 0x0000_0015,
+                    text: "ret".to_string(),
+                },
+                Entry::Data {
+                    address: 0x0014,
+                    bytes: vec![0x78, 0x56, 0x34, 0x12],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_ignores_overlapping_and_outside_targets() {
+        let content = bytes(&[
+            0x0002_0012, // jz 0x0002, which is inside this instruction
+            0x2000_0010, // jmp 0x2000, which is outside the rom
+        ]);
+
+        let listing = trace(&content, 0x0000);
+        assert_eq!(listing.entries.len(), 3);
+        assert_eq!(
+            listing.entries[1],
+            Entry::Instruction {
+                address: 0x0000,
+                word: 0x0002_0012,
+                text: "jz 0x0002".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn trace_names_labels_with_symbols() {
+        let content = bytes(&[
+            0x000C_0014, // call draw_paddle
+            0x0004_0010, // jmp label_0004
+            0xFFFF_FFFF, // data
+            0x0000_0015, // ret
+            0x1234_5678, // sprite
+            0x9ABC_DEF0,
+        ]);
+
+        let mut symbols = Symbols::new();
+        symbols.add_label(0x0000, "start");
+        symbols.add_label(0x000C, "draw_paddle");
+        symbols.add_label(0x0010, "sprite");
+        symbols.add_line(0x0000, "pong.s", 3);
+        symbols.add_line(0x0010, "pong.s", 9);
+
+        let listing = trace_with_symbols(&content, 0x0000, symbols);
+        assert_eq!(
+            listing.to_string(),
+            "; start address start\n\
+             \n\
+             start:\n    \
+             call draw_paddle            ; 0000: 000c0014 pong.s:3\n\
+             \n\
+             label_0004:\n    \
+             jmp label_0004              ; 0004: 00040010\n    \
+             db 0xFF, 0xFF, 0xFF, 0xFF                         ; 0008\n\
+             \n\
+             draw_paddle:\n    \
+             ret                         ; 000c: 00000015\n\
+             \n\
+             sprite:\n    \
+             db 0x78, 0x56, 0x34, 0x12, 0xF0, 0xDE, 0xBC, 0x9A ; 0010 pong.s:9\n"
+        );
+    }
+
+    // Every byte of the rom appears in the listing exactly once, in order.
+    #[test]
+    fn trace_covers_sample_roms() {
+        use rom::Rom;
+        use std::fs::{self, File};
+        use std::path::Path;
+
+        let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms");
+        for entry in fs::read_dir(roms).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "c16") {
+                continue;
+            }
+
+            let rom = Rom::new(File::open(&path).unwrap()).unwrap();
+            let listing = trace(&rom.content, rom.start_address);
+
+            let mut content = Vec::new();
+            for entry in &listing.entries {
+                match *entry {
+                    Entry::Label(address) => assert_eq!(address as usize, content.len()),
+                    Entry::Instruction { address, word, .. } => {
+                        assert_eq!(address as usize, content.len());
+                        content.extend_from_slice(&bytes(&[word]));
+                    }
+                    Entry::Data { address, ref bytes } => {
+                        assert_eq!(address as usize, content.len());
+                        content.extend_from_slice(bytes);
+                    }
+                }
+            }
+            assert_eq!(content, rom.content, "{:?}", path);
+        }
+    }
+
+    // The sample roms come with listings from an earlier disassembler, with a line such as
+    // `0038 DRWR 6, 6, 6` for each word. Words that it could not decode only have an address.
+    #[test]
+    fn decode_sample_listings() {
+        use byteorder::{LittleEndian, ReadBytesExt};
+        use rom::Rom;
+        use std::fs::{self, File};
+        use std::path::Path;
+
+        let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms");
+        for entry in fs::read_dir(roms).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "asm") {
+                continue;
+            }
+
+            let rom = Rom::new(File::open(path.with_extension("c16")).unwrap()).unwrap();
+            for line in fs::read_to_string(&path).unwrap().lines() {
+                let mut fields = line.split_whitespace();
+                let (address, name) = match (fields.next(), fields.next()) {
+                    (Some(address), Some(name)) => (address, name),
+                    _ => continue,
+                };
+                let address = usize::from_str_radix(address, 16).unwrap();
+                let word = (&rom.content[address..])
+                    .read_u32::<LittleEndian>()
+                    .unwrap();
+                let operation = Instruction::new(word).decode_operation();
+                assert_eq!(
+                    operation.map(|operation| format!("{:?}", operation)),
+                    Some(name.to_string()),
+                    "{:?} at {:04x}",
+                    path,
+                    address
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn formats_data() {
+        assert_eq!(
+            disassemble_or_data(&Instruction::new(0x1234_56FF)),
+            "db 0xFF, 0x56, 0x34, 0x12"
+        );
+        assert_eq!(disassemble_or_data(&Instruction::new(0x0000_0015)), "ret");
+    }
+}
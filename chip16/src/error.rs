@@ -0,0 +1,66 @@
+use profile::ExecutionProfile;
+
+// Every error records the address of the instruction that caused it, and where it is useful the
+// raw instruction word as well, so that a frontend can show exactly what the rom was doing.
+#[derive(Debug, Fail, PartialEq)]
+pub enum EmulationError {
+    #[fail(display = "invalid opcode in {:08x} at {:04x}", word, pc)]
+    InvalidOpcode { pc: u16, word: u32 },
+
+    #[fail(display = "program counter overflowed at {:04x}", pc)]
+    PcOverflow { pc: u16 },
+
+    #[fail(
+        display = "stack overflow at {:04x} with the stack pointer at {:04x}",
+        pc, sp
+    )]
+    StackOverflow { pc: u16, sp: u16 },
+
+    #[fail(
+        display = "stack underflow at {:04x} with the stack pointer at {:04x}",
+        pc, sp
+    )]
+    StackUnderflow { pc: u16, sp: u16 },
+
+    #[fail(display = "divide by zero in {:08x} at {:04x}", word, pc)]
+    DivideByZero { pc: u16, word: u32 },
+
+    #[fail(
+        display = "memory access out of bounds at {:04x} by {:08x} at {:04x}",
+        address, word, pc
+    )]
+    MemoryOutOfBounds { pc: u16, word: u32, address: u16 },
+
+    #[fail(
+        display = "{:08x} at {:04x} is not defined by the {} specification",
+        word, pc, profile
+    )]
+    UnsupportedOpcode {
+        pc: u16,
+        word: u32,
+        profile: ExecutionProfile,
+    },
+}
+
+// Faults are raised by individual operations, which do not know where they were fetched from.
+// The cpu turns them into an `EmulationError` once it has the full context.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Fault {
+    StackOverflow,
+    StackUnderflow,
+    DivideByZero,
+    MemoryOutOfBounds(u16),
+}
+
+impl Fault {
+    pub(crate) fn into_error(self, pc: u16, word: u32, sp: u16) -> EmulationError {
+        match self {
+            Fault::StackOverflow => EmulationError::StackOverflow { pc, sp },
+            Fault::StackUnderflow => EmulationError::StackUnderflow { pc, sp },
+            Fault::DivideByZero => EmulationError::DivideByZero { pc, word },
+            Fault::MemoryOutOfBounds(address) => {
+                EmulationError::MemoryOutOfBounds { pc, word, address }
+            }
+        }
+    }
+}
@@ -28,4 +28,227 @@ impl Flags {
 
         signed_result as u16
     }
+
+    // NOTE: Used by subi, subr2, subr3, cmpi and cmpr.
+    pub fn sub(&mut self, a: u16, b: u16) -> u16 {
+        let (_, carry) = u16::overflowing_sub(a, b);
+        let (signed_result, overflow) = i16::overflowing_sub(a as i16, b as i16);
+
+        self.carry = carry;
+        self.zero = signed_result == 0;
+        self.overflow = overflow;
+        self.negative = signed_result < 0;
+
+        signed_result as u16
+    }
+
+    // NOTE: Used by andi, andr2, andr3, tsti and tstr.
+    pub fn and(&mut self, a: u16, b: u16) -> u16 {
+        self.logical(a & b)
+    }
+
+    pub fn or(&mut self, a: u16, b: u16) -> u16 {
+        self.logical(a | b)
+    }
+
+    pub fn xor(&mut self, a: u16, b: u16) -> u16 {
+        self.logical(a ^ b)
+    }
+
+    pub fn not(&mut self, a: u16) -> u16 {
+        self.logical(!a)
+    }
+
+    pub fn neg(&mut self, a: u16) -> u16 {
+        self.logical(a.wrapping_neg())
+    }
+
+    pub fn shl(&mut self, a: u16, n: u16) -> u16 {
+        self.logical(a.checked_shl(n as u32).unwrap_or(0))
+    }
+
+    pub fn shr(&mut self, a: u16, n: u16) -> u16 {
+        self.logical(a.checked_shr(n as u32).unwrap_or(0))
+    }
+
+    // Arithmetic shifts keep the sign bit, so shifting by 16 or more fills every bit with it.
+    pub fn sar(&mut self, a: u16, n: u16) -> u16 {
+        let n = u16::min(n, 15);
+        self.logical(((a as i16) >> n) as u16)
+    }
+
+    // The carry flag is set when the unsigned result does not fit into 16 bits.
+    pub fn mul(&mut self, a: u16, b: u16) -> u16 {
+        let result = a as u32 * b as u32;
+
+        self.carry = result > u16::MAX as u32;
+        self.logical(result as u16)
+    }
+
+    // The carry flag is set when the division leaves a remainder.
+    pub fn div(&mut self, a: u16, b: u16) -> Option<u16> {
+        let result = a.checked_div(b)?;
+
+        self.carry = !a.is_multiple_of(b);
+        Some(self.logical(result))
+    }
+
+    // The sign of the result follows the divisor.
+    pub fn modulo(&mut self, a: u16, b: u16) -> Option<u16> {
+        if b == 0 {
+            return None;
+        }
+
+        let remainder = (a as i16).wrapping_rem(b as i16);
+        let result = if remainder != 0 && (remainder < 0) != ((b as i16) < 0) {
+            remainder.wrapping_add(b as i16)
+        } else {
+            remainder
+        };
+
+        Some(self.logical(result as u16))
+    }
+
+    // The sign of the result follows the dividend.
+    pub fn rem(&mut self, a: u16, b: u16) -> Option<u16> {
+        if b == 0 {
+            return None;
+        }
+
+        let remainder = (a as i16).wrapping_rem(b as i16);
+
+        Some(self.logical(remainder as u16))
+    }
+
+    // Logical operations only affect the zero and negative flags.
+    fn logical(&mut self, result: u16) -> u16 {
+        self.zero = result == 0;
+        self.negative = (result as i16) < 0;
+
+        result
+    }
+}
+
+// Flags are stored on the stack as a single byte, with the layout N O 0 0 0 Z C 0.
+impl From<u8> for Flags {
+    fn from(byte: u8) -> Flags {
+        Flags {
+            carry: byte & 0x02 != 0,
+            zero: byte & 0x04 != 0,
+            overflow: byte & 0x40 != 0,
+            negative: byte & 0x80 != 0,
+        }
+    }
+}
+
+impl<'a> From<&'a Flags> for u8 {
+    fn from(flags: &'a Flags) -> u8 {
+        (flags.carry as u8) << 1
+            | (flags.zero as u8) << 2
+            | (flags.overflow as u8) << 6
+            | (flags.negative as u8) << 7
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_round_trip() {
+        let flags = Flags {
+            carry: true,
+            zero: false,
+            overflow: true,
+            negative: false,
+        };
+        assert_eq!(u8::from(&flags), 0x42);
+        assert_eq!(Flags::from(0x42), flags);
+        assert_eq!(Flags::from(0xFF), Flags::from(0xC6));
+    }
+
+    #[test]
+    fn sub_borrow_and_overflow() {
+        let mut flags = Flags::new();
+        assert_eq!(flags.sub(0x0000, 0x0001), 0xFFFF);
+        assert!(flags.carry && !flags.zero && !flags.overflow && flags.negative);
+
+        assert_eq!(flags.sub(0x8000, 0x0001), 0x7FFF);
+        assert!(!flags.carry && !flags.zero && flags.overflow && !flags.negative);
+
+        assert_eq!(flags.sub(0x1234, 0x1234), 0x0000);
+        assert!(!flags.carry && flags.zero && !flags.overflow && !flags.negative);
+    }
+
+    #[test]
+    fn logical_preserves_carry_and_overflow() {
+        let mut flags = Flags::new();
+        flags.carry = true;
+        flags.overflow = true;
+
+        assert_eq!(flags.and(0xF0F0, 0x0F0F), 0x0000);
+        assert!(flags.carry && flags.zero && flags.overflow && !flags.negative);
+
+        assert_eq!(flags.or(0x8000, 0x0001), 0x8001);
+        assert!(!flags.zero && flags.negative);
+
+        assert_eq!(flags.xor(0xFFFF, 0xFFFF), 0x0000);
+        assert!(flags.zero);
+
+        assert_eq!(flags.not(0x0000), 0xFFFF);
+        assert!(flags.negative);
+
+        assert_eq!(flags.neg(0x0001), 0xFFFF);
+        assert!(flags.negative);
+    }
+
+    #[test]
+    fn shifts() {
+        let mut flags = Flags::new();
+        assert_eq!(flags.shl(0x0001, 15), 0x8000);
+        assert_eq!(flags.shl(0x0001, 16), 0x0000);
+        assert_eq!(flags.shr(0x8000, 15), 0x0001);
+        assert_eq!(flags.sar(0x8000, 15), 0xFFFF);
+        assert_eq!(flags.sar(0x8000, 20), 0xFFFF);
+        assert_eq!(flags.sar(0x4000, 14), 0x0001);
+    }
+
+    #[test]
+    fn mul_carry() {
+        let mut flags = Flags::new();
+        assert_eq!(flags.mul(0x0100, 0x0100), 0x0000);
+        assert!(flags.carry && flags.zero);
+
+        assert_eq!(flags.mul(0x0010, 0x0010), 0x0100);
+        assert!(!flags.carry && !flags.zero);
+    }
+
+    #[test]
+    fn div_carry_on_remainder() {
+        let mut flags = Flags::new();
+        assert_eq!(flags.div(7, 2), Some(3));
+        assert!(flags.carry);
+
+        assert_eq!(flags.div(8, 2), Some(4));
+        assert!(!flags.carry);
+
+        assert_eq!(flags.div(8, 0), None);
+    }
+
+    #[test]
+    fn modulo_and_rem_signs() {
+        let mut flags = Flags::new();
+        let minus = |n: i16| n as u16;
+
+        assert_eq!(flags.modulo(minus(-7), 3), Some(2));
+        assert_eq!(flags.modulo(7, minus(-3)), Some(minus(-2)));
+        assert_eq!(flags.rem(minus(-7), 3), Some(minus(-1)));
+        assert_eq!(flags.rem(7, minus(-3)), Some(1));
+
+        assert_eq!(flags.modulo(minus(i16::MIN), minus(-1)), Some(0));
+        assert!(flags.zero);
+
+        assert_eq!(flags.modulo(1, 0), None);
+        assert_eq!(flags.rem(1, 0), None);
+    }
 }
@@ -0,0 +1,827 @@
+use cpu::CYCLES_PER_FRAME;
+use debugger::{Debugger, Stop, WatchKind, Watchpoint};
+use error::EmulationError;
+use failure::Error;
+use flags::Flags;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+// The registers in the order that `g` reads them: r0 to rF, then pc, sp and the flags. Each is
+// 16 bits wide and sent little endian, and the flags have the layout they are pushed with.
+const REGISTER_COUNT: usize = 19;
+const PC: usize = 16;
+const SP: usize = 17;
+const FLAGS: usize = 18;
+
+// Continuing runs the cpu a frame's worth of cycles at a time, and checks for an interrupt from
+// gdb in between.
+const RUN_CYCLES: u64 = CYCLES_PER_FRAME as u64;
+
+// The byte that gdb sends outside of a packet to interrupt the target.
+const INTERRUPT: u8 = 0x03;
+
+// The signals that stop replies report.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+// What a packet asks the stub to do, once it has been answered.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    // Reply, then close the connection.
+    Detach(String),
+    Kill,
+}
+
+// A stub for the gdb remote serial protocol, which lets gdb and other frontends that speak it
+// inspect and run the cpu of a debugger over a socket. There is a single thread, which is always
+// stopped when a packet is handled.
+pub struct GdbStub {
+    pub debugger: Debugger,
+    // The reply to `?`, which is the reply to the last step or continue.
+    stop: String,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> GdbStub {
+        GdbStub {
+            debugger,
+            stop: format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    // Handles packets from gdb until it detaches, kills the target or disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> Result<(), Error> {
+        let mut connection = Connection::new(stream)?;
+        while let Some(packet) = connection.read_packet()? {
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            match self.handle(&packet) {
+                Action::Reply(reply) => {
+                    connection.send(reply.as_bytes())?;
+                    // The reply to this packet is the last one that is acknowledged.
+                    if packet == "QStartNoAckMode" {
+                        connection.acknowledge = false;
+                    }
+                }
+                Action::Step => {
+                    let stop = self.debugger.step_into();
+                    self.stopped(&mut connection, stop)?;
+                }
+                Action::Continue => {
+                    // Each run checks for breakpoints before it stops at the cycle limit, so the
+                    // next run can execute the instruction at the program counter straight away.
+                    let stop = loop {
+                        match self.debugger.run(RUN_CYCLES) {
+                            Ok(Stop::CycleLimit) if !connection.interrupted()? => {}
+                            stop => break stop,
+                        }
+                    };
+                    self.stopped(&mut connection, stop)?;
+                }
+                Action::Detach(reply) => {
+                    connection.send(reply.as_bytes())?;
+                    return Ok(());
+                }
+                Action::Kill => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Action {
+        // The command is a single character, but a malformed packet can start with any.
+        let split = packet.chars().next().map_or(0, char::len_utf8);
+        let (command, args) = packet.split_at(split);
+        let reply = match command {
+            "?" => self.stop.clone(),
+            "g" => (0..REGISTER_COUNT)
+                .map(|register| encode_u16(self.register(register)))
+                .collect(),
+            "G" => self.write_registers(args),
+            "p" => match parse_hex(args) {
+                Some(register) if register < REGISTER_COUNT => encode_u16(self.register(register)),
+                _ => error(),
+            },
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match parse_address(args) {
+                        Some(address) => self.debugger.cpu.program_counter = address,
+                        None => return Action::Reply(error()),
+                    }
+                }
+                return if command == "s" {
+                    Action::Step
+                } else {
+                    Action::Continue
+                };
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "q" | "Q" => query(packet),
+            // There is only one thread, so every thread operation applies to it.
+            "H" | "T" => "OK".to_string(),
+            "D" => return Action::Detach("OK".to_string()),
+            "k" => return Action::Kill,
+            // An empty reply tells gdb that a packet is not supported.
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn register(&self, register: usize) -> u16 {
+        let cpu = &self.debugger.cpu;
+        match register {
+            PC => cpu.program_counter,
+            SP => cpu.stack_pointer,
+            FLAGS => u16::from(u8::from(&cpu.flags)),
+            register => *cpu.registers.get(register),
+        }
+    }
+
+    fn set_register(&mut self, register: usize, value: u16) {
+        let cpu = &mut self.debugger.cpu;
+        match register {
+            PC => cpu.program_counter = value,
+            SP => cpu.stack_pointer = value,
+            FLAGS => cpu.flags = Flags::from(value as u8),
+            register => *cpu.registers.get_mut(register) = value,
+        }
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let values = match decode_bytes(args) {
+            Some(ref bytes) if bytes.len() == REGISTER_COUNT * 2 => bytes
+                .chunks(2)
+                .map(|pair| u16::from(pair[0]) | u16::from(pair[1]) << 8)
+                .collect::<Vec<_>>(),
+            _ => return error(),
+        };
+        for (register, value) in values.into_iter().enumerate() {
+            self.set_register(register, value);
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let mut fields = args.splitn(2, '=');
+        let register = fields.next().and_then(parse_hex);
+        let value = fields.next().and_then(decode_bytes);
+        match (register, value) {
+            (Some(register), Some(ref bytes)) if register < REGISTER_COUNT && bytes.len() == 2 => {
+                self.set_register(register, u16::from(bytes[0]) | u16::from(bytes[1]) << 8);
+                "OK".to_string()
+            }
+            _ => error(),
+        }
+    }
+
+    // Reads as much of the memory as exists, which can be less than gdb asks for.
+    fn read_memory(&self, args: &str) -> String {
+        let (address, length) = match parse_range(args) {
+            Some(range) => range,
+            None => return error(),
+        };
+        let end = (address as usize).saturating_add(length).min(0x10000);
+        match self
+            .debugger
+            .cpu
+            .memory
+            .read_slice(address, end - address as usize)
+        {
+            Some(bytes) if !bytes.is_empty() || length == 0 => encode_bytes(bytes),
+            _ => error(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let mut fields = args.splitn(2, ':');
+        let range = fields.next().and_then(parse_range);
+        let data = fields.next().and_then(decode_bytes);
+        match (range, data) {
+            (Some((address, length)), Some(data)) if data.len() == length => {
+                match self.debugger.cpu.memory.write_slice(address, &data) {
+                    Some(()) => "OK".to_string(),
+                    None => error(),
+                }
+            }
+            _ => error(),
+        }
+    }
+
+    // Inserts or removes a breakpoint or watchpoint. Software and hardware breakpoints are the
+    // same, and the kind of a breakpoint is ignored, as every instruction is four bytes long.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let fields: Vec<_> = args.split(',').collect();
+        let (kind, address, length) = match *fields {
+            [kind, address, length] => match (parse_address(address), parse_hex(length)) {
+                (Some(address), Some(length)) => (kind, address, length),
+                _ => return error(),
+            },
+            _ => return error(),
+        };
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        let end = match (address as usize).checked_add(length.max(1) - 1) {
+            Some(end) if end <= 0xFFFF => end,
+            _ => return error(),
+        };
+        let watchpoint = Watchpoint {
+            kind: watch_kind,
+            start: address,
+            end: end as u16,
+        };
+        if insert {
+            self.debugger.add_watchpoint(watchpoint);
+        } else {
+            self.debugger.remove_watchpoint(&watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    // Tells gdb why the cpu stopped. Errors are also written to its console, as the signal alone
+    // does not say much.
+    fn stopped(
+        &mut self,
+        connection: &mut Connection,
+        stop: Result<Stop, EmulationError>,
+    ) -> io::Result<()> {
+        // Opcodes outside of the rom's profile, with `ProfilePolicy::Warn`, are shown in gdb's
+        // console like errors are, but do not stop the cpu.
+        for warning in self.debugger.cpu.take_warnings() {
+            let message = encode_bytes(format!("warning: {}\n", warning).as_bytes());
+            connection.send(format!("O{}", message).as_bytes())?;
+        }
+        self.stop = match stop {
+            Ok(Stop::Watchpoint {
+                watchpoint,
+                address,
+            }) => match watchpoint.kind {
+                WatchKind::Write => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+                WatchKind::Read => format!("T{:02x}rwatch:{:x};", SIGTRAP, address),
+                WatchKind::Access => format!("T{:02x}awatch:{:x};", SIGTRAP, address),
+                WatchKind::Execute => format!("S{:02x}", SIGTRAP),
+            },
+            // Continuing only stops at the cycle limit when gdb interrupts it.
+            Ok(Stop::CycleLimit) => format!("S{:02x}", SIGINT),
+            Ok(_) => format!("S{:02x}", SIGTRAP),
+            Err(error) => {
+                let message = encode_bytes(format!("{}\n", error).as_bytes());
+                connection.send(format!("O{}", message).as_bytes())?;
+                let signal = match error {
+                    EmulationError::InvalidOpcode { .. }
+                    | EmulationError::UnsupportedOpcode { .. } => SIGILL,
+                    EmulationError::DivideByZero { .. } => SIGFPE,
+                    _ => SIGSEGV,
+                };
+                format!("S{:02x}", signal)
+            }
+        };
+        connection.send(self.stop.as_bytes())
+    }
+}
+
+// A connection to gdb, which frames packets and acknowledges them.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    // Packets are acknowledged with `+` until gdb turns it off with `QStartNoAckMode`.
+    acknowledge: bool,
+    // The last packet sent, which is sent again if gdb asks for it with `-`.
+    last: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        // Packets are small and gdb waits for each reply, so they are sent straight away.
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            acknowledge: true,
+            last: Vec::new(),
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // Reads the next packet, or None if gdb disconnected. Acknowledgements and interrupts outside
+    // of a packet are skipped, as the cpu is already stopped.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => {
+                    let last = self.last.clone();
+                    self.write_packet(&last)?;
+                    continue;
+                }
+                Some(_) => continue,
+            }
+
+            let mut packet = Vec::new();
+            let mut checksum = 0u8;
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => {
+                        checksum = checksum.wrapping_add(byte);
+                        packet.push(byte);
+                    }
+                }
+            }
+            let mut expected = [0; 2];
+            self.reader.read_exact(&mut expected)?;
+            let expected = String::from_utf8_lossy(&expected).into_owned();
+
+            if !self.acknowledge {
+                return Ok(Some(unescape(&packet)));
+            }
+            if parse_hex(&expected) == Some(checksum as usize) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(unescape(&packet)));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.last = packet.to_vec();
+        self.write_packet(packet)
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let checksum = packet.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let mut data = Vec::with_capacity(packet.len() + 4);
+        data.push(b'$');
+        data.extend_from_slice(packet);
+        data.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+        self.writer.write_all(&data)
+    }
+
+    // Checks, without waiting, whether gdb sent an interrupt. A closed connection interrupts the
+    // cpu as well, so that it stops running.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if self.reader.buffer().is_empty() {
+            self.reader.get_ref().set_nonblocking(true)?;
+            let filled = self.reader.fill_buf().map(|buffer| buffer.is_empty());
+            self.reader.get_ref().set_nonblocking(false)?;
+            match filled {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(error) => return Err(error),
+            }
+        }
+        // Only an interrupt is expected while the cpu runs. Anything before it, such as a late
+        // acknowledgement, is dropped so that it cannot hide an interrupt sent after it.
+        let buffer = self.reader.buffer();
+        let (consumed, interrupted) = match buffer.iter().position(|&byte| byte == INTERRUPT) {
+            Some(index) => (index + 1, true),
+            None => (buffer.len(), false),
+        };
+        self.reader.consume(consumed);
+        Ok(interrupted)
+    }
+}
+
+// Answers the general queries that gdb makes when it connects.
+fn query(packet: &str) -> String {
+    let name = packet.split(':').next().unwrap_or(packet);
+    match name {
+        "qSupported" => "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+".to_string(),
+        "qXfer" => match *packet.splitn(5, ':').collect::<Vec<_>>() {
+            [_, "features", "read", "target.xml", range] => {
+                read_object(target_description().as_bytes(), range)
+            }
+            [_, "features", "read", _, _] => "E00".to_string(),
+            _ => String::new(),
+        },
+        "QStartNoAckMode" => "OK".to_string(),
+        // The cpu was already running when gdb attached, and there is only one thread.
+        "qAttached" => "1".to_string(),
+        "qC" => "QC1".to_string(),
+        "qfThreadInfo" => "m1".to_string(),
+        "qsThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+// Describes the registers that `g` reads, so that gdb knows how to show them.
+fn target_description() -> String {
+    let mut registers = String::new();
+    for register in 0..16 {
+        registers += &format!(
+            "    <reg name=\"r{:X}\" bitsize=\"16\" type=\"uint16\"/>\n",
+            register
+        );
+    }
+    format!(
+        "<?xml version=\"1.0\"?>
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">
+<target version=\"1.0\">
+  <feature name=\"org.chip16.core\">
+    <flags id=\"chip16_flags\" size=\"2\">
+      <field name=\"C\" start=\"1\" end=\"1\"/>
+      <field name=\"Z\" start=\"2\" end=\"2\"/>
+      <field name=\"O\" start=\"6\" end=\"6\"/>
+      <field name=\"N\" start=\"7\" end=\"7\"/>
+    </flags>
+{}    <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>
+    <reg name=\"sp\" bitsize=\"16\" type=\"data_ptr\"/>
+    <reg name=\"flags\" bitsize=\"16\" type=\"chip16_flags\"/>
+  </feature>
+</target>
+",
+        registers
+    )
+}
+
+// Reads part of an object for `qXfer`, from a range written as `offset,length`. The reply starts
+// with `l` if it holds the end of the object, and `m` if there is more to read.
+fn read_object(object: &[u8], range: &str) -> String {
+    let mut fields = range.splitn(2, ',');
+    let offset = fields.next().and_then(parse_hex);
+    let length = fields.next().and_then(parse_hex);
+    let (offset, length) = match (offset, length) {
+        (Some(offset), Some(length)) => (offset, length),
+        _ => return error(),
+    };
+    let start = offset.min(object.len());
+    let end = offset.saturating_add(length).min(object.len());
+    let more = if end < object.len() { 'm' } else { 'l' };
+    format!("{}{}", more, String::from_utf8_lossy(&object[start..end]))
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+// Numbers in packets are hexadecimal, without a prefix.
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    parse_hex(text)
+        .filter(|&address| address <= 0xFFFF)
+        .map(|address| address as u16)
+}
+
+// An address and a length, written as `addr,length`.
+fn parse_range(text: &str) -> Option<(u16, usize)> {
+    let mut fields = text.splitn(2, ',');
+    let address = parse_address(fields.next()?)?;
+    let length = parse_hex(fields.next()?)?;
+    Some((address, length))
+}
+
+fn encode_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn encode_u16(value: u16) -> String {
+    encode_bytes(&[value as u8, (value >> 8) as u8])
+}
+
+fn decode_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| parse_hex(text.get(index..index + 2)?).map(|byte| byte as u8))
+        .collect()
+}
+
+// Bytes in a packet that would be read as framing are escaped with `}` and xored with 0x20.
+fn unescape(packet: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(packet.len());
+    let mut escaped = false;
+    for &byte in packet {
+        if escaped {
+            bytes.push(byte ^ 0x20);
+            escaped = false;
+        } else if byte == b'}' {
+            escaped = true;
+        } else {
+            bytes.push(byte);
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+    use cpu::Cpu;
+    use profile::{ExecutionProfile, ProfilePolicy};
+    use rom::Rom;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    const PROGRAM: &str = "
+        start:   ldi r0, 0
+                 call add_one
+                 call add_one
+                 stm r0, result
+        done:    jmp done
+        add_one: addi r0, 1
+                 ret
+        result:  dw 0
+    ";
+
+    // A scripted gdb, connected to a stub that runs a program on another thread.
+    struct Client {
+        stream: TcpStream,
+        acknowledge: bool,
+        stub: Option<JoinHandle<()>>,
+    }
+
+    impl Client {
+        fn connect(source: &'static str) -> Client {
+            Client::connect_with(source, |_| {})
+        }
+
+        // Connects to a stub whose cpu has been set up by `setup` after loading the program.
+        fn connect_with<F>(source: &'static str, setup: F) -> Client
+        where
+            F: FnOnce(&mut Cpu) + Send + 'static,
+        {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let stub = thread::spawn(move || {
+                let assembly = assemble(source).unwrap();
+                let mut cpu = Cpu::new();
+                cpu.load(&Rom::from_content(assembly.content)).unwrap();
+                setup(&mut cpu);
+                let (stream, _) = listener.accept().unwrap();
+                GdbStub::new(Debugger::new(cpu)).serve(stream).unwrap();
+            });
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_nodelay(true).unwrap();
+            // A stub that stops replying fails the test instead of hanging it.
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            Client {
+                stream,
+                acknowledge: true,
+                stub: Some(stub),
+            }
+        }
+
+        fn read_byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        fn write(&mut self, packet: &str) {
+            let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+            write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+            if self.acknowledge {
+                assert_eq!(self.read_byte(), b'+');
+            }
+        }
+
+        fn read(&mut self) -> String {
+            assert_eq!(self.read_byte(), b'$');
+            let mut packet = Vec::new();
+            loop {
+                match self.read_byte() {
+                    b'#' => break,
+                    byte => packet.push(byte),
+                }
+            }
+            let checksum = [self.read_byte(), self.read_byte()];
+            let sum = packet.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            assert_eq!(String::from_utf8_lossy(&checksum), format!("{:02x}", sum));
+            if self.acknowledge {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(packet).unwrap()
+        }
+
+        fn send(&mut self, packet: &str) -> String {
+            self.write(packet);
+            self.read()
+        }
+
+        // Kills the target, and waits for the stub to finish serving.
+        fn kill(mut self) {
+            self.write("k");
+            self.stub.take().unwrap().join().unwrap();
+        }
+    }
+
+    #[test]
+    fn read_and_write_registers() {
+        let mut client = Client::connect(PROGRAM);
+        assert_eq!(
+            client.send("qSupported:swbreak+"),
+            "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+"
+        );
+        assert_eq!(client.send("?"), "S05");
+        assert_eq!(client.send("g"), format!("{}f0fd0000", "0000".repeat(17)));
+        assert_eq!(client.send("P3=3412"), "OK");
+        assert_eq!(client.send("p3"), "3412");
+        assert_eq!(client.send("P12=c600"), "OK");
+        assert_eq!(client.send("p12"), "c600");
+        assert_eq!(client.send("p13"), "E01");
+        assert_eq!(client.send("P3=34"), "E01");
+
+        // Only the bits of the flags that the cpu has are kept.
+        let registers: String = (0..FLAGS as u16)
+            .chain(Some(0x00C6))
+            .map(encode_u16)
+            .collect();
+        assert_eq!(client.send(&format!("G{}", registers)), "OK");
+        assert_eq!(client.send("g"), registers);
+        assert_eq!(client.send("G00"), "E01");
+        assert_eq!(client.send("vMustReplyEmpty"), "");
+        client.kill();
+    }
+
+    #[test]
+    fn read_target_description() {
+        let mut client = Client::connect(PROGRAM);
+        let mut description = String::new();
+        loop {
+            let reply = client.send(&format!(
+                "qXfer:features:read:target.xml:{:x},100",
+                description.len()
+            ));
+            description += &reply[1..];
+            if reply.starts_with('l') {
+                break;
+            }
+            assert!(reply.starts_with('m'));
+        }
+        assert_eq!(description, target_description());
+        assert_eq!(description.matches("<reg ").count(), REGISTER_COUNT);
+        assert!(description.contains("<reg name=\"rF\" bitsize=\"16\" type=\"uint16\"/>"));
+        assert!(description.contains("<reg name=\"flags\" bitsize=\"16\" type=\"chip16_flags\"/>"));
+
+        assert_eq!(client.send("qXfer:features:read:target.xml:ffff,100"), "l");
+        assert_eq!(client.send("qXfer:features:read:other.xml:0,100"), "E00");
+        assert_eq!(client.send("qXfer:features:read:target.xml:0"), "E01");
+        client.kill();
+    }
+
+    #[test]
+    fn read_and_write_memory() {
+        let mut client = Client::connect(PROGRAM);
+        assert_eq!(client.send("m0,4"), "20000000");
+        assert_eq!(client.send("M1c,2:ff02"), "OK");
+        assert_eq!(client.send("m1c,2"), "ff02");
+        assert_eq!(client.send("Mfffe,2:0102"), "OK");
+        assert_eq!(client.send("mfffe,4"), "0102");
+        assert_eq!(client.send("m10000,1"), "E01");
+        assert_eq!(client.send("Mffff,2:0102"), "E01");
+        assert_eq!(client.send("M0,2:01"), "E01");
+        assert_eq!(client.send("mfffe,ffffffffffffffff"), "0102");
+        assert_eq!(client.send("M1,ffffffffffffffff:00"), "E01");
+        client.kill();
+    }
+
+    #[test]
+    fn breakpoints_and_steps() {
+        let mut client = Client::connect(PROGRAM);
+        assert_eq!(client.send("Z0,14,4"), "OK");
+        assert_eq!(client.send("c"), "S05");
+        assert_eq!(client.send("p10"), "1400");
+        assert_eq!(client.send("s"), "S05");
+        assert_eq!(client.send("p10"), "1800");
+        assert_eq!(client.send("c"), "S05");
+        assert_eq!(client.send("p0"), "0100");
+        assert_eq!(client.send("z0,14,4"), "OK");
+
+        assert_eq!(client.send("Z2,1c,2"), "OK");
+        assert_eq!(client.send("c"), "T05watch:1c;");
+        assert_eq!(client.send("?"), "T05watch:1c;");
+        assert_eq!(client.send("m1c,2"), "0200");
+        assert_eq!(client.send("z2,1c,2"), "OK");
+        assert_eq!(client.send("Z3,ffff,2"), "E01");
+        assert_eq!(client.send("Z2,1,ffffffffffffffff"), "E01");
+        assert_eq!(client.send("Z5,0,4"), "");
+
+        // Stepping from an address moves the program counter there first.
+        assert_eq!(client.send("s14"), "S05");
+        assert_eq!(client.send("p0"), "0300");
+        client.kill();
+    }
+
+    #[test]
+    fn interrupt_a_hung_rom() {
+        let mut client = Client::connect(PROGRAM);
+        client.write("c");
+        client.stream.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!(client.read(), "S02");
+
+        // Stray bytes that arrive while the cpu runs do not stop an interrupt after them.
+        client.write("c");
+        client.stream.write_all(b"+-+").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        client.stream.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!(client.read(), "S02");
+        assert_eq!(client.send("p10"), "1000");
+        assert_eq!(client.send("D"), "OK");
+        client.stub.take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn report_errors() {
+        let mut client = Client::connect("ldi r1, 0\ndiv r0, r1");
+        client.write("c");
+        let message = client.read();
+        let message = decode_bytes(&message[1..]).unwrap();
+        assert_eq!(
+            String::from_utf8(message).unwrap(),
+            "divide by zero in 000010a1 at 0004\n"
+        );
+        assert_eq!(client.read(), "S08");
+
+        // The program counter stays on the faulting instruction, so continuing faults again.
+        assert_eq!(client.send("p10"), "0400");
+        client.write("c");
+        client.read();
+        assert_eq!(client.read(), "S08");
+        client.kill();
+    }
+
+    #[test]
+    fn report_warnings() {
+        let mut client = Client::connect_with("muli r0, 2\nmuli r1, 3", |cpu| {
+            cpu.profile = Some(ExecutionProfile::V1_0);
+            cpu.profile_policy = ProfilePolicy::Warn;
+        });
+        client.write("s");
+        let message = client.read();
+        let message = decode_bytes(&message[1..]).unwrap();
+        assert_eq!(
+            String::from_utf8(message).unwrap(),
+            "warning: 00020090 at 0000 is not defined by the 1.0 specification\n"
+        );
+        assert_eq!(client.read(), "S05");
+
+        // Each opcode is only reported once.
+        assert_eq!(client.send("s"), "S05");
+        client.kill();
+    }
+
+    #[test]
+    fn ignore_malformed_packets() {
+        let mut client = Client::connect(PROGRAM);
+        client.stream.write_all(b"$\xff#ff").unwrap();
+        assert_eq!(client.read_byte(), b'+');
+        assert_eq!(client.read(), "");
+        assert_eq!(client.send("?"), "S05");
+        client.kill();
+    }
+
+    #[test]
+    fn acknowledgements() {
+        let mut client = Client::connect(PROGRAM);
+        client.stream.write_all(b"$?#00").unwrap();
+        assert_eq!(client.read_byte(), b'-');
+
+        // A reply that gdb did not receive is sent again.
+        assert_eq!(client.send("?"), "S05");
+        client.stream.write_all(b"-").unwrap();
+        assert_eq!(client.read(), "S05");
+
+        assert_eq!(client.send("QStartNoAckMode"), "OK");
+        client.acknowledge = false;
+        assert_eq!(client.send("p10"), "0000");
+        client.kill();
+    }
+}
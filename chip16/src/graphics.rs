@@ -1,5 +1,7 @@
-const SCREEN_WIDTH: usize = 320;
-const SCREEN_HEIGHT: usize = 240;
+use palette::Palette;
+
+pub const SCREEN_WIDTH: usize = 320;
+pub const SCREEN_HEIGHT: usize = 240;
 
 // Each pixel is stored as two 4-bit values in a single byte.
 const ADDRESSABLE_VIDEO_MEMORY: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 2;
@@ -8,13 +10,13 @@ const ADDRESSABLE_VIDEO_MEMORY: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 2;
 pub type PixelDouble = u8;
 
 pub struct Graphics {
-    pub foreground_layer: [u8; ADDRESSABLE_VIDEO_MEMORY],
+    pub foreground_layer: [PixelDouble; ADDRESSABLE_VIDEO_MEMORY],
     pub background_layer: u8,
     pub sprite_width: u8,
     pub sprite_height: u8,
     pub vertical_flip: bool,
     pub horizontal_flip: bool,
-    // TODO: Add palette into this struct.
+    pub palette: Palette,
 }
 
 impl Graphics {
@@ -26,6 +28,7 @@ impl Graphics {
             sprite_height: 0,
             vertical_flip: false,
             horizontal_flip: false,
+            palette: Palette::new(),
         }
     }
 
@@ -34,21 +37,252 @@ impl Graphics {
         self.background_layer = 0;
     }
 
-    // These methods may not even be needed.
-    // Could be helpful for debugging.
-    // pub fn read_pixel(x: u16, y: u16) -> u8 {}
-    // pub fn write_pixel(x: u16, y: u16, color: u8) {}
+    // Pixels outside of the screen are ignored, which lets sprites be partially drawn off-screen.
+    pub fn read_pixel(&self, x: usize, y: usize) -> u8 {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return 0;
+        }
+
+        let pixel_double = self.foreground_layer[(y * SCREEN_WIDTH + x) / 2];
+        if x.is_multiple_of(2) {
+            pixel_double >> 4
+        } else {
+            pixel_double & 0x0F
+        }
+    }
+
+    pub fn write_pixel(&mut self, x: usize, y: usize, color: u8) {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return;
+        }
+
+        let pixel_double = &mut self.foreground_layer[(y * SCREEN_WIDTH + x) / 2];
+        if x.is_multiple_of(2) {
+            *pixel_double = (*pixel_double & 0x0F) | (color << 4);
+        } else {
+            *pixel_double = (*pixel_double & 0xF0) | (color & 0x0F);
+        }
+    }
+
+    // Sprites are stored row by row, with two pixels in each byte and the left pixel in the upper
+    // nibble. Color 0 is transparent, as are the pixels of any bytes missing from the end of the
+    // data. Returns true if any pixel was drawn over a non-zero pixel.
+    pub fn draw_sprite(&mut self, x_pos: i16, y_pos: i16, sprite_data: &[u8]) -> bool {
+        let width = self.sprite_width as usize * 2;
+        let height = self.sprite_height as usize;
+        let mut collision = false;
+
+        for row in 0..height {
+            for column in 0..width {
+                let index = row * self.sprite_width as usize + column / 2;
+                let pixel_double = sprite_data.get(index).cloned().unwrap_or(0);
+                let color = if column.is_multiple_of(2) {
+                    pixel_double >> 4
+                } else {
+                    pixel_double & 0x0F
+                };
+                if color == 0 {
+                    continue;
+                }
+
+                let column = if self.horizontal_flip {
+                    width - 1 - column
+                } else {
+                    column
+                };
+                let row = if self.vertical_flip {
+                    height - 1 - row
+                } else {
+                    row
+                };
+                let x = x_pos as isize + column as isize;
+                let y = y_pos as isize + row as isize;
+                if x < 0 || y < 0 {
+                    continue;
+                }
+
+                let (x, y) = (x as usize, y as usize);
+                collision |= self.read_pixel(x, y) != 0;
+                self.write_pixel(x, y, color);
+            }
+        }
+
+        collision
+    }
+
+    // Resolves the screen into RGBA pixels, row by row. Transparent pixels show the background
+    // color. The buffer must hold at least SCREEN_WIDTH * SCREEN_HEIGHT * 4 bytes.
+    pub fn render_rgba(&self, buffer: &mut [u8]) {
+        let pixels = buffer.chunks_mut(4).take(SCREEN_WIDTH * SCREEN_HEIGHT);
+        for (index, pixel) in pixels.enumerate() {
+            let color = match self.read_pixel(index % SCREEN_WIDTH, index / SCREEN_WIDTH) {
+                0 => self.background_layer,
+                color => color,
+            };
+
+            let color = self.palette.get(color);
+            pixel.copy_from_slice(&[color.r, color.g, color.b, 0xFF]);
+        }
+    }
+
+    // The number of bytes of sprite data used by draw_sprite.
+    pub fn sprite_size(&self) -> usize {
+        self.sprite_width as usize * self.sprite_height as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // pub fn read_pixel_double(x: u16, y: u16) -> u8 {}
-    // pub fn write_pixel_double(x: u16, y: u16, pixel_double: u8) {}
+    // A 4x4 sprite filled with color 1, with color 2 on the top-left pixel.
+    #[rustfmt::skip]
+    const SPRITE: &[u8] = &[
+        0x21, 0x11,
+        0x11, 0x11,
+        0x11, 0x11,
+        0x11, 0x11,
+    ];
 
-    // TODO: Create better variable names.
-    // TODO: Make sure this works with signed values. Allow drawing out-of-bounds.
-    // pub fn draw_sprite(x_pos: u16, y_pos: u16, sprite_data: &[u8]) {
-    //     for x in self.sprite_width {
-    //         for y in self.sprite_height {
-    //             self.write_pixel_double(i, j, sprite_data[x * width + height]);
-    //         }
-    //     }
-    // }
+    fn graphics() -> Graphics {
+        let mut graphics = Graphics::new();
+        graphics.sprite_width = 2;
+        graphics.sprite_height = 4;
+        graphics
+    }
+
+    fn count_pixels(graphics: &Graphics) -> usize {
+        let mut count = 0;
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                if graphics.read_pixel(x, y) != 0 {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn draw_inside_screen() {
+        let mut graphics = graphics();
+        assert!(!graphics.draw_sprite(10, 20, SPRITE));
+        assert_eq!(graphics.read_pixel(10, 20), 2);
+        assert_eq!(graphics.read_pixel(13, 23), 1);
+        assert_eq!(graphics.read_pixel(14, 23), 0);
+        assert_eq!(graphics.read_pixel(13, 24), 0);
+        assert_eq!(count_pixels(&graphics), 16);
+    }
+
+    #[test]
+    fn clip_left_edge() {
+        let mut graphics = graphics();
+        graphics.draw_sprite(-3, 0, SPRITE);
+        assert_eq!(graphics.read_pixel(0, 0), 1);
+        assert_eq!(graphics.read_pixel(1, 0), 0);
+        assert_eq!(count_pixels(&graphics), 4);
+    }
+
+    #[test]
+    fn clip_right_edge() {
+        let mut graphics = graphics();
+        graphics.draw_sprite(318, 0, SPRITE);
+        assert_eq!(graphics.read_pixel(318, 0), 2);
+        assert_eq!(graphics.read_pixel(319, 3), 1);
+        assert_eq!(graphics.read_pixel(0, 1), 0);
+        assert_eq!(count_pixels(&graphics), 8);
+    }
+
+    #[test]
+    fn clip_top_edge() {
+        let mut graphics = graphics();
+        graphics.draw_sprite(0, -3, SPRITE);
+        assert_eq!(graphics.read_pixel(0, 0), 1);
+        assert_eq!(graphics.read_pixel(3, 0), 1);
+        assert_eq!(graphics.read_pixel(0, 1), 0);
+        assert_eq!(count_pixels(&graphics), 4);
+    }
+
+    #[test]
+    fn clip_bottom_edge() {
+        let mut graphics = graphics();
+        graphics.draw_sprite(0, 238, SPRITE);
+        assert_eq!(graphics.read_pixel(0, 238), 2);
+        assert_eq!(graphics.read_pixel(3, 239), 1);
+        assert_eq!(count_pixels(&graphics), 8);
+    }
+
+    #[test]
+    fn clip_entirely_off_screen() {
+        let mut graphics = graphics();
+        assert!(!graphics.draw_sprite(-4, -4, SPRITE));
+        assert!(!graphics.draw_sprite(320, 240, SPRITE));
+        assert!(!graphics.draw_sprite(i16::MIN, i16::MAX, SPRITE));
+        assert_eq!(count_pixels(&graphics), 0);
+    }
+
+    #[test]
+    fn draw_short_sprite() {
+        let mut graphics = graphics();
+        assert!(!graphics.draw_sprite(0, 0, &SPRITE[..3]));
+        assert_eq!(graphics.read_pixel(0, 0), 2);
+        assert_eq!(graphics.read_pixel(1, 1), 1);
+        assert_eq!(graphics.read_pixel(2, 1), 0);
+        assert_eq!(count_pixels(&graphics), 6);
+        assert!(!graphics.draw_sprite(10, 10, &[]));
+        assert_eq!(count_pixels(&graphics), 6);
+    }
+
+    #[test]
+    fn flip_sprite() {
+        let mut graphics = graphics();
+        graphics.horizontal_flip = true;
+        graphics.draw_sprite(0, 0, SPRITE);
+        assert_eq!(graphics.read_pixel(3, 0), 2);
+
+        graphics.clear();
+        graphics.horizontal_flip = false;
+        graphics.vertical_flip = true;
+        graphics.draw_sprite(0, 0, SPRITE);
+        assert_eq!(graphics.read_pixel(0, 3), 2);
+
+        graphics.clear();
+        graphics.horizontal_flip = true;
+        graphics.draw_sprite(0, 0, SPRITE);
+        assert_eq!(graphics.read_pixel(3, 3), 2);
+        assert_eq!(graphics.read_pixel(0, 0), 1);
+    }
+
+    #[test]
+    fn render_rgba() {
+        let mut graphics = graphics();
+        graphics.background_layer = 0xF;
+        graphics.draw_sprite(1, 0, SPRITE);
+
+        let mut buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        graphics.render_rgba(&mut buffer);
+        assert_eq!(&buffer[0..4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(&buffer[4..8], &[0x88, 0x88, 0x88, 0xFF]);
+        assert_eq!(&buffer[8..12], &[0x00, 0x00, 0x00, 0xFF]);
+
+        let last = buffer.len() - 4;
+        assert_eq!(&buffer[last..], &[0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn transparency_and_collision() {
+        let mut graphics = graphics();
+        graphics.sprite_width = 1;
+        graphics.sprite_height = 1;
+
+        assert!(!graphics.draw_sprite(0, 0, &[0x10]));
+        assert!(!graphics.draw_sprite(0, 0, &[0x03]));
+        assert_eq!(graphics.read_pixel(0, 0), 1);
+        assert_eq!(graphics.read_pixel(1, 0), 3);
+
+        assert!(!graphics.draw_sprite(2, 0, &[0x40]));
+        assert!(graphics.draw_sprite(1, 0, &[0x50]));
+        assert_eq!(graphics.read_pixel(1, 0), 5);
+        assert_eq!(graphics.read_pixel(0, 0), 1);
+    }
 }
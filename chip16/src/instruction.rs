@@ -1,90 +1,41 @@
-#[derive(Debug, PartialEq)]
-pub enum Operation {
-    NOP,
-    CLS,
-    VBLNK,
-    BGC,
-    SPR,
-    DRWI,
-    DRWR,
-    RND,
-    FLIP,
-    SND0,
-    SND1,
-    SND2,
-    SND3,
-    SNP,
-    SNG,
-    JMPI,
-    JMC,
-    JX,
-    JME,
-    CALLI,
-    RET,
-    JMPR,
-    CX,
-    CALLR,
-    LDIR,
-    LDIS,
-    LDMI,
-    LDMR,
-    MOV,
-    STMI,
-    STMR,
-    ADDI,
-    ADDR2,
-    ADDR3,
-    SUBI,
-    SUBR2,
-    SUBR3,
-    CMPI,
-    CMPR,
-    ANDI,
-    ANDR2,
-    ANDR3,
-    TSTI,
-    TSTR,
-    ORI,
-    ORR2,
-    ORR3,
-    XORI,
-    XORR2,
-    XORR3,
-    MULI,
-    MULR2,
-    MULR3,
-    DIVI,
-    DIVR2,
-    DIVR3,
-    MODI,
-    MODR2,
-    MODR3,
-    REMI,
-    REMR2,
-    REMR3,
-    SHLN,
-    SHRN,
-    SARN,
-    SHLR,
-    SHRR,
-    SARR,
-    PUSH,
-    POP,
-    PUSHALL,
-    POPALL,
-    PUSHF,
-    POPF,
-    PALI,
-    PALR,
-    NOTI,
-    NOTR1,
-    NOTR2,
-    NEGI,
-    NEGR1,
-    NEGR2,
+use profile::ExecutionProfile;
+
+// The fields of the instruction word that each operation uses. Every other bit must be zero.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Format {
+    None,
+    N,
+    I,
+    R,
+    RN,
+    RI,
+    RR,
+    RRI,
+    RRR,
+    SpI,
+    CondI,
+    Flip,
+    Sng,
 }
 
-#[derive(Debug, PartialEq)]
+impl Format {
+    pub(crate) fn mask(self) -> u32 {
+        match self {
+            Format::None => 0x0000_00FF,
+            Format::N => 0x000F_00FF,
+            Format::I | Format::SpI => 0xFFFF_00FF,
+            Format::R => 0x0000_0FFF,
+            Format::RN => 0x000F_0FFF,
+            Format::RI | Format::CondI => 0xFFFF_0FFF,
+            Format::RR => 0x0000_FFFF,
+            Format::RRR => 0x000F_FFFF,
+            Format::RRI | Format::Sng => 0xFFFF_FFFF,
+            Format::Flip => 0x0300_00FF,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Condition {
     Z,
     NZ,
@@ -103,6 +54,70 @@ pub enum Condition {
     LE,
 }
 
+impl Condition {
+    // Every condition, in the order of its encoding.
+    pub const ALL: &'static [Condition] = &[
+        Condition::Z,
+        Condition::NZ,
+        Condition::N,
+        Condition::NN,
+        Condition::P,
+        Condition::O,
+        Condition::NO,
+        Condition::A,
+        Condition::AE,
+        Condition::B,
+        Condition::BE,
+        Condition::G,
+        Condition::GE,
+        Condition::L,
+        Condition::LE,
+    ];
+
+    // The suffix that names the condition in the mnemonics of JX and CX, such as `jnz`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Condition::Z => "z",
+            Condition::NZ => "nz",
+            Condition::N => "n",
+            Condition::NN => "nn",
+            Condition::P => "p",
+            Condition::O => "o",
+            Condition::NO => "no",
+            Condition::A => "a",
+            Condition::AE => "ae",
+            Condition::B => "b",
+            Condition::BE => "be",
+            Condition::G => "g",
+            Condition::GE => "ge",
+            Condition::L => "l",
+            Condition::LE => "le",
+        }
+    }
+
+    // The inverse of `suffix`, which also accepts `c` and `nc` for the carry flag.
+    pub fn from_suffix(suffix: &str) -> Option<Condition> {
+        match suffix {
+            "z" => Some(Condition::Z),
+            "nz" => Some(Condition::NZ),
+            "n" => Some(Condition::N),
+            "nn" => Some(Condition::NN),
+            "p" => Some(Condition::P),
+            "o" => Some(Condition::O),
+            "no" => Some(Condition::NO),
+            "a" => Some(Condition::A),
+            "ae" | "nc" => Some(Condition::AE),
+            "b" | "c" => Some(Condition::B),
+            "be" => Some(Condition::BE),
+            "g" => Some(Condition::G),
+            "ge" => Some(Condition::GE),
+            "l" => Some(Condition::L),
+            "le" => Some(Condition::LE),
+            _ => None,
+        }
+    }
+}
+
 macro_rules! extract_argument {
     ($name:ident, $output:ty, $width:expr, $index:expr) => {
         pub fn $name(&self) -> $output {
@@ -114,6 +129,20 @@ macro_rules! extract_argument {
     };
 }
 
+// The operand fields of an instruction, named after the accessors that extract them. Only the
+// fields used by the operation are encoded: the condition of JX and CX is stored in `x`, the AD
+// byte of SNG in `y` and `x`, and the flip bits of FLIP in the high byte of `hhll`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Operands {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+    pub n: u8,
+    pub hhll: u16,
+}
+
+isa!(define_instructions);
+
 #[derive(Debug, PartialEq)]
 pub struct Instruction(pub u32);
 
@@ -122,97 +151,51 @@ impl Instruction {
         Instruction(data)
     }
 
+    pub fn encode(operation: Operation, operands: Operands) -> Instruction {
+        let x = (operands.x as u32 & 0xF) << 8;
+        let y = (operands.y as u32 & 0xF) << 12;
+        let z = (operands.z as u32 & 0xF) << 16;
+        let n = (operands.n as u32 & 0xF) << 16;
+        let hhll = (operands.hhll as u32) << 16;
+
+        let fields = match operation.format() {
+            Format::None => 0,
+            Format::N => n,
+            Format::I | Format::SpI | Format::Flip => hhll,
+            Format::R => x,
+            Format::RN => x | n,
+            Format::RI | Format::CondI => x | hhll,
+            Format::RR => x | y,
+            Format::RRI | Format::Sng => x | y | hhll,
+            Format::RRR => x | y | z,
+        };
+
+        let word = operation.opcode() as u32 | fields;
+        Instruction(word & operation.format().mask())
+    }
+
     pub fn decode_operation(&self) -> Option<Operation> {
-        match self.ii() {
-            0x00 => Some(Operation::NOP),
-            0x01 => Some(Operation::CLS),
-            0x02 => Some(Operation::VBLNK),
-            0x03 => Some(Operation::BGC),
-            0x04 => Some(Operation::SPR),
-            0x05 => Some(Operation::DRWI),
-            0x06 => Some(Operation::DRWR),
-            0x07 => Some(Operation::RND),
-            0x08 => Some(Operation::FLIP),
-            0x09 => Some(Operation::SND0),
-            0x0A => Some(Operation::SND1),
-            0x0B => Some(Operation::SND2),
-            0x0C => Some(Operation::SND3),
-            0x0D => Some(Operation::SNP),
-            0x0E => Some(Operation::SNG),
-            0x10 => Some(Operation::JMPI),
-            0x11 => Some(Operation::JMC),
-            0x12 => Some(Operation::JX),
-            0x13 => Some(Operation::JME),
-            0x14 => Some(Operation::CALLI),
-            0x15 => Some(Operation::RET),
-            0x16 => Some(Operation::JMPR),
-            0x17 => Some(Operation::CX),
-            0x18 => Some(Operation::CALLR),
-            0x20 => Some(Operation::LDIR),
-            0x21 => Some(Operation::LDIS),
-            0x22 => Some(Operation::LDMI),
-            0x23 => Some(Operation::LDMR),
-            0x24 => Some(Operation::MOV),
-            0x30 => Some(Operation::STMI),
-            0x31 => Some(Operation::STMR),
-            0x40 => Some(Operation::ADDI),
-            0x41 => Some(Operation::ADDR2),
-            0x42 => Some(Operation::ADDR3),
-            0x50 => Some(Operation::SUBI),
-            0x51 => Some(Operation::SUBR2),
-            0x52 => Some(Operation::SUBR3),
-            0x53 => Some(Operation::CMPI),
-            0x54 => Some(Operation::CMPR),
-            0x60 => Some(Operation::ANDI),
-            0x61 => Some(Operation::ANDR2),
-            0x62 => Some(Operation::ANDR3),
-            0x63 => Some(Operation::TSTI),
-            0x64 => Some(Operation::TSTR),
-            0x70 => Some(Operation::ORI),
-            0x71 => Some(Operation::ORR2),
-            0x72 => Some(Operation::ORR3),
-            0x80 => Some(Operation::XORI),
-            0x81 => Some(Operation::XORR2),
-            0x82 => Some(Operation::XORR3),
-            0x90 => Some(Operation::MULI),
-            0x91 => Some(Operation::MULR2),
-            0x92 => Some(Operation::MULR3),
-            0xA0 => Some(Operation::DIVI),
-            0xA1 => Some(Operation::DIVR2),
-            0xA2 => Some(Operation::DIVR3),
-            0xA3 => Some(Operation::MODI),
-            0xA4 => Some(Operation::MODR2),
-            0xA5 => Some(Operation::MODR3),
-            0xA6 => Some(Operation::REMI),
-            0xA7 => Some(Operation::REMR2),
-            0xA8 => Some(Operation::REMR3),
-            0xB0 => Some(Operation::SHLN),
-            0xB1 => Some(Operation::SHRN),
-            0xB2 => Some(Operation::SARN),
-            0xB3 => Some(Operation::SHLR),
-            0xB4 => Some(Operation::SHRR),
-            0xB5 => Some(Operation::SARR),
-            0xC0 => Some(Operation::PUSH),
-            0xC1 => Some(Operation::POP),
-            0xC2 => Some(Operation::PUSHALL),
-            0xC3 => Some(Operation::POPALL),
-            0xC4 => Some(Operation::PUSHF),
-            0xC5 => Some(Operation::POPF),
-            0xD0 => Some(Operation::PALI),
-            0xD1 => Some(Operation::PALR),
-            0xE0 => Some(Operation::NOTI),
-            0xE1 => Some(Operation::NOTR1),
-            0xE2 => Some(Operation::NOTR2),
-            0xE3 => Some(Operation::NEGI),
-            0xE4 => Some(Operation::NEGR1),
-            0xE5 => Some(Operation::NEGR2),
-            _ => None,
-        }
+        Operation::from_opcode(self.ii())
     }
 
     pub fn decode_condition(&self) -> Option<Condition> {
         match self.x() {
             0x0 => Some(Condition::Z),
+            0x1 => Some(Condition::NZ),
+            0x2 => Some(Condition::N),
+            0x3 => Some(Condition::NN),
+            0x4 => Some(Condition::P),
+            0x5 => Some(Condition::O),
+            0x6 => Some(Condition::NO),
+            0x7 => Some(Condition::A),
+            0x8 => Some(Condition::AE),
+            0x9 => Some(Condition::B),
+            0xA => Some(Condition::BE),
+            0xB => Some(Condition::G),
+            0xC => Some(Condition::GE),
+            0xD => Some(Condition::L),
+            0xE => Some(Condition::LE),
+            // 0xF is reserved by the specification.
             _ => None,
         }
     }
@@ -241,3 +224,174 @@ impl Instruction {
     extract_argument!(a, u8, 4, 3);
     extract_argument!(d, u8, 4, 2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_operands() {
+        let operands = Operands {
+            x: 0x1,
+            y: 0x2,
+            z: 0x3,
+            n: 0x4,
+            hhll: 0x5678,
+        };
+
+        let encode = |operation| Instruction::encode(operation, operands).0;
+        assert_eq!(encode(Operation::NOP), 0x0000_0000);
+        assert_eq!(encode(Operation::BGC), 0x0004_0003);
+        assert_eq!(encode(Operation::SPR), 0x5678_0004);
+        assert_eq!(encode(Operation::DRWI), 0x5678_2105);
+        assert_eq!(encode(Operation::DRWR), 0x0003_2106);
+        assert_eq!(encode(Operation::LDIR), 0x5678_0120);
+        assert_eq!(encode(Operation::LDIS), 0x5678_0021);
+        assert_eq!(encode(Operation::MOV), 0x0000_2124);
+        assert_eq!(encode(Operation::SHLN), 0x0004_01B0);
+        assert_eq!(encode(Operation::PUSH), 0x0000_01C0);
+        assert_eq!(encode(Operation::SNG), 0x5678_210E);
+        assert_eq!(encode(Operation::FLIP), 0x0200_0008);
+    }
+
+    #[test]
+    fn encode_condition() {
+        let operands = Operands {
+            x: Condition::GE as u8,
+            hhll: 0x0100,
+            ..Operands::default()
+        };
+
+        let instruction = Instruction::encode(Operation::JX, operands);
+        assert_eq!(instruction, Instruction(0x0100_0C12));
+        assert_eq!(instruction.decode_condition(), Some(Condition::GE));
+    }
+
+    #[test]
+    fn decode_operands() {
+        assert_eq!(
+            Instruction::new(0x0003_2142).decode(),
+            Some(DecodedInstruction::Addr3 { x: 1, y: 2, z: 3 })
+        );
+        assert_eq!(
+            Instruction::new(0x1234_0312).decode(),
+            Some(DecodedInstruction::Jx {
+                cond: Condition::NN,
+                addr: 0x1234,
+            })
+        );
+        assert_eq!(
+            Instruction::new(0x0804_0004).decode(),
+            Some(DecodedInstruction::Spr {
+                width: 0x04,
+                height: 0x08,
+            })
+        );
+        assert_eq!(
+            Instruction::new(0x0200_0008).decode(),
+            Some(DecodedInstruction::Flip {
+                horizontal: true,
+                vertical: false,
+            })
+        );
+        assert_eq!(
+            Instruction::new(0x5678_3A0E).decode(),
+            Some(DecodedInstruction::Sng {
+                ad: 0x3A,
+                vtsr: 0x5678,
+            })
+        );
+    }
+
+    #[test]
+    fn decode_rejects_malformed_words() {
+        // Unknown opcode, reserved condition, and non-zero reserved nibbles or bytes.
+        assert_eq!(Instruction::new(0x0000_00FF).decode(), None);
+        assert_eq!(Instruction::new(0x1234_0F12).decode(), None);
+        assert_eq!(Instruction::new(0x0000_0100).decode(), None);
+        assert_eq!(Instruction::new(0x0010_2142).decode(), None);
+        assert_eq!(Instruction::new(0x0001_0015).decode(), None);
+        assert_eq!(Instruction::new(0x0400_0008).decode(), None);
+    }
+
+    #[test]
+    fn instruction_set_table() {
+        assert_eq!(Operation::from_opcode(0x42), Some(Operation::ADDR3));
+        assert_eq!(Operation::from_opcode(0x43), None);
+        assert_eq!(Operation::ADDR3.mnemonic(), "add");
+        assert_eq!(Operation::ADDR3.flags_affected(), "CZON");
+        assert_eq!(Operation::TSTI.flags_affected(), "ZN");
+        assert_eq!(Operation::JMPI.flags_affected(), "");
+        assert_eq!(Operation::CX.introduced(), ExecutionProfile::V1_0);
+        assert_eq!(Operation::NEGR2.introduced(), ExecutionProfile::V1_3);
+        assert_eq!(Operation::VBLNK.cycles(), 1);
+    }
+
+    // Every valid word decodes to an instruction that encodes back to the same word.
+    #[test]
+    fn decode_encode_round_trip() {
+        for opcode in 0..=0xFF {
+            for fields in &[0x0000_0000, 0xFFFF_FF00, 0x1234_5600, 0x000F_0300] {
+                let instruction = Instruction::new(fields | opcode);
+                if let Some(decoded) = instruction.decode() {
+                    assert_eq!(decoded.encode(), instruction);
+                    assert_eq!(instruction.decode_operation(), Some(decoded.operation()));
+                }
+            }
+        }
+    }
+
+    // Every opcode encodes to itself, and decodes back to the same operation and operands.
+    #[test]
+    fn encode_every_opcode() {
+        let operands = Operands {
+            x: 0xA,
+            y: 0xB,
+            z: 0xC,
+            n: 0xC,
+            hhll: 0xFEDC,
+        };
+
+        let mut count = 0;
+        for opcode in 0..=0xFF {
+            let operation = match Instruction::new(opcode).decode_operation() {
+                Some(operation) => operation,
+                None => continue,
+            };
+            count += 1;
+            assert_eq!(operation.opcode() as u32, opcode);
+
+            let instruction = Instruction::encode(operation, operands);
+            assert_eq!(instruction.decode_operation(), Some(operation));
+
+            let mask = operation.format().mask();
+            let word = 0xFEDC_BA00 | opcode;
+            assert_eq!(instruction.0, word & mask, "{:?}", operation);
+
+            let decoded = Operands {
+                x: instruction.x(),
+                y: instruction.y(),
+                z: instruction.z(),
+                n: instruction.n(),
+                hhll: instruction.hhll(),
+            };
+            assert_eq!(Instruction::encode(operation, decoded), instruction);
+        }
+        assert_eq!(count, 82);
+        assert_eq!(Operation::ALL.len(), count);
+        assert!(Operation::ALL
+            .windows(2)
+            .all(|w| w[0].opcode() < w[1].opcode()));
+    }
+
+    #[test]
+    fn decode_every_condition() {
+        for (nibble, &condition) in Condition::ALL.iter().enumerate() {
+            let instruction = Instruction::new(0x1234_0012 | (nibble as u32) << 8);
+            assert_eq!(instruction.decode_condition(), Some(condition));
+            assert_eq!(Condition::from_suffix(condition.suffix()), Some(condition));
+        }
+
+        assert_eq!(Instruction::new(0x1234_0F12).decode_condition(), None);
+    }
+}
@@ -0,0 +1,256 @@
+// The instruction set, which is the single source for decoding, encoding, disassembly and
+// execution. Each row is the opcode, the `Operation`, the `DecodedInstruction` with its operands,
+// the cpu handler, the tchip16 mnemonic, the format, the first version of the specification that
+// defines it, the flags it affects and the cycles it takes.
+//
+// The table is passed to a callback macro, which turns it into items.
+macro_rules! isa {
+    ($callback:ident) => {
+        $callback! {
+            0x00 => NOP     Nop     {}                       nop     "nop"     None  V0_7 ""     1;
+            0x01 => CLS     Cls     {}                       cls     "cls"     None  V0_7 ""     1;
+            0x02 => VBLNK   Vblnk   {}                       vblnk   "vblnk"   None  V0_7 ""     1;
+            0x03 => BGC     Bgc     { n }                    bgc     "bgc"     N     V0_7 ""     1;
+            0x04 => SPR     Spr     { width, height }        spr     "spr"     I     V0_7 ""     1;
+            0x05 => DRWI    Drwi    { x, y, addr }           drwi    "drw"     RRI   V0_7 "C"    1;
+            0x06 => DRWR    Drwr    { x, y, z }              drwr    "drw"     RRR   V0_7 "C"    1;
+            0x07 => RND     Rnd     { x, max }               rnd     "rnd"     RI    V0_7 ""     1;
+            0x08 => FLIP    Flip    { horizontal, vertical } flip    "flip"    Flip  V0_7 ""     1;
+            0x09 => SND0    Snd0    {}                       snd0    "snd0"    None  V0_7 ""     1;
+            0x0A => SND1    Snd1    { duration }             snd1    "snd1"    I     V0_7 ""     1;
+            0x0B => SND2    Snd2    { duration }             snd2    "snd2"    I     V0_7 ""     1;
+            0x0C => SND3    Snd3    { duration }             snd3    "snd3"    I     V0_7 ""     1;
+            0x0D => SNP     Snp     { x, duration }          snp     "snp"     RI    V1_1 ""     1;
+            0x0E => SNG     Sng     { ad, vtsr }             sng     "sng"     Sng   V1_1 ""     1;
+            0x10 => JMPI    Jmpi    { addr }                 jmpi    "jmp"     I     V0_7 ""     1;
+            0x11 => JMC     Jmc     { addr }                 jmc     "jmc"     I     V0_7 ""     1;
+            0x12 => JX      Jx      { cond, addr }           jx      "j"       CondI V1_0 ""     1;
+            0x13 => JME     Jme     { x, y, addr }           jme     "jme"     RRI   V0_7 ""     1;
+            0x14 => CALLI   Calli   { addr }                 calli   "call"    I     V0_7 ""     1;
+            0x15 => RET     Ret     {}                       ret     "ret"     None  V0_7 ""     1;
+            0x16 => JMPR    Jmpr    { x }                    jmpr    "jmp"     R     V0_7 ""     1;
+            0x17 => CX      Cx      { cond, addr }           cx      "c"       CondI V1_0 ""     1;
+            0x18 => CALLR   Callr   { x }                    callr   "call"    R     V0_7 ""     1;
+            0x20 => LDIR    Ldir    { x, imm }               ldir    "ldi"     RI    V0_7 ""     1;
+            0x21 => LDIS    Ldis    { imm }                  ldis    "ldi"     SpI   V0_7 ""     1;
+            0x22 => LDMI    Ldmi    { x, addr }              ldmi    "ldm"     RI    V0_7 ""     1;
+            0x23 => LDMR    Ldmr    { x, y }                 ldmr    "ldm"     RR    V0_7 ""     1;
+            0x24 => MOV     Mov     { x, y }                 mov     "mov"     RR    V0_7 ""     1;
+            0x30 => STMI    Stmi    { x, addr }              stmi    "stm"     RI    V0_7 ""     1;
+            0x31 => STMR    Stmr    { x, y }                 stmr    "stm"     RR    V0_7 ""     1;
+            0x40 => ADDI    Addi    { x, imm }               addi    "addi"    RI    V0_7 "CZON" 1;
+            0x41 => ADDR2   Addr2   { x, y }                 addr2   "add"     RR    V0_7 "CZON" 1;
+            0x42 => ADDR3   Addr3   { x, y, z }              addr3   "add"     RRR   V0_7 "CZON" 1;
+            0x50 => SUBI    Subi    { x, imm }               subi    "subi"    RI    V0_7 "CZON" 1;
+            0x51 => SUBR2   Subr2   { x, y }                 subr2   "sub"     RR    V0_7 "CZON" 1;
+            0x52 => SUBR3   Subr3   { x, y, z }              subr3   "sub"     RRR   V0_7 "CZON" 1;
+            0x53 => CMPI    Cmpi    { x, imm }               cmpi    "cmpi"    RI    V0_7 "CZON" 1;
+            0x54 => CMPR    Cmpr    { x, y }                 cmpr    "cmp"     RR    V0_7 "CZON" 1;
+            0x60 => ANDI    Andi    { x, imm }               andi    "andi"    RI    V0_7 "ZN"   1;
+            0x61 => ANDR2   Andr2   { x, y }                 andr2   "and"     RR    V0_7 "ZN"   1;
+            0x62 => ANDR3   Andr3   { x, y, z }              andr3   "and"     RRR   V0_7 "ZN"   1;
+            0x63 => TSTI    Tsti    { x, imm }               tsti    "tsti"    RI    V0_7 "ZN"   1;
+            0x64 => TSTR    Tstr    { x, y }                 tstr    "tst"     RR    V0_7 "ZN"   1;
+            0x70 => ORI     Ori     { x, imm }               ori     "ori"     RI    V0_7 "ZN"   1;
+            0x71 => ORR2    Orr2    { x, y }                 orr2    "or"      RR    V0_7 "ZN"   1;
+            0x72 => ORR3    Orr3    { x, y, z }              orr3    "or"      RRR   V0_7 "ZN"   1;
+            0x80 => XORI    Xori    { x, imm }               xori    "xori"    RI    V0_7 "ZN"   1;
+            0x81 => XORR2   Xorr2   { x, y }                 xorr2   "xor"     RR    V0_7 "ZN"   1;
+            0x82 => XORR3   Xorr3   { x, y, z }              xorr3   "xor"     RRR   V0_7 "ZN"   1;
+            0x90 => MULI    Muli    { x, imm }               muli    "muli"    RI    V1_1 "CZN"  1;
+            0x91 => MULR2   Mulr2   { x, y }                 mulr2   "mul"     RR    V0_7 "CZN"  1;
+            0x92 => MULR3   Mulr3   { x, y, z }              mulr3   "mul"     RRR   V0_7 "CZN"  1;
+            0xA0 => DIVI    Divi    { x, imm }               divi    "divi"    RI    V0_7 "CZN"  1;
+            0xA1 => DIVR2   Divr2   { x, y }                 divr2   "div"     RR    V0_7 "CZN"  1;
+            0xA2 => DIVR3   Divr3   { x, y, z }              divr3   "div"     RRR   V0_7 "CZN"  1;
+            0xA3 => MODI    Modi    { x, imm }               modi    "modi"    RI    V1_3 "ZN"   1;
+            0xA4 => MODR2   Modr2   { x, y }                 modr2   "mod"     RR    V1_3 "ZN"   1;
+            0xA5 => MODR3   Modr3   { x, y, z }              modr3   "mod"     RRR   V1_3 "ZN"   1;
+            0xA6 => REMI    Remi    { x, imm }               remi    "remi"    RI    V1_3 "ZN"   1;
+            0xA7 => REMR2   Remr2   { x, y }                 remr2   "rem"     RR    V1_3 "ZN"   1;
+            0xA8 => REMR3   Remr3   { x, y, z }              remr3   "rem"     RRR   V1_3 "ZN"   1;
+            0xB0 => SHLN    Shln    { x, n }                 shln    "shl"     RN    V0_7 "ZN"   1;
+            0xB1 => SHRN    Shrn    { x, n }                 shrn    "shr"     RN    V0_7 "ZN"   1;
+            0xB2 => SARN    Sarn    { x, n }                 sarn    "sar"     RN    V0_7 "ZN"   1;
+            0xB3 => SHLR    Shlr    { x, y }                 shlr    "shl"     RR    V0_7 "ZN"   1;
+            0xB4 => SHRR    Shrr    { x, y }                 shrr    "shr"     RR    V0_7 "ZN"   1;
+            0xB5 => SARR    Sarr    { x, y }                 sarr    "sar"     RR    V0_7 "ZN"   1;
+            0xC0 => PUSH    Push    { x }                    push    "push"    R     V0_7 ""     1;
+            0xC1 => POP     Pop     { x }                    pop     "pop"     R     V0_7 ""     1;
+            0xC2 => PUSHALL Pushall {}                       pushall "pushall" None  V0_7 ""     1;
+            0xC3 => POPALL  Popall  {}                       popall  "popall"  None  V0_7 ""     1;
+            0xC4 => PUSHF   Pushf   {}                       pushf   "pushf"   None  V1_1 ""     1;
+            0xC5 => POPF    Popf    {}                       popf    "popf"    None  V1_1 "CZON" 1;
+            0xD0 => PALI    Pali    { addr }                 pali    "pal"     I     V1_1 ""     1;
+            0xD1 => PALR    Palr    { x }                    palr    "pal"     R     V1_1 ""     1;
+            0xE0 => NOTI    Noti    { x, imm }               noti    "noti"    RI    V1_3 "ZN"   1;
+            0xE1 => NOTR1   Notr1   { x }                    notr1   "not"     R     V1_3 "ZN"   1;
+            0xE2 => NOTR2   Notr2   { x, y }                 notr2   "not"     RR    V1_3 "ZN"   1;
+            0xE3 => NEGI    Negi    { x, imm }               negi    "negi"    RI    V1_3 "ZN"   1;
+            0xE4 => NEGR1   Negr1   { x }                    negr1   "neg"     R     V1_3 "ZN"   1;
+            0xE5 => NEGR2   Negr2   { x, y }                 negr2   "neg"     RR    V1_3 "ZN"   1;
+        }
+    };
+}
+
+// The type of each named operand.
+#[rustfmt::skip]
+macro_rules! operand_type {
+    (x) => { u8 };
+    (y) => { u8 };
+    (z) => { u8 };
+    (n) => { u8 };
+    (ad) => { u8 };
+    (width) => { u8 };
+    (height) => { u8 };
+    (horizontal) => { bool };
+    (vertical) => { bool };
+    (cond) => { Condition };
+    ($hhll:ident) => { u16 };
+}
+
+// Reads a named operand from the fields of an instruction. This is used inside a function that
+// returns an Option, so a reserved condition fails the decode.
+#[rustfmt::skip]
+macro_rules! decode_operand {
+    ($instruction:ident, x) => { $instruction.x() };
+    ($instruction:ident, y) => { $instruction.y() };
+    ($instruction:ident, z) => { $instruction.z() };
+    ($instruction:ident, n) => { $instruction.n() };
+    ($instruction:ident, ad) => { $instruction.y() << 4 | $instruction.x() };
+    ($instruction:ident, width) => { $instruction.ll() };
+    ($instruction:ident, height) => { $instruction.hh() };
+    ($instruction:ident, horizontal) => { $instruction.hh() & 0x02 != 0 };
+    ($instruction:ident, vertical) => { $instruction.hh() & 0x01 != 0 };
+    ($instruction:ident, cond) => { $instruction.decode_condition()? };
+    ($instruction:ident, $hhll:ident) => { $instruction.hhll() };
+}
+
+// Writes a named operand into the fields of `Operands`, the inverse of `decode_operand`.
+#[rustfmt::skip]
+macro_rules! encode_operand {
+    ($operands:ident, x, $value:expr) => { $operands.x = $value };
+    ($operands:ident, y, $value:expr) => { $operands.y = $value };
+    ($operands:ident, z, $value:expr) => { $operands.z = $value };
+    ($operands:ident, n, $value:expr) => { $operands.n = $value };
+    ($operands:ident, ad, $value:expr) => {{
+        $operands.y = $value >> 4;
+        $operands.x = $value & 0xF;
+    }};
+    ($operands:ident, width, $value:expr) => { $operands.hhll |= $value as u16 };
+    ($operands:ident, height, $value:expr) => { $operands.hhll |= ($value as u16) << 8 };
+    ($operands:ident, horizontal, $value:expr) => { $operands.hhll |= ($value as u16) << 9 };
+    ($operands:ident, vertical, $value:expr) => { $operands.hhll |= ($value as u16) << 8 };
+    ($operands:ident, cond, $value:expr) => { $operands.x = $value as u8 };
+    ($operands:ident, $hhll:ident, $value:expr) => { $operands.hhll = $value };
+}
+
+// Defines `Operation` and `DecodedInstruction`, and everything that converts between them.
+macro_rules! define_instructions {
+    ($(
+        $opcode:literal => $operation:ident $variant:ident { $($operand:ident),* } $handler:ident
+        $mnemonic:literal $format:ident $version:ident $flags:literal $cycles:literal;
+    )*) => {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum Operation {
+            $($operation,)*
+        }
+
+        // An instruction with the operands its operation uses. Registers are the indices `x`,
+        // `y` and `z`, and the 16-bit field is named for what it holds.
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum DecodedInstruction {
+            $($variant { $($operand: operand_type!($operand)),* },)*
+        }
+
+        impl Operation {
+            // Every operation, in opcode order.
+            pub const ALL: &'static [Operation] = &[$(Operation::$operation),*];
+
+            pub fn from_opcode(opcode: u8) -> Option<Operation> {
+                match opcode {
+                    $($opcode => Some(Operation::$operation),)*
+                    _ => None,
+                }
+            }
+
+            pub fn opcode(self) -> u8 {
+                match self {
+                    $(Operation::$operation => $opcode,)*
+                }
+            }
+
+            pub fn mnemonic(self) -> &'static str {
+                match self {
+                    $(Operation::$operation => $mnemonic,)*
+                }
+            }
+
+            pub(crate) fn format(self) -> Format {
+                match self {
+                    $(Operation::$operation => Format::$format,)*
+                }
+            }
+
+            // The first revision of the specification that defines the operation.
+            pub fn introduced(self) -> ExecutionProfile {
+                match self {
+                    $(Operation::$operation => ExecutionProfile::$version,)*
+                }
+            }
+
+            // The flags written by the operation, as the letters C, Z, O and N.
+            pub fn flags_affected(self) -> &'static str {
+                match self {
+                    $(Operation::$operation => $flags,)*
+                }
+            }
+
+            pub fn cycles(self) -> u32 {
+                match self {
+                    $(Operation::$operation => $cycles,)*
+                }
+            }
+        }
+
+        impl DecodedInstruction {
+            pub fn operation(&self) -> Operation {
+                match *self {
+                    $(DecodedInstruction::$variant { .. } => Operation::$operation,)*
+                }
+            }
+
+            pub fn operands(&self) -> Operands {
+                let mut operands = Operands::default();
+                match *self {
+                    $(DecodedInstruction::$variant { $($operand),* } => {
+                        $(encode_operand!(operands, $operand, $operand);)*
+                    })*
+                }
+                operands
+            }
+
+            pub fn encode(&self) -> Instruction {
+                Instruction::encode(self.operation(), self.operands())
+            }
+        }
+
+        impl Instruction {
+            // Decodes the operation and its operands. Fails for unknown opcodes, reserved
+            // conditions and words with bits set outside of the fields the operation uses.
+            pub fn decode(&self) -> Option<DecodedInstruction> {
+                let operation = self.decode_operation()?;
+                if self.0 & !operation.format().mask() != 0 {
+                    return None;
+                }
+
+                let decoded = match operation {
+                    $(Operation::$operation => DecodedInstruction::$variant {
+                        $($operand: decode_operand!(self, $operand)),*
+                    },)*
+                };
+                Some(decoded)
+            }
+        }
+    };
+}
@@ -4,14 +4,44 @@ extern crate crc;
 extern crate failure;
 extern crate rand;
 
+// The instruction set table is used by the modules after it, so it must be declared first.
+#[macro_use]
+mod isa;
+
+mod asm;
+mod audio;
+mod console;
+mod controller;
 mod cpu;
+mod debugger;
+mod disasm;
+mod error;
 mod flags;
+mod gdb;
 mod graphics;
 mod instruction;
 mod memory;
+mod palette;
+mod profile;
 mod register;
 mod rom;
+mod symbols;
 
-pub use cpu::Cpu;
-pub use instruction::{Condition, Instruction, Operation};
-pub use rom::{Rom, RomFormat, Version};
+pub use asm::{assemble, AssembleError, Assembler, Assembly};
+pub use audio::{write_wav, Audio, Envelope, Waveform, DEFAULT_SAMPLE_RATE};
+pub use console::Console;
+pub use controller::{Controller, Port};
+pub use cpu::{Cpu, FrameStats, StepOutcome, CLOCK_RATE, CYCLES_PER_FRAME, FRAME_RATE};
+pub use debugger::{Comparison, Debugger, RegisterCondition, Stop, WatchKind, Watchpoint};
+pub use disasm::{
+    branch_target, disassemble, disassemble_or_data, disassemble_with_labels, label_name, trace,
+    trace_with_symbols, Entry, Listing,
+};
+pub use error::EmulationError;
+pub use gdb::GdbStub;
+pub use graphics::{SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use instruction::{Condition, DecodedInstruction, Instruction, Operands, Operation};
+pub use palette::{Color, Palette};
+pub use profile::{ExecutionProfile, ProfilePolicy};
+pub use rom::{Rom, RomFormat, RomLoadOptions, Version};
+pub use symbols::{SourceLocation, Symbols};
@@ -1,56 +1,126 @@
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use failure::Error;
+use std::cell::RefCell;
 use std::mem;
 
 const ADDRESSABLE_MEMORY: usize = 65_536;
 
-pub struct Memory([u8; ADDRESSABLE_MEMORY]);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// A read or write of `length` bytes from `address`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub address: u16,
+    pub length: usize,
+}
+
+pub struct Memory {
+    bytes: [u8; ADDRESSABLE_MEMORY],
+    // Accesses are only recorded while tracing, which the debugger uses for its watchpoints. Reads
+    // only borrow the memory, so they are recorded through a RefCell.
+    tracing: bool,
+    accesses: RefCell<Vec<MemoryAccess>>,
+}
 
 impl Memory {
     pub fn new() -> Memory {
-        Memory([0; ADDRESSABLE_MEMORY])
+        Memory {
+            bytes: [0; ADDRESSABLE_MEMORY],
+            tracing: false,
+            accesses: RefCell::new(Vec::new()),
+        }
     }
 
     pub fn clear(&mut self) {
-        self.0 = [0; ADDRESSABLE_MEMORY];
+        self.bytes = [0; ADDRESSABLE_MEMORY];
     }
 
-    // read_sprite
-    // write_sprite
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+    }
+
+    // The accesses recorded since the last call, in the order they were made.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        mem::take(self.accesses.get_mut())
+    }
 
-    pub fn read_u8<I: Into<usize>>(&self, index: I) -> u8 {
-        *self.0.get(index.into()).unwrap()
+    fn record(&self, access: Access, index: usize, length: usize) {
+        if self.tracing {
+            self.accesses.borrow_mut().push(MemoryAccess {
+                access,
+                address: index as u16,
+                length,
+            });
+        }
     }
 
-    pub fn read_u16<I: Into<usize>>(&self, index: I) -> u16 {
+    // Accesses that run past the end of memory return `None` rather than wrapping around.
+
+    pub fn read_slice<I: Into<usize>>(&self, index: I, length: usize) -> Option<&[u8]> {
+        let index = index.into();
+        let slice = self.bytes.get(index..index + length)?;
+        self.record(Access::Read, index, length);
+        Some(slice)
+    }
+
+    pub fn write_slice<I: Into<usize>>(&mut self, index: I, data: &[u8]) -> Option<()> {
+        let index = index.into();
+        self.bytes
+            .get_mut(index..index + data.len())?
+            .copy_from_slice(data);
+        self.record(Access::Write, index, data.len());
+        Some(())
+    }
+
+    pub fn read_u8<I: Into<usize>>(&self, index: I) -> Option<u8> {
+        let index = index.into();
+        let value = self.bytes.get(index).cloned()?;
+        self.record(Access::Read, index, 1);
+        Some(value)
+    }
+
+    pub fn read_u16<I: Into<usize>>(&self, index: I) -> Option<u16> {
         let index = index.into();
         let width = mem::size_of::<u16>();
-        let mut buf = self.0.get(index..index + width).unwrap();
-        buf.read_u16::<LittleEndian>().unwrap()
+        let mut buf = self.bytes.get(index..index + width)?;
+        self.record(Access::Read, index, width);
+        buf.read_u16::<LittleEndian>().ok()
     }
 
-    pub fn read_u32<I: Into<usize>>(&self, index: I) -> u32 {
+    pub fn read_u32<I: Into<usize>>(&self, index: I) -> Option<u32> {
         let index = index.into();
         let width = mem::size_of::<u32>();
-        let mut buf = self.0.get(index..index + width).unwrap();
-        buf.read_u32::<LittleEndian>().unwrap()
+        let mut buf = self.bytes.get(index..index + width)?;
+        self.record(Access::Read, index, width);
+        buf.read_u32::<LittleEndian>().ok()
     }
 
-    pub fn write_u8<I: Into<usize>>(&mut self, index: I, value: u8) {
-        *self.0.get_mut(index.into()).unwrap() = value;
+    pub fn write_u8<I: Into<usize>>(&mut self, index: I, value: u8) -> Option<()> {
+        let index = index.into();
+        *self.bytes.get_mut(index)? = value;
+        self.record(Access::Write, index, 1);
+        Some(())
     }
 
-    pub fn write_u16<I: Into<usize>>(&mut self, index: I, value: u16) {
+    pub fn write_u16<I: Into<usize>>(&mut self, index: I, value: u16) -> Option<()> {
         let index = index.into();
         let width = mem::size_of::<u16>();
-        let mut buf = self.0.get_mut(index..index + width).unwrap();
-        buf.write_u16::<LittleEndian>(value).unwrap()
+        let mut buf = self.bytes.get_mut(index..index + width)?;
+        buf.write_u16::<LittleEndian>(value).ok()?;
+        self.record(Access::Write, index, width);
+        Some(())
     }
 
-    pub fn write_u32<I: Into<usize>>(&mut self, index: I, value: u32) {
+    pub fn write_u32<I: Into<usize>>(&mut self, index: I, value: u32) -> Option<()> {
         let index = index.into();
         let width = mem::size_of::<u32>();
-        let mut buf = self.0.get_mut(index..index + width).unwrap();
-        buf.write_u32::<LittleEndian>(value).unwrap()
+        let mut buf = self.bytes.get_mut(index..index + width)?;
+        buf.write_u32::<LittleEndian>(value).ok()?;
+        self.record(Access::Write, index, width);
+        Some(())
     }
 }
@@ -0,0 +1,89 @@
+const PALETTE_SIZE: usize = 16;
+
+// Each color is stored in memory as three bytes, so a full palette is 48 bytes.
+pub const PALETTE_DATA_SIZE: usize = PALETTE_SIZE * 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+}
+
+impl From<u32> for Color {
+    fn from(rgb: u32) -> Color {
+        Color::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Palette(pub [Color; PALETTE_SIZE]);
+
+#[rustfmt::skip]
+const DEFAULT_PALETTE: [u32; PALETTE_SIZE] = [
+    0x000000, 0x000000, 0x888888, 0xBF3932,
+    0xDE7AAE, 0x4C3D21, 0x905F25, 0xE49452,
+    0xEAD979, 0x537A3B, 0xABD54A, 0x252E38,
+    0x00467F, 0x68ABCC, 0xBCDEE4, 0xFFFFFF,
+];
+
+impl Default for Palette {
+    fn default() -> Palette {
+        let mut colors = [Color::new(0, 0, 0); PALETTE_SIZE];
+        for (color, &rgb) in colors.iter_mut().zip(DEFAULT_PALETTE.iter()) {
+            *color = Color::from(rgb);
+        }
+        Palette(colors)
+    }
+}
+
+impl Palette {
+    pub fn new() -> Palette {
+        Palette::default()
+    }
+
+    pub fn get(&self, index: u8) -> Color {
+        self.0[index as usize % PALETTE_SIZE]
+    }
+
+    // Loads a palette from memory, which is stored as consecutive RGB triples.
+    pub fn load(&mut self, data: &[u8]) {
+        for (color, rgb) in self.0.iter_mut().zip(data.chunks(3)) {
+            if let [r, g, b] = *rgb {
+                *color = Color::new(r, g, b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_palette() {
+        let palette = Palette::new();
+        assert_eq!(palette.get(0x0), Color::new(0x00, 0x00, 0x00));
+        assert_eq!(palette.get(0x3), Color::new(0xBF, 0x39, 0x32));
+        assert_eq!(palette.get(0xF), Color::new(0xFF, 0xFF, 0xFF));
+    }
+
+    #[test]
+    fn load_palette() {
+        let mut data = [0; PALETTE_DATA_SIZE];
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        let mut palette = Palette::new();
+        palette.load(&data);
+        assert_eq!(palette.get(0x0), Color::new(0, 1, 2));
+        assert_eq!(palette.get(0xF), Color::new(45, 46, 47));
+    }
+}
@@ -0,0 +1,118 @@
+use instruction::Operation;
+use rom::Version;
+use std::fmt;
+
+// The instruction sets of each revision of the specification. Roms declaring a version between
+// two revisions use the older one, except for 0.9, which already added everything in 1.0.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum ExecutionProfile {
+    V0_7,
+    V1_0,
+    V1_1,
+    V1_3,
+}
+
+// What the cpu does when a rom executes an opcode that its profile does not define.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProfilePolicy {
+    // Stop with an `EmulationError::UnsupportedOpcode`.
+    #[default]
+    Strict,
+    // Execute the opcode anyway, and report it once through `Cpu::take_warnings`.
+    Warn,
+}
+
+impl From<Version> for ExecutionProfile {
+    fn from(version: Version) -> ExecutionProfile {
+        if version >= Version(1, 3) {
+            ExecutionProfile::V1_3
+        } else if version >= Version(1, 1) {
+            ExecutionProfile::V1_1
+        } else if version >= Version(0, 9) {
+            ExecutionProfile::V1_0
+        } else {
+            ExecutionProfile::V0_7
+        }
+    }
+}
+
+impl fmt::Display for ExecutionProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.version())
+    }
+}
+
+impl ExecutionProfile {
+    pub fn version(self) -> Version {
+        match self {
+            ExecutionProfile::V0_7 => Version(0, 7),
+            ExecutionProfile::V1_0 => Version(1, 0),
+            ExecutionProfile::V1_1 => Version(1, 1),
+            ExecutionProfile::V1_3 => Version(1, 3),
+        }
+    }
+
+    pub fn supports(self, operation: &Operation) -> bool {
+        operation.introduced() <= self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use instruction::Operation::*;
+
+    #[test]
+    fn profile_from_version() {
+        assert_eq!(
+            ExecutionProfile::from(Version(0, 7)),
+            ExecutionProfile::V0_7
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(0, 8)),
+            ExecutionProfile::V0_7
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(0, 9)),
+            ExecutionProfile::V1_0
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(1, 0)),
+            ExecutionProfile::V1_0
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(1, 1)),
+            ExecutionProfile::V1_1
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(1, 2)),
+            ExecutionProfile::V1_1
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(1, 3)),
+            ExecutionProfile::V1_3
+        );
+        assert_eq!(
+            ExecutionProfile::from(Version(2, 0)),
+            ExecutionProfile::V1_3
+        );
+    }
+
+    #[test]
+    fn supported_operations() {
+        assert!(ExecutionProfile::V0_7.supports(&DRWI));
+        assert!(!ExecutionProfile::V0_7.supports(&JX));
+        assert!(ExecutionProfile::V1_0.supports(&CX));
+        assert!(!ExecutionProfile::V1_0.supports(&PALR));
+        assert!(!ExecutionProfile::V1_0.supports(&MODR2));
+        assert!(ExecutionProfile::V1_1.supports(&SNG));
+        assert!(!ExecutionProfile::V1_1.supports(&NEGR1));
+        assert!(ExecutionProfile::V1_3.supports(&NOTI));
+    }
+
+    #[test]
+    fn display_version() {
+        assert_eq!(ExecutionProfile::V0_7.to_string(), "0.7");
+        assert_eq!(ExecutionProfile::V1_3.to_string(), "1.3");
+    }
+}
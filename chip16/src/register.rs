@@ -14,11 +14,13 @@ impl RegisterFile {
         self.0 = [0; ADDRESSABLE_REGISTERS];
     }
 
+    // Registers are addressed by a single nibble, so only the lower four bits of the index are used.
+
     pub fn get<I: Into<usize>>(&self, index: I) -> &Register {
-        self.0.get(index.into()).unwrap()
+        &self.0[index.into() % ADDRESSABLE_REGISTERS]
     }
 
     pub fn get_mut<I: Into<usize>>(&mut self, index: I) -> &mut Register {
-        self.0.get_mut(index.into()).unwrap()
+        &mut self.0[index.into() % ADDRESSABLE_REGISTERS]
     }
 }
@@ -1,18 +1,52 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use crc::{Hasher32, crc32};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc::{crc32, Hasher32};
 use failure::Error;
-use std::io::Read;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
 
 const CRC32_POLYNOMIAL: u32 = 0x04C11DB7;
 
+// The crc crate works on the reversed (LSB first) form of the polynomial.
+fn checksum(content: &[u8]) -> u32 {
+    let mut digest = crc32::Digest::new(CRC32_POLYNOMIAL.reverse_bits());
+    digest.write(content);
+    digest.sum32()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RomLoadOptions {
+    // When false, a bad checksum does not stop the rom from loading. Use
+    // Rom::has_valid_checksum to report it instead.
+    pub strict: bool,
+}
+
+impl Default for RomLoadOptions {
+    fn default() -> RomLoadOptions {
+        RomLoadOptions::strict()
+    }
+}
+
+impl RomLoadOptions {
+    pub fn strict() -> RomLoadOptions {
+        RomLoadOptions { strict: true }
+    }
+
+    pub fn lenient() -> RomLoadOptions {
+        RomLoadOptions { strict: false }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum RomFormat {
     Raw,
     Chip16,
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
-pub struct Version(u8, u8);
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Version(pub u8, pub u8);
 
 impl From<u8> for Version {
     fn from(byte: u8) -> Version {
@@ -22,17 +56,52 @@ impl From<u8> for Version {
     }
 }
 
+impl From<Version> for u8 {
+    fn from(version: Version) -> u8 {
+        (version.0 << 4) | (version.1 & 0x0F)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.0, self.1)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Version, Error> {
+        let mut parts = s.splitn(2, '.');
+        let major = parts.next().unwrap_or("").parse::<u8>()?;
+        let minor = parts.next().unwrap_or("0").parse::<u8>()?;
+        ensure!(
+            major <= 0xF && minor <= 0xF,
+            "version {} does not fit in a single byte",
+            s
+        );
+        Ok(Version(major, minor))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Rom {
     pub format: RomFormat,
     pub version: Option<Version>,
     pub size: u32,
     pub start_address: u16,
+    // The checksum stored in the header, raw roms do not have one.
+    pub checksum: Option<u32>,
+    pub computed_checksum: u32,
     pub content: Vec<u8>,
 }
 
 impl Rom {
-    pub fn new<R: Read>(mut reader: R) -> Result<Rom, Error> {
+    pub fn new<R: Read>(reader: R) -> Result<Rom, Error> {
+        Rom::with_options(reader, RomLoadOptions::default())
+    }
+
+    pub fn with_options<R: Read>(mut reader: R, options: RomLoadOptions) -> Result<Rom, Error> {
         let mut header = [0; 16];
         let bytes_read = reader.read(&mut header)?;
         ensure!(bytes_read >= 4, "the rom must be at least 4 bytes");
@@ -41,7 +110,7 @@ impl Rom {
         match signature {
             b"CH16" => {
                 ensure!(bytes_read == 16, "the header is not 16 bytes");
-                Rom::decode_chip16(metadata, reader)
+                Rom::decode_chip16(metadata, reader, options)
             }
             _ => {
                 let content = header[..bytes_read].chain(reader);
@@ -50,19 +119,31 @@ impl Rom {
         }
     }
 
-    fn decode_raw<R: Read>(mut reader: R) -> Result<Rom, Error> {
-        let mut content = Vec::new();
-        reader.read_to_end(&mut content)?;
-        Ok(Rom {
+    // A raw rom holding the content, such as the output of the assembler. Set the format,
+    // version and start address to write it as a chip16 rom instead.
+    pub fn from_content(content: Vec<u8>) -> Rom {
+        Rom {
             format: RomFormat::Raw,
             version: None,
             size: content.len() as u32,
             start_address: 0,
+            checksum: None,
+            computed_checksum: checksum(&content),
             content,
-        })
+        }
+    }
+
+    fn decode_raw<R: Read>(mut reader: R) -> Result<Rom, Error> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        Ok(Rom::from_content(content))
     }
 
-    fn decode_chip16<R: Read>(mut metadata: &[u8], mut reader: R) -> Result<Rom, Error> {
+    fn decode_chip16<R: Read>(
+        mut metadata: &[u8],
+        reader: R,
+        options: RomLoadOptions,
+    ) -> Result<Rom, Error> {
         let reserved = metadata.read_u8()?;
         ensure!(reserved == 0, "reserved is non-zero");
 
@@ -77,7 +158,7 @@ impl Rom {
             "start address is larger than size"
         );
 
-        let checksum = metadata.read_u32::<LittleEndian>()?;
+        let stored_checksum = metadata.read_u32::<LittleEndian>()?;
 
         let mut content = Vec::new();
         reader.take(size as u64).read_to_end(&mut content)?;
@@ -86,24 +167,76 @@ impl Rom {
             "the length of content is smaller than size"
         );
 
-        // NOTE: Until the crc crate gets updated we cannot compute the checksum.
-        // let mut digest = crc32::Digest::new(CRC32_POLYNOMIAL);
-        // digest.write(&contents[..]);
-        // ensure!(digest.sum32() == checksum, "the checksum is invalid");
+        let computed_checksum = checksum(&content);
+        ensure!(
+            !options.strict || computed_checksum == stored_checksum,
+            "the checksum is invalid, expected {:08x} but computed {:08x}",
+            stored_checksum,
+            computed_checksum
+        );
 
         Ok(Rom {
             format: RomFormat::Chip16,
             version,
             size,
             start_address,
+            checksum: Some(stored_checksum),
+            computed_checksum,
             content,
         })
     }
+
+    // Raw roms are written as they are. Chip16 roms are written with a header, where the size
+    // and checksum are always computed from the content.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        if self.format == RomFormat::Chip16 {
+            let version = match self.version {
+                Some(version) => version,
+                None => bail!("a chip16 rom must have a version"),
+            };
+            ensure!(
+                self.content.len() >= 4,
+                "the rom content must be at least 4 bytes"
+            );
+            ensure!(
+                (self.start_address as usize) < self.content.len(),
+                "start address is larger than size"
+            );
+
+            writer.write_all(b"CH16")?;
+            writer.write_u8(0)?;
+            writer.write_u8(version.into())?;
+            writer.write_u32::<LittleEndian>(self.content.len() as u32)?;
+            writer.write_u16::<LittleEndian>(self.start_address)?;
+            writer.write_u32::<LittleEndian>(checksum(&self.content))?;
+        }
+
+        writer.write_all(&self.content)?;
+        Ok(())
+    }
+
+    // Writes the rom to a file. The rom is checked before the file is created, so a rom that
+    // cannot be written leaves an existing file as it was.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut data = Vec::new();
+        self.write(&mut data)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn has_valid_checksum(&self) -> bool {
+        self.checksum
+            .is_none_or(|checksum| checksum == self.computed_checksum)
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_slicing)]
 mod tests {
     use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::process;
 
     macro_rules! test_assert_rom {
         ($func:ident, $reader:expr, $rom:expr) => {
@@ -116,6 +249,17 @@ mod tests {
         };
     }
 
+    macro_rules! test_assert_lenient_rom {
+        ($func:ident, $reader:expr, $rom:expr) => {
+            #[test]
+            fn $func() {
+                let rom = Rom::with_options($reader, RomLoadOptions::lenient()).unwrap();
+                println!("{:?}", rom);
+                assert!(rom == $rom);
+            }
+        };
+    }
+
     macro_rules! test_assert_error {
         ($func:ident, $data:expr) => {
             #[test]
@@ -126,10 +270,10 @@ mod tests {
         };
     }
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_ONE_INSTRUCTION: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x04, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
+        0x00, 0x00, 0x00, 0x00, 0xCD, 0xFB, 0x3C, 0xB6,
 
         0x01, 0x02, 0x03, 0x04,
     ];
@@ -142,6 +286,8 @@ mod tests {
             version: None,
             size: 4,
             start_address: 0,
+            checksum: None,
+            computed_checksum: 0xB63CFBCD,
             content: ROM_ONE_INSTRUCTION[16..(16 + 4)].to_vec(),
         }
     );
@@ -154,11 +300,13 @@ mod tests {
             version: Some(Version(1, 2)),
             size: 4,
             start_address: 0,
+            checksum: Some(0xB63CFBCD),
+            computed_checksum: 0xB63CFBCD,
             content: ROM_ONE_INSTRUCTION[16..(16 + 4)].to_vec(),
         }
     );
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_MAZE: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x11, 0xD8, 0x00,
         0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -200,6 +348,8 @@ mod tests {
             version: None,
             size: 216,
             start_address: 0,
+            checksum: None,
+            computed_checksum: 0xC51A03A7,
             content: ROM_MAZE[16..(16 + 216)].to_vec(),
         }
     );
@@ -212,11 +362,145 @@ mod tests {
             version: Some(Version(1, 1)),
             size: 216,
             start_address: 0,
+            checksum: Some(0xC51A03A7),
+            computed_checksum: 0xC51A03A7,
             content: ROM_MAZE[16..(16 + 216)].to_vec(),
         }
     );
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
+    const ROM_BAD_CHECKSUM: &[u8] = &[
+        0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x04, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xCD, 0xFB, 0x3C, 0xB6,
+
+        0x01, 0x02, 0x03, 0x05,
+    ];
+
+    test_assert_error!(chip16_bad_checksum, &ROM_BAD_CHECKSUM[..]);
+
+    test_assert_lenient_rom!(
+        chip16_bad_checksum_lenient,
+        &ROM_BAD_CHECKSUM[..],
+        Rom {
+            format: RomFormat::Chip16,
+            version: Some(Version(1, 2)),
+            size: 4,
+            start_address: 0,
+            checksum: Some(0xB63CFBCD),
+            computed_checksum: 0xC13BCB5B,
+            content: ROM_BAD_CHECKSUM[16..(16 + 4)].to_vec(),
+        }
+    );
+
+    #[test]
+    fn valid_checksum() {
+        let rom = Rom::new(&ROM_MAZE[..]).unwrap();
+        assert!(rom.has_valid_checksum());
+
+        let rom = Rom::new(&ROM_MAZE[16..]).unwrap();
+        assert!(rom.has_valid_checksum());
+
+        let rom = Rom::with_options(&ROM_BAD_CHECKSUM[..], RomLoadOptions::lenient()).unwrap();
+        assert!(!rom.has_valid_checksum());
+    }
+
+    #[test]
+    fn sample_roms_have_valid_checksums() {
+        let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms");
+        for entry in fs::read_dir(roms).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "c16") {
+                let file = File::open(&path).unwrap();
+                assert!(Rom::new(file).is_ok(), "{} is corrupt", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn write_chip16() {
+        let rom = Rom::new(&ROM_MAZE[..]).unwrap();
+        let mut data = Vec::new();
+        rom.write(&mut data).unwrap();
+        assert_eq!(&data[..], &ROM_MAZE[..]);
+    }
+
+    #[test]
+    fn write_raw() {
+        let rom = Rom::new(&ROM_MAZE[16..]).unwrap();
+        let mut data = Vec::new();
+        rom.write(&mut data).unwrap();
+        assert_eq!(&data[..], &ROM_MAZE[16..]);
+    }
+
+    #[test]
+    fn write_from_content() {
+        let mut rom = Rom::from_content(ROM_MAZE[16..].to_vec());
+        assert_eq!(rom, Rom::new(&ROM_MAZE[16..]).unwrap());
+
+        rom.format = RomFormat::Chip16;
+        rom.version = Some(Version(1, 1));
+        let mut data = Vec::new();
+        rom.write(&mut data).unwrap();
+        assert_eq!(&data[..], &ROM_MAZE[..]);
+    }
+
+    #[test]
+    fn write_fixes_checksum() {
+        let mut rom = Rom::with_options(&ROM_BAD_CHECKSUM[..], RomLoadOptions::lenient()).unwrap();
+        rom.version = Some(Version(1, 3));
+        let mut data = Vec::new();
+        rom.write(&mut data).unwrap();
+
+        let rom = Rom::new(&data[..]).unwrap();
+        assert_eq!(rom.version, Some(Version(1, 3)));
+        assert_eq!(rom.checksum, Some(0xC13BCB5B));
+    }
+
+    #[test]
+    fn convert_raw_to_chip16() {
+        let mut rom = Rom::new(&ROM_ONE_INSTRUCTION[16..]).unwrap();
+        rom.format = RomFormat::Chip16;
+        rom.version = Some(Version(1, 2));
+        let mut data = Vec::new();
+        rom.write(&mut data).unwrap();
+        assert_eq!(&data[..], &ROM_ONE_INSTRUCTION[..]);
+
+        rom.version = None;
+        assert!(rom.write(&mut Vec::new()).is_err());
+
+        rom.version = Some(Version(1, 2));
+        rom.start_address = 4;
+        assert!(rom.write(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn save_only_valid_roms() {
+        let path = env::temp_dir().join(format!("chip16-save-{}.c16", process::id()));
+        fs::write(&path, b"original").unwrap();
+
+        let mut rom = Rom::new(&ROM_ONE_INSTRUCTION[..]).unwrap();
+        rom.start_address = 0xFFFF;
+        assert!(rom.save(&path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+
+        rom.start_address = 0;
+        rom.save(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), ROM_ONE_INSTRUCTION);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!("1.1".parse::<Version>().unwrap(), Version(1, 1));
+        assert_eq!("0.7".parse::<Version>().unwrap(), Version(0, 7));
+        assert_eq!("1".parse::<Version>().unwrap(), Version(1, 0));
+        assert!("1.16".parse::<Version>().is_err());
+        assert!("one".parse::<Version>().is_err());
+        assert_eq!(Version(1, 3).to_string(), "1.3");
+        assert_eq!(u8::from(Version(1, 3)), 0x13);
+    }
+
+    #[rustfmt::skip]
     const ROM_EMPTY: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -225,7 +509,7 @@ mod tests {
     test_assert_error!(raw_empty, &ROM_EMPTY[16..]);
     test_assert_error!(chip16_empty, &ROM_EMPTY[..]);
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_ONE_BYTE: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x01, 0x00,
         0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -236,14 +520,14 @@ mod tests {
     test_assert_error!(raw_one_byte, &ROM_ONE_BYTE[16..]);
     test_assert_error!(chip16_one_byte, &ROM_ONE_BYTE[..]);
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_INCOMPLETE_HEADER: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x00, 0x00,
     ];
 
     test_assert_error!(chip16_incomplete_header, &ROM_INCOMPLETE_HEADER[..]);
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_NON_ZERO_RESERVED_BYTE: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x01, 0x12, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -254,7 +538,7 @@ mod tests {
         &ROM_NON_ZERO_RESERVED_BYTE[..]
     );
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_START_ADDRESS_LARGER_THAN_SIZE: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x10, 0x00,
         0x00, 0x00, 0x10, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -268,7 +552,7 @@ mod tests {
         &ROM_START_ADDRESS_LARGER_THAN_SIZE[..]
     );
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_SIZE_LARGER_THAN_DATA: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x20, 0x00,
         0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -279,7 +563,7 @@ mod tests {
 
     test_assert_error!(chip16_size_larger_than_data, &ROM_SIZE_LARGER_THAN_DATA[..]);
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const ROM_SIZE_IS_ZERO: &[u8] = &[
         0x43, 0x48, 0x31, 0x36, 0x00, 0x12, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0xA7, 0x03, 0x1A, 0xC5,
@@ -0,0 +1,232 @@
+use failure::Error;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// The line of source that an address was assembled from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub path: String,
+    pub line: usize,
+}
+
+// Debug symbols for a rom, which name addresses and map them back to their source. The assembler
+// writes them next to the rom, in a text file with one symbol on each line:
+//
+//     label 01a4 draw_paddle
+//     line 01a4 pong.s:42
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Symbols {
+    // Several labels can name the same address, the first one defined is shown.
+    labels: BTreeMap<u16, Vec<String>>,
+    lines: BTreeMap<u16, SourceLocation>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    // The file that holds the symbols of a rom, such as pong.sym for pong.c16.
+    pub fn sidecar_path<P: AsRef<Path>>(rom: P) -> PathBuf {
+        rom.as_ref().with_extension("sym")
+    }
+
+    // Loads the symbols next to a rom. Returns None if there are none.
+    pub fn load_sidecar<P: AsRef<Path>>(rom: P) -> Result<Option<Symbols>, Error> {
+        let path = Symbols::sidecar_path(rom);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path)?;
+        Symbols::read(BufReader::new(file))
+            .map(Some)
+            .map_err(|error| format_err!("{}: {}", path.display(), error))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Symbols, Error> {
+        let mut symbols = Symbols::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            symbols
+                .read_symbol(line)
+                .map_err(|error| format_err!("line {}: {}", index + 1, error))?;
+        }
+        Ok(symbols)
+    }
+
+    fn read_symbol(&mut self, symbol: &str) -> Result<(), Error> {
+        let mut fields = symbol.splitn(3, ' ');
+        let (kind, address, value) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(kind), Some(address), Some(value)) => (kind, address, value),
+            _ => bail!("expected a kind, an address and a value"),
+        };
+        let address = u16::from_str_radix(address, 16)
+            .map_err(|_| format_err!("`{}` is not an address", address))?;
+
+        match kind {
+            "label" => self.add_label(address, value),
+            "line" => {
+                // The path can hold colons, but the line number cannot.
+                let (path, line) = match value.rfind(':') {
+                    Some(index) => (&value[..index], &value[index + 1..]),
+                    None => bail!("expected a path and a line number"),
+                };
+                let line = line
+                    .parse()
+                    .map_err(|_| format_err!("`{}` is not a line number", line))?;
+                self.add_line(address, path, line);
+            }
+            _ => bail!("unknown symbol kind `{}`", kind),
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for (&address, names) in &self.labels {
+            for name in names {
+                writeln!(writer, "label {:04x} {}", address, name)?;
+            }
+        }
+        for (&address, location) in &self.lines {
+            writeln!(
+                writer,
+                "line {:04x} {}:{}",
+                address, location.path, location.line
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn add_label(&mut self, address: u16, name: &str) {
+        self.labels
+            .entry(address)
+            .or_default()
+            .push(name.to_string());
+    }
+
+    // Only the first line assembled at an address is kept.
+    pub fn add_line(&mut self, address: u16, path: &str, line: usize) {
+        self.lines.entry(address).or_insert_with(|| SourceLocation {
+            path: path.to_string(),
+            line,
+        });
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels
+            .get(&address)
+            .and_then(|names| names.first())
+            .map(String::as_str)
+    }
+
+    // The closest label at or before an address, to name the addresses inside a routine.
+    pub fn label_before(&self, address: u16) -> Option<(u16, &str)> {
+        self.labels
+            .range(..=address)
+            .next_back()
+            .and_then(|(&label, names)| names.first().map(|name| (label, name.as_str())))
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.labels()
+            .find(|&(_, label)| label == name)
+            .map(|(address, _)| address)
+    }
+
+    // Every label, in address order.
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels
+            .iter()
+            .flat_map(|(&address, names)| names.iter().map(move |name| (address, name.as_str())))
+    }
+
+    pub fn source(&self, address: u16) -> Option<&SourceLocation> {
+        self.lines.get(&address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols() -> Symbols {
+        let mut symbols = Symbols::new();
+        symbols.add_label(0x0000, "start");
+        symbols.add_label(0x01A4, "draw_paddle");
+        symbols.add_label(0x0000, "reset");
+        symbols.add_line(0x0000, "pong.s", 3);
+        symbols.add_line(0x01A4, "C:\\games\\paddle.s", 12);
+        symbols.add_line(0x01A4, "pong.s", 40);
+        symbols
+    }
+
+    #[test]
+    fn lookup_symbols() {
+        let symbols = symbols();
+        assert_eq!(symbols.label(0x0000), Some("start"));
+        assert_eq!(symbols.label(0x0004), None);
+        assert_eq!(symbols.address_of("reset"), Some(0x0000));
+        assert_eq!(symbols.address_of("draw_paddle"), Some(0x01A4));
+        assert_eq!(symbols.address_of("missing"), None);
+        assert_eq!(symbols.label_before(0x0100), Some((0x0000, "start")));
+        assert_eq!(symbols.label_before(0x01A4), Some((0x01A4, "draw_paddle")));
+        assert_eq!(
+            symbols.source(0x01A4),
+            Some(&SourceLocation {
+                path: "C:\\games\\paddle.s".to_string(),
+                line: 12
+            })
+        );
+    }
+
+    #[test]
+    fn write_and_read() {
+        let mut data = Vec::new();
+        symbols().write(&mut data).unwrap();
+        assert_eq!(
+            String::from_utf8(data.clone()).unwrap(),
+            "label 0000 start\n\
+             label 0000 reset\n\
+             label 01a4 draw_paddle\n\
+             line 0000 pong.s:3\n\
+             line 01a4 C:\\games\\paddle.s:12\n"
+        );
+        assert_eq!(Symbols::read(&data[..]).unwrap(), symbols());
+    }
+
+    #[test]
+    fn read_errors() {
+        let error = |text: &str| Symbols::read(text.as_bytes()).unwrap_err().to_string();
+        assert_eq!(
+            error("; comment\n\nlabel 0000"),
+            "line 3: expected a kind, an address and a value"
+        );
+        assert_eq!(error("label xyz start"), "line 1: `xyz` is not an address");
+        assert_eq!(
+            error("line 0000 pong.s"),
+            "line 1: expected a path and a line number"
+        );
+        assert_eq!(
+            error("line 0000 pong.s:x"),
+            "line 1: `x` is not a line number"
+        );
+        assert_eq!(
+            error("name 0000 start"),
+            "line 1: unknown symbol kind `name`"
+        );
+    }
+
+    #[test]
+    fn sidecar_path() {
+        assert_eq!(
+            Symbols::sidecar_path("roms/pong.c16"),
+            PathBuf::from("roms/pong.sym")
+        );
+    }
+}
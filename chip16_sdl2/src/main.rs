@@ -1,21 +1,28 @@
 extern crate chip16;
 extern crate sdl2;
 
-use chip16::{Cpu, Rom};
+use chip16::{
+    Controller, Cpu, Port, ProfilePolicy, Rom, RomLoadOptions, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::keyboard::{KeyboardState, Keycode, Scancode};
+use sdl2::pixels::PixelFormatEnum;
 use std::env;
 use std::fs::File;
+use std::thread;
 use std::time::Duration;
 
 fn main() {
     let filename = env::args().nth(1).unwrap();
     let file = File::open(filename).unwrap();
-    let rom = Rom::new(file).unwrap();
+    let rom = Rom::with_options(file, RomLoadOptions::lenient()).unwrap();
+    if !rom.has_valid_checksum() {
+        eprintln!("warning: the rom checksum is invalid");
+    }
 
-    let cpu = Cpu::new();
-    // cpu.load(rom);
+    let mut cpu = Cpu::new();
+    cpu.load(&rom).unwrap();
+    cpu.profile_policy = ProfilePolicy::Warn;
 
     run(cpu);
 }
@@ -32,12 +39,16 @@ fn run(mut cpu: Cpu) {
 
     let mut canvas = window.into_canvas().build().unwrap();
 
+    // ABGR8888 stores each pixel as R, G, B, A bytes, which matches Graphics::render_rgba.
     let texture_creator = canvas.texture_creator();
-    // let texture = texture_creator.
-
-    canvas.set_draw_color(Color::RGB(255, 0, 0));
-    canvas.clear();
-    canvas.present();
+    let mut texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::ABGR8888,
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        )
+        .unwrap();
+    let mut pixels = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
 
     let mut event_pump = sdl_context.event_pump().unwrap();
     'running: loop {
@@ -51,5 +62,36 @@ fn run(mut cpu: Cpu) {
                 _ => {}
             }
         }
+
+        let controller = read_controller(&event_pump.keyboard_state());
+        cpu.set_controller(Port::One, controller);
+
+        if let Err(error) = cpu.run_frame() {
+            eprintln!("error: {}", error);
+            break 'running;
+        }
+        for warning in cpu.take_warnings() {
+            eprintln!("warning: {}", warning);
+        }
+
+        cpu.graphics.render_rgba(&mut pixels);
+        texture.update(None, &pixels, SCREEN_WIDTH * 4).unwrap();
+        canvas.copy(&texture, None, None).unwrap();
+        canvas.present();
+
+        thread::sleep(Duration::new(0, 1_000_000_000 / 60));
+    }
+}
+
+fn read_controller(keyboard: &KeyboardState) -> Controller {
+    Controller {
+        up: keyboard.is_scancode_pressed(Scancode::Up),
+        down: keyboard.is_scancode_pressed(Scancode::Down),
+        left: keyboard.is_scancode_pressed(Scancode::Left),
+        right: keyboard.is_scancode_pressed(Scancode::Right),
+        select: keyboard.is_scancode_pressed(Scancode::RShift),
+        start: keyboard.is_scancode_pressed(Scancode::Return),
+        a: keyboard.is_scancode_pressed(Scancode::Z),
+        b: keyboard.is_scancode_pressed(Scancode::X),
     }
 }